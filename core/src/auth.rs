@@ -0,0 +1,29 @@
+//! Proofs that a peer knows the room password, without sending it. Rooms
+//! without a password use an empty one.
+
+use hmac_sha256::HMAC;
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// Challenge sent to the other side, from bytes nobody can guess
+pub fn nonce(secret: [u8; 16]) -> String {
+    hex(&secret)
+}
+
+// Each side signs the other's nonce under its own role, so a proof asked of
+// one side can't be passed off as the other's
+fn sign(password: &str, role: &str, nonce: &str) -> String {
+    hex(&HMAC::mac(format!("{}:{}", role, nonce), password))
+}
+
+// What a guest answers the host's challenge with
+pub fn guest_proof(password: &str, nonce: &str) -> String {
+    sign(password, "guest", nonce)
+}
+
+// What the host answers the guest's challenge with, once it let them in
+pub fn host_proof(password: &str, nonce: &str) -> String {
+    sign(password, "host", nonce)
+}
@@ -1,11 +1,47 @@
-use crate::game::{
-    directions::{DirectionIterator, MovementIterator},
-    piece::{Piece, PieceKind},
+use std::fmt::Display;
+
+use crate::{
+    directions::{DirectionIterator, MovementIterator, Step},
+    notation::on_board,
+    piece::{Color, Piece, PieceKind},
 };
 
-use super::piece::Color;
+// Each side starts with these, besides the king and 9 pawns
+const STARTING_PIECES: [(PieceKind, usize); 4] = [
+    (PieceKind::Queen, 1),
+    (PieceKind::Rook, 2),
+    (PieceKind::Bishop, 3),
+    (PieceKind::Knight, 2),
+];
+const PAWNS: usize = 9;
+
+// Why a described board can't be played on. Pieces go by index.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BoardError {
+    UnknownKind(u8),
+    OffBoard(u8),
+    // Shares its hex with an earlier piece
+    Overlapping(u8),
+    MissingKing(Color),
+    DuplicateKing(Color),
+    // More pieces than pawns could have promoted into
+    TooManyPieces(Color),
+}
+
+impl Display for BoardError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownKind(idx) => write!(f, "piece {} is of an unknown kind", idx),
+            Self::OffBoard(idx) => write!(f, "piece {} is off the board", idx),
+            Self::Overlapping(idx) => write!(f, "piece {} is on top of another", idx),
+            Self::MissingKing(color) => write!(f, "{:?} has no king", color),
+            Self::DuplicateKing(color) => write!(f, "{:?} has more than one king", color),
+            Self::TooManyPieces(color) => write!(f, "{:?} has too many pieces", color),
+        }
+    }
+}
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Board {
     pub pieces: Vec<Piece>,
     pub passant: Option<(u8, u8, u8)>,
@@ -23,6 +59,12 @@ macro_rules! add_piece {
     };
 }
 
+impl Default for Board {
+    fn default() -> Self {
+        Board::new()
+    }
+}
+
 impl Board {
     pub fn new() -> Self {
         Board {
@@ -61,12 +103,47 @@ impl Board {
         }
     }
 
-    pub fn load_desc(&mut self, desc: Vec<u16>) {
-        self.pieces = desc
-            .iter()
-            .enumerate()
-            .map(|(idx, piece)| Piece::from_desc(idx as u8, *piece))
-            .collect();
+    // Leaves the board as it was if the description doesn't make sense
+    pub fn load_desc(&mut self, desc: &[u16]) -> Result<(), BoardError> {
+        let mut pieces: Vec<Piece> = vec![];
+        for (idx, piece) in desc.iter().enumerate() {
+            let idx = idx as u8;
+            let piece = Piece::from_desc(idx, *piece).ok_or(BoardError::UnknownKind(idx))?;
+            if !piece.is_captured() {
+                if !on_board(piece.q, piece.r) {
+                    return Err(BoardError::OffBoard(idx));
+                }
+                if pieces.iter().any(|p| p.q == piece.q && p.r == piece.r) {
+                    return Err(BoardError::Overlapping(idx));
+                }
+            }
+            pieces.push(piece);
+        }
+
+        for color in [Color::Light, Color::Dark] {
+            let count = |kind| {
+                pieces
+                    .iter()
+                    .filter(|p| p.color == color && p.kind == kind)
+                    .count()
+            };
+            match count(PieceKind::King) {
+                0 => return Err(BoardError::MissingKing(color)),
+                1 => {}
+                _ => return Err(BoardError::DuplicateKing(color)),
+            }
+
+            let promoted: usize = STARTING_PIECES
+                .iter()
+                .map(|&(kind, start)| count(kind).saturating_sub(start))
+                .sum();
+            if count(PieceKind::Pawn) + promoted > PAWNS {
+                return Err(BoardError::TooManyPieces(color));
+            }
+        }
+
+        self.pieces = pieces;
+        Ok(())
     }
 
     pub fn describe(&self) -> Vec<u16> {
@@ -89,10 +166,7 @@ impl Board {
             .find(|piece| piece.q == q && piece.r == r)
     }
 
-    fn avoid_checks<'a>(
-        &'a self,
-        piece: &'a Piece,
-    ) -> impl Fn(&(u8, u8, (i8, i8, u8))) -> bool + 'a {
+    fn avoid_checks<'a>(&'a self, piece: &'a Piece) -> impl Fn(&Step) -> bool + 'a {
         move |pos| {
             if piece.kind == PieceKind::King {
                 return !self.is_threatened(pos.0, pos.1, piece.color);
@@ -178,6 +252,19 @@ impl Board {
             .filter(|p| p.q != 0 || p.r != 0)
     }
 
+    // FNV-1a over the pieces, side to move and en passant target
+    pub fn checksum(&self, turn: Color) -> u32 {
+        let passant = self.passant.unwrap_or((0xff, 0xff, 0xff));
+        let mut bytes = vec![turn.is_light() as u8, passant.0, passant.1, passant.2];
+        for desc in self.describe() {
+            bytes.extend_from_slice(&desc.to_be_bytes());
+        }
+
+        bytes.iter().fold(0x811c9dc5, |hash, byte| {
+            (hash ^ u32::from(*byte)).wrapping_mul(0x01000193)
+        })
+    }
+
     pub fn get_piece(&self, idx: u8) -> Option<&Piece> {
         self.pieces.get(idx as usize)
     }
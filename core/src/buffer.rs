@@ -66,6 +66,17 @@ impl Buffer {
         self
     }
 
+    pub fn read_u32(&mut self) -> Option<u32> {
+        let high = self.read_u16()? as u32;
+        let low = self.read_u16()? as u32;
+        Some(high << 16 | low)
+    }
+
+    pub fn write_u32(&mut self, value: u32) -> &mut Self {
+        self.write_u16((value >> 16) as u16);
+        self.write_u16((value & 0xFFFF) as u16)
+    }
+
     pub fn read_js_string(&mut self) -> Option<String> {
         let slice = self.buf.get(self.ptr..)?;
         self.ptr = self.buf.len();
@@ -1,6 +1,7 @@
-use crate::game::piece::{Piece, PieceKind};
+use crate::piece::{Color, Piece, PieceKind};
 
-use super::piece::Color;
+// A reachable hex, along with the direction (and distance) it was reached from
+pub type Step = (u8, u8, (i8, i8, u8));
 
 pub const KING: &[(i8, i8, u8)] = &[
     (-1, -1, 1),
@@ -181,7 +182,7 @@ impl DirectionIterator {
 }
 
 impl Iterator for DirectionIterator {
-    type Item = (u8, u8, (i8, i8, u8));
+    type Item = Step;
 
     fn next(&mut self) -> Option<Self::Item> {
         loop {
@@ -254,7 +255,7 @@ impl<'a> MovementIterator<'a> {
 }
 
 impl<'a> Iterator for MovementIterator<'a> {
-    type Item = (u8, u8, (i8, i8, u8));
+    type Item = Step;
 
     fn next(&mut self) -> Option<Self::Item> {
         while let Some(pos) = self.extra.next() {
@@ -0,0 +1,13 @@
+//! Board, rules and wire protocol of the game, with no browser dependencies.
+
+pub mod auth;
+pub mod board;
+pub mod buffer;
+pub mod directions;
+pub mod notation;
+pub mod packet;
+pub mod piece;
+pub mod position;
+mod rules;
+#[cfg(feature = "serde")]
+pub mod signalling;
@@ -0,0 +1,65 @@
+use crate::piece::{Piece, PieceKind};
+
+// Glinski notation: files a to l (there's no j), ranks counted from light's side
+const FILES: &[u8] = b"abcdefghikl";
+
+pub fn on_board(q: u8, r: u8) -> bool {
+    q <= 10 && r <= 10 && (5..=15).contains(&(q + r))
+}
+
+// Hex of a file that's closest to light
+fn first_rank(q: u8) -> u8 {
+    10.min(15 - q)
+}
+
+pub fn hex_name(q: u8, r: u8) -> Option<String> {
+    if !on_board(q, r) {
+        return None;
+    }
+
+    let file = FILES[q as usize] as char;
+    Some(format!("{}{}", file, first_rank(q) - r + 1))
+}
+
+pub fn parse_hex(name: &str) -> Option<(u8, u8)> {
+    let mut chars = name.chars();
+    let file = chars.next()?;
+    let q = FILES.iter().position(|f| *f as char == file)? as u8;
+    let rank: u8 = chars.as_str().parse().ok()?;
+    if rank == 0 {
+        return None;
+    }
+
+    let r = (first_rank(q) + 1).checked_sub(rank)?;
+    if !on_board(q, r) {
+        return None;
+    }
+    Some((q, r))
+}
+
+// Light's letter for the kind; dark's is the lowercase one
+pub fn letter(kind: PieceKind) -> char {
+    match kind {
+        PieceKind::King => 'K',
+        PieceKind::Queen => 'Q',
+        PieceKind::Rook => 'R',
+        PieceKind::Bishop => 'B',
+        PieceKind::Knight => 'N',
+        PieceKind::Pawn => 'P',
+    }
+}
+
+// Long notation, like Nc1-d3 or Qe1xe5. Pawn moves have no letter.
+pub fn move_name(piece: &Piece, to: (u8, u8), capture: bool) -> Option<String> {
+    let letter = match piece.kind {
+        PieceKind::Pawn => String::new(),
+        kind => letter(kind).to_string(),
+    };
+    Some(format!(
+        "{}{}{}{}",
+        letter,
+        hex_name(piece.q, piece.r)?,
+        if capture { 'x' } else { '-' },
+        hex_name(to.0, to.1)?
+    ))
+}
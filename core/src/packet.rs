@@ -0,0 +1,644 @@
+use crate::buffer::Buffer;
+use std::fmt::Display;
+
+// Range of protocol versions this build can speak
+pub const NET_VERSION: u8 = 4;
+pub const MIN_NET_VERSION: u8 = 4;
+
+// Optional features, advertised during the handshake. Variants and draw
+// offers are reserved bits for peers that implement them.
+pub const FEATURE_VARIANTS: u8 = 1 << 0;
+pub const FEATURE_DRAW_OFFERS: u8 = 1 << 1;
+pub const FEATURE_CLOCKS: u8 = 1 << 2;
+pub const FEATURES: u8 = FEATURE_VARIANTS | FEATURE_DRAW_OFFERS | FEATURE_CLOCKS;
+
+#[derive(Debug, Clone)]
+pub enum ParseError {
+    MissingFields(String),
+    UnknownPacket(u8),
+    VersionMismatch(u8, u8),
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingFields(k) => write!(f, "missing a field of kind {}", k),
+            Self::UnknownPacket(c) => write!(f, "unknown packet with code {}", c),
+            Self::VersionMismatch(got, exp) => {
+                write!(f, "net version mismatch: got {}, expected {}", got, exp)
+            }
+        }
+    }
+}
+
+macro_rules! read {
+    ($data: expr, $method: ident) => {
+        $data
+            .$method()
+            .ok_or(ParseError::MissingFields(stringify!($method).to_owned()))?
+    };
+}
+
+trait Packet: Sized {
+    const CODE: u8;
+
+    fn read(buf: Buffer) -> Result<Self, ParseError>;
+    fn write(&self, buf: &mut Buffer);
+}
+
+// The handshake layout must never change: it is read before versions are agreed on.
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Handshake {
+    pub name: String,
+    pub min_version: u8,
+    pub max_version: u8,
+    pub features: u8,
+}
+impl Handshake {
+    pub fn new(name: String) -> Self {
+        Handshake {
+            name,
+            min_version: MIN_NET_VERSION,
+            max_version: NET_VERSION,
+            features: FEATURES,
+        }
+    }
+
+    // Highest version both ends speak and the features both have, if any
+    // version is common
+    pub fn negotiate_with(&self, peer: &Handshake) -> Option<(u8, u8)> {
+        let min = self.min_version.max(peer.min_version);
+        let max = self.max_version.min(peer.max_version);
+        if min > max {
+            return None;
+        }
+
+        Some((max, self.features & peer.features))
+    }
+
+    // Same, against this build
+    pub fn negotiate(&self) -> Option<(u8, u8)> {
+        Handshake::new(String::new()).negotiate_with(self)
+    }
+}
+impl Packet for Handshake {
+    const CODE: u8 = 0;
+
+    fn read(mut data: Buffer) -> Result<Self, ParseError> {
+        let name = read!(data, read_string);
+        // Version 0 peers only send their name
+        Ok(Handshake {
+            name,
+            min_version: data.read_u8().unwrap_or(0),
+            max_version: data.read_u8().unwrap_or(0),
+            features: data.read_u8().unwrap_or(0),
+        })
+    }
+    fn write(&self, data: &mut Buffer) {
+        data.write_string(&self.name)
+            .write_u8(self.min_version)
+            .write_u8(self.max_version)
+            .write_u8(self.features);
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Start {}
+impl Packet for Start {
+    const CODE: u8 = 1;
+
+    fn read(_: Buffer) -> Result<Self, ParseError> {
+        Ok(Start {})
+    }
+    fn write(&self, _: &mut Buffer) {}
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChatMessage {
+    pub content: String,
+}
+impl Packet for ChatMessage {
+    const CODE: u8 = 2;
+
+    fn read(mut data: Buffer) -> Result<Self, ParseError> {
+        Ok(ChatMessage {
+            content: read!(data, read_string),
+        })
+    }
+    fn write(&self, data: &mut Buffer) {
+        data.write_string(&self.content);
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Movement {
+    pub idx: u8,
+    pub q: u8,
+    pub r: u8,
+    pub time_left: Option<u16>,
+    // Board checksum before the move
+    pub checksum: u32,
+}
+impl Packet for Movement {
+    const CODE: u8 = 3;
+
+    fn read(mut data: Buffer) -> Result<Self, ParseError> {
+        Ok(Movement {
+            idx: read!(data, read_u8),
+            q: read!(data, read_u8),
+            r: read!(data, read_u8),
+            time_left: match read!(data, read_u16) {
+                0 => None,
+                t => Some(t),
+            },
+            checksum: read!(data, read_u32),
+        })
+    }
+    fn write(&self, data: &mut Buffer) {
+        data.write_u8(self.idx)
+            .write_u8(self.q)
+            .write_u8(self.r)
+            .write_u16(self.time_left.unwrap_or(0))
+            .write_u32(self.checksum);
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Resign {}
+impl Packet for Resign {
+    const CODE: u8 = 4;
+
+    fn read(_: Buffer) -> Result<Self, ParseError> {
+        Ok(Resign {})
+    }
+    fn write(&self, _: &mut Buffer) {}
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Ping {
+    pub request: Option<u16>,
+    pub reply_to: Option<u16>,
+}
+impl Packet for Ping {
+    const CODE: u8 = 5;
+
+    fn read(mut data: Buffer) -> Result<Self, ParseError> {
+        let request = if read!(data, read_bool) {
+            Some(read!(data, read_u16))
+        } else {
+            None
+        };
+        let reply_to = if read!(data, read_bool) {
+            Some(read!(data, read_u16))
+        } else {
+            None
+        };
+
+        Ok(Ping { request, reply_to })
+    }
+    fn write(&self, data: &mut Buffer) {
+        match self.request {
+            Some(id) => data.write_bool(true).write_u16(id),
+            None => data.write_bool(false),
+        };
+        match self.reply_to {
+            Some(id) => data.write_bool(true).write_u16(id),
+            None => data.write_bool(false),
+        };
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SetBoard {
+    pub board: Vec<u16>,
+}
+impl Packet for SetBoard {
+    const CODE: u8 = 6;
+
+    fn read(mut data: Buffer) -> Result<Self, ParseError> {
+        let mut board = Vec::new();
+        for _ in 0..read!(data, read_u8) {
+            board.push(read!(data, read_u16));
+        }
+
+        Ok(SetBoard { board })
+    }
+    fn write(&self, data: &mut Buffer) {
+        data.write_u8(
+            self.board
+                .len()
+                .try_into()
+                .expect("too many pieces in board"),
+        );
+
+        for piece in self.board.iter() {
+            data.write_u16(*piece);
+        }
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SetSettings {
+    pub timer: u16,
+    pub host_as_light: bool,
+    pub reconnect_window: u16,
+    pub pause_on_disconnect: bool,
+}
+impl Packet for SetSettings {
+    const CODE: u8 = 7;
+
+    fn read(mut data: Buffer) -> Result<Self, ParseError> {
+        Ok(SetSettings {
+            timer: read!(data, read_u16),
+            host_as_light: read!(data, read_bool),
+            reconnect_window: read!(data, read_u16),
+            pause_on_disconnect: read!(data, read_bool),
+        })
+    }
+    fn write(&self, data: &mut Buffer) {
+        data.write_u16(self.timer)
+            .write_bool(self.host_as_light)
+            .write_u16(self.reconnect_window)
+            .write_bool(self.pause_on_disconnect);
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Promote {
+    pub idx: u8,
+    pub kind: u8,
+}
+impl Packet for Promote {
+    const CODE: u8 = 8;
+
+    fn read(mut data: Buffer) -> Result<Self, ParseError> {
+        Ok(Promote {
+            idx: read!(data, read_u8),
+            kind: read!(data, read_u8),
+        })
+    }
+    fn write(&self, data: &mut Buffer) {
+        data.write_u8(self.idx).write_u8(self.kind);
+    }
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SyncState {
+    pub board: Vec<u16>,
+    pub passant: Option<(u8, u8, u8)>,
+    pub running: bool,
+    pub light_turn: bool,
+    pub light_ms: u32,
+    pub dark_ms: u32,
+    pub clock_active: bool,
+    pub history: Vec<u16>,
+}
+impl Packet for SyncState {
+    const CODE: u8 = 9;
+
+    fn read(mut data: Buffer) -> Result<Self, ParseError> {
+        let mut board = Vec::new();
+        for _ in 0..read!(data, read_u8) {
+            board.push(read!(data, read_u16));
+        }
+        let passant = if read!(data, read_bool) {
+            Some((
+                read!(data, read_u8),
+                read!(data, read_u8),
+                read!(data, read_u8),
+            ))
+        } else {
+            None
+        };
+        let running = read!(data, read_bool);
+        let light_turn = read!(data, read_bool);
+        let light_ms = read!(data, read_u32);
+        let dark_ms = read!(data, read_u32);
+        let clock_active = read!(data, read_bool);
+        let mut history = Vec::new();
+        for _ in 0..read!(data, read_u16) {
+            history.push(read!(data, read_u16));
+        }
+
+        Ok(SyncState {
+            board,
+            passant,
+            running,
+            light_turn,
+            light_ms,
+            dark_ms,
+            clock_active,
+            history,
+        })
+    }
+    fn write(&self, data: &mut Buffer) {
+        data.write_u8(
+            self.board
+                .len()
+                .try_into()
+                .expect("too many pieces in board"),
+        );
+        for piece in self.board.iter() {
+            data.write_u16(*piece);
+        }
+
+        match self.passant {
+            Some((idx, q, r)) => data.write_bool(true).write_u8(idx).write_u8(q).write_u8(r),
+            None => data.write_bool(false),
+        };
+        data.write_bool(self.running)
+            .write_bool(self.light_turn)
+            .write_u32(self.light_ms)
+            .write_u32(self.dark_ms)
+            .write_bool(self.clock_active);
+
+        data.write_u16(
+            self.history
+                .len()
+                .try_into()
+                .expect("too many moves in history"),
+        );
+        for movement in self.history.iter() {
+            data.write_u16(*movement);
+        }
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Spectate {
+    pub light_name: String,
+    pub dark_name: String,
+    pub timer: u16,
+}
+impl Packet for Spectate {
+    const CODE: u8 = 10;
+
+    fn read(mut data: Buffer) -> Result<Self, ParseError> {
+        Ok(Spectate {
+            light_name: read!(data, read_string),
+            dark_name: read!(data, read_string),
+            timer: read!(data, read_u16),
+        })
+    }
+    fn write(&self, data: &mut Buffer) {
+        data.write_string(&self.light_name)
+            .write_string(&self.dark_name)
+            .write_u16(self.timer);
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RelayChat {
+    pub is_light: bool,
+    pub content: String,
+}
+impl Packet for RelayChat {
+    const CODE: u8 = 11;
+
+    fn read(mut data: Buffer) -> Result<Self, ParseError> {
+        Ok(RelayChat {
+            is_light: read!(data, read_bool),
+            content: read!(data, read_string),
+        })
+    }
+    fn write(&self, data: &mut Buffer) {
+        data.write_bool(self.is_light).write_string(&self.content);
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Clocks {
+    pub light_ms: u32,
+    pub dark_ms: u32,
+    // Same as setTimers: 0 light, 1 dark, -1 none
+    pub active: i8,
+}
+impl Packet for Clocks {
+    const CODE: u8 = 12;
+
+    fn read(mut data: Buffer) -> Result<Self, ParseError> {
+        Ok(Clocks {
+            light_ms: read!(data, read_u32),
+            dark_ms: read!(data, read_u32),
+            active: read!(data, read_u8) as i8,
+        })
+    }
+    fn write(&self, data: &mut Buffer) {
+        data.write_u32(self.light_ms)
+            .write_u32(self.dark_ms)
+            .write_u8(self.active as u8);
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GameOver {
+    pub won_light: Option<bool>,
+}
+impl Packet for GameOver {
+    const CODE: u8 = 13;
+
+    fn read(mut data: Buffer) -> Result<Self, ParseError> {
+        Ok(GameOver {
+            won_light: match read!(data, read_u8) {
+                0 => Some(true),
+                1 => Some(false),
+                _ => None,
+            },
+        })
+    }
+    fn write(&self, data: &mut Buffer) {
+        data.write_u8(match self.won_light {
+            Some(true) => 0,
+            Some(false) => 1,
+            None => 2,
+        });
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RequestSync {}
+impl Packet for RequestSync {
+    const CODE: u8 = 14;
+
+    fn read(_: Buffer) -> Result<Self, ParseError> {
+        Ok(RequestSync {})
+    }
+    fn write(&self, _: &mut Buffer) {}
+}
+
+// Host asks whoever connected to prove they know the room password
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AuthChallenge {
+    pub nonce: String,
+}
+impl Packet for AuthChallenge {
+    const CODE: u8 = 15;
+
+    fn read(mut data: Buffer) -> Result<Self, ParseError> {
+        Ok(AuthChallenge {
+            nonce: read!(data, read_string),
+        })
+    }
+    fn write(&self, data: &mut Buffer) {
+        data.write_string(&self.nonce);
+    }
+}
+
+// Guest's answer, and its own challenge for the host
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AuthProof {
+    pub proof: String,
+    pub nonce: String,
+}
+impl Packet for AuthProof {
+    const CODE: u8 = 16;
+
+    fn read(mut data: Buffer) -> Result<Self, ParseError> {
+        Ok(AuthProof {
+            proof: read!(data, read_string),
+            nonce: read!(data, read_string),
+        })
+    }
+    fn write(&self, data: &mut Buffer) {
+        data.write_string(&self.proof);
+        data.write_string(&self.nonce);
+    }
+}
+
+// The host's proof is empty when it turns the guest down
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AuthResult {
+    pub accepted: bool,
+    pub proof: String,
+}
+impl Packet for AuthResult {
+    const CODE: u8 = 17;
+
+    fn read(mut data: Buffer) -> Result<Self, ParseError> {
+        Ok(AuthResult {
+            accepted: read!(data, read_bool),
+            proof: read!(data, read_string),
+        })
+    }
+    fn write(&self, data: &mut Buffer) {
+        data.write_bool(self.accepted);
+        data.write_string(&self.proof);
+    }
+}
+
+// Packets sent before versions are agreed on, or to peers that never agree on
+// one. Their layout must never change either.
+const VERSIONLESS: [u8; 5] = [
+    Handshake::CODE,
+    Ping::CODE,
+    AuthChallenge::CODE,
+    AuthProof::CODE,
+    AuthResult::CODE,
+];
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ChessPacket {
+    Handshake(Handshake),
+    Start(Start),
+    ChatMessage(ChatMessage),
+    Movement(Movement),
+    Resign(Resign),
+    Ping(Ping),
+    SetBoard(SetBoard),
+    SetSettings(SetSettings),
+    Promote(Promote),
+    SyncState(SyncState),
+    Spectate(Spectate),
+    RelayChat(RelayChat),
+    Clocks(Clocks),
+    GameOver(GameOver),
+    RequestSync(RequestSync),
+    AuthChallenge(AuthChallenge),
+    AuthProof(AuthProof),
+    AuthResult(AuthResult),
+}
+impl ChessPacket {
+    pub fn read(mut data: Buffer) -> Result<ChessPacket, ParseError> {
+        let version = read!(data, read_u8);
+        let code = read!(data, read_u8);
+        if !VERSIONLESS.contains(&code) && !(MIN_NET_VERSION..=NET_VERSION).contains(&version) {
+            return Err(ParseError::VersionMismatch(version, NET_VERSION));
+        }
+
+        let packet = match code {
+            Handshake::CODE => ChessPacket::Handshake(Handshake::read(data)?),
+            Start::CODE => ChessPacket::Start(Start::read(data)?),
+            ChatMessage::CODE => ChessPacket::ChatMessage(ChatMessage::read(data)?),
+            Movement::CODE => ChessPacket::Movement(Movement::read(data)?),
+            Resign::CODE => ChessPacket::Resign(Resign::read(data)?),
+            Ping::CODE => ChessPacket::Ping(Ping::read(data)?),
+            SetBoard::CODE => ChessPacket::SetBoard(SetBoard::read(data)?),
+            SetSettings::CODE => ChessPacket::SetSettings(SetSettings::read(data)?),
+            Promote::CODE => ChessPacket::Promote(Promote::read(data)?),
+            SyncState::CODE => ChessPacket::SyncState(SyncState::read(data)?),
+            Spectate::CODE => ChessPacket::Spectate(Spectate::read(data)?),
+            RelayChat::CODE => ChessPacket::RelayChat(RelayChat::read(data)?),
+            Clocks::CODE => ChessPacket::Clocks(Clocks::read(data)?),
+            GameOver::CODE => ChessPacket::GameOver(GameOver::read(data)?),
+            RequestSync::CODE => ChessPacket::RequestSync(RequestSync::read(data)?),
+            AuthChallenge::CODE => ChessPacket::AuthChallenge(AuthChallenge::read(data)?),
+            AuthProof::CODE => ChessPacket::AuthProof(AuthProof::read(data)?),
+            AuthResult::CODE => ChessPacket::AuthResult(AuthResult::read(data)?),
+            code => {
+                return Err(ParseError::UnknownPacket(code));
+            }
+        };
+        Ok(packet)
+    }
+    pub fn write(&self) -> Buffer {
+        self.write_as(NET_VERSION)
+    }
+
+    // Encodes it for a peer that agreed on `version`
+    pub fn write_as(&self, version: u8) -> Buffer {
+        let mut data = Buffer::new();
+        data.write_u8(version);
+
+        match self {
+            ChessPacket::Handshake(p) => p.write(data.write_u8(Handshake::CODE)),
+            ChessPacket::Start(p) => p.write(data.write_u8(Start::CODE)),
+            ChessPacket::ChatMessage(p) => p.write(data.write_u8(ChatMessage::CODE)),
+            ChessPacket::Movement(p) => p.write(data.write_u8(Movement::CODE)),
+            ChessPacket::Resign(p) => p.write(data.write_u8(Resign::CODE)),
+            ChessPacket::Ping(p) => p.write(data.write_u8(Ping::CODE)),
+            ChessPacket::SetBoard(p) => p.write(data.write_u8(SetBoard::CODE)),
+            ChessPacket::SetSettings(p) => p.write(data.write_u8(SetSettings::CODE)),
+            ChessPacket::Promote(p) => p.write(data.write_u8(Promote::CODE)),
+            ChessPacket::SyncState(p) => p.write(data.write_u8(SyncState::CODE)),
+            ChessPacket::Spectate(p) => p.write(data.write_u8(Spectate::CODE)),
+            ChessPacket::RelayChat(p) => p.write(data.write_u8(RelayChat::CODE)),
+            ChessPacket::Clocks(p) => p.write(data.write_u8(Clocks::CODE)),
+            ChessPacket::GameOver(p) => p.write(data.write_u8(GameOver::CODE)),
+            ChessPacket::RequestSync(p) => p.write(data.write_u8(RequestSync::CODE)),
+            ChessPacket::AuthChallenge(p) => p.write(data.write_u8(AuthChallenge::CODE)),
+            ChessPacket::AuthProof(p) => p.write(data.write_u8(AuthProof::CODE)),
+            ChessPacket::AuthResult(p) => p.write(data.write_u8(AuthResult::CODE)),
+        };
+
+        data
+    }
+}
@@ -1,9 +1,7 @@
-use crate::{
-    game::directions::{DirectionIterator, BISHOP, KING, KNIGHT, QUEEN, ROOK},
-    glue::promotePieces,
-};
+use crate::directions::{DirectionIterator, BISHOP, KING, KNIGHT, QUEEN, ROOK};
 
 #[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum PieceKind {
     King = 0,
@@ -15,6 +13,7 @@ pub enum PieceKind {
 }
 
 #[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum Color {
     Light = 0,
@@ -34,21 +33,24 @@ impl Color {
     }
 }
 
-impl From<u8> for PieceKind {
-    fn from(value: u8) -> Self {
+impl TryFrom<u8> for PieceKind {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, u8> {
         match value {
-            0 => Self::King,
-            1 => Self::Queen,
-            2 => Self::Rook,
-            3 => Self::Bishop,
-            4 => Self::Knight,
-            5 => Self::Pawn,
-            _ => panic!("invalid piece kind"),
+            0 => Ok(Self::King),
+            1 => Ok(Self::Queen),
+            2 => Ok(Self::Rook),
+            3 => Ok(Self::Bishop),
+            4 => Ok(Self::Knight),
+            5 => Ok(Self::Pawn),
+            _ => Err(value),
         }
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Piece {
     pub idx: u8,
     pub kind: PieceKind,
@@ -58,18 +60,19 @@ pub struct Piece {
 }
 
 impl Piece {
-    pub fn from_desc(idx: u8, desc: u16) -> Self {
+    // None if the kind is unknown
+    pub fn from_desc(idx: u8, desc: u16) -> Option<Self> {
         let q = (desc >> 4 & 0xf) as u8;
         let r = (desc & 0xf) as u8;
         let kind = (desc >> 8 & 0x7) as u8;
         let dark = (desc >> 11) > 0;
-        Piece {
+        Some(Piece {
             idx,
-            kind: kind.into(),
+            kind: kind.try_into().ok()?,
             color: if dark { Color::Dark } else { Color::Light },
             q,
             r,
-        }
+        })
     }
 
     pub fn describe(&self) -> u16 {
@@ -117,12 +120,13 @@ impl Piece {
         self.r == row || (self.q + self.r) == col
     }
 
-    pub fn promote(&mut self, kind: PieceKind) {
+    // Returns the promotion as the UI expects it
+    pub fn promote(&mut self, kind: PieceKind) -> u16 {
         self.kind = kind;
         let idx: u16 = self.idx.into();
         let kind: u16 = (kind as u8).into();
 
-        promotePieces(&[kind << 8 | idx]);
+        kind << 8 | idx
     }
 
     pub fn available(&self) -> DirectionIterator {
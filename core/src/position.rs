@@ -0,0 +1,285 @@
+use std::fmt::Display;
+
+use crate::{
+    board::Board,
+    notation::{hex_name, letter, move_name, parse_hex},
+    piece::{Color, Piece, PieceKind},
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PositionError {
+    InvalidNotation(String),
+    NoPiece(String),
+    NotYourTurn,
+    IllegalMove,
+    // Pawns reaching the last rank must say what they become
+    PromotionRequired,
+    InvalidPromotion,
+    GameOver,
+}
+
+impl Display for PositionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidNotation(why) => write!(f, "invalid position: {}", why),
+            Self::NoPiece(hex) => write!(f, "no piece on {}", hex),
+            Self::NotYourTurn => write!(f, "not that side's turn"),
+            Self::IllegalMove => write!(f, "illegal move"),
+            Self::PromotionRequired => write!(f, "pawn must be promoted"),
+            Self::InvalidPromotion => write!(f, "pawns promote to a queen, rook, bishop or knight"),
+            Self::GameOver => write!(f, "game is over"),
+        }
+    }
+}
+
+// Where the game stands, for the side to move
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Status {
+    pub turn: Color,
+    pub check: bool,
+    // In check, and nothing gets the king out of it
+    pub mate: bool,
+    // Not in check, but no moves left
+    pub stalemate: bool,
+    // Neither side can mate anymore
+    pub draw: bool,
+    pub winner: Option<Color>,
+}
+
+// What a move did
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Played {
+    // Long notation, like Nd1-e3
+    pub name: String,
+    pub captured: Option<Piece>,
+    pub promotion: Option<PieceKind>,
+}
+
+pub fn parse_kind(letter: char) -> Option<PieceKind> {
+    Some(match letter.to_ascii_uppercase() {
+        'K' => PieceKind::King,
+        'Q' => PieceKind::Queen,
+        'R' => PieceKind::Rook,
+        'B' => PieceKind::Bishop,
+        'N' => PieceKind::Knight,
+        'P' => PieceKind::Pawn,
+        _ => return None,
+    })
+}
+
+// A board and whose turn it is: everything needed to keep playing
+#[derive(Debug, Clone)]
+pub struct Position {
+    pub board: Board,
+    pub turn: Color,
+}
+
+impl Default for Position {
+    fn default() -> Self {
+        let mut board = Board::new();
+        board.load_default();
+        Position {
+            board,
+            turn: Color::Light,
+        }
+    }
+}
+
+impl Position {
+    // Side to move, en passant hex (or -), then every piece by hex with
+    // light in uppercase: "w - Kg1 Qe1 Pe5 kg10 pe7"
+    pub fn parse(text: &str) -> Result<Self, PositionError> {
+        let invalid = |why: &str| PositionError::InvalidNotation(why.to_owned());
+        let mut tokens = text.split_whitespace();
+
+        let turn = match tokens.next() {
+            Some("w") => Color::Light,
+            Some("b") => Color::Dark,
+            _ => return Err(invalid("side to move must be w or b")),
+        };
+        let passant = match tokens.next() {
+            Some("-") => None,
+            Some(hex) => Some(parse_hex(hex).ok_or_else(|| invalid(hex))?),
+            None => return Err(invalid("missing en passant hex")),
+        };
+
+        let mut pieces: Vec<Piece> = vec![];
+        for token in tokens {
+            let mut chars = token.chars();
+            let kind = chars.next().and_then(parse_kind);
+            let (kind, hex) = match (kind, parse_hex(chars.as_str())) {
+                (Some(kind), Some(hex)) => (kind, hex),
+                _ => return Err(invalid(token)),
+            };
+            if pieces.iter().any(|p| (p.q, p.r) == hex) {
+                return Err(invalid(&format!("two pieces on {}", chars.as_str())));
+            }
+            // Pieces are numbered with a byte
+            let idx = u8::try_from(pieces.len())
+                .ok()
+                .filter(|&idx| idx < u8::MAX)
+                .ok_or_else(|| invalid("too many pieces"))?;
+
+            let dark = token.starts_with(|c: char| c.is_ascii_lowercase());
+            pieces.push(Piece {
+                idx,
+                kind,
+                color: if dark { Color::Dark } else { Color::Light },
+                q: hex.0,
+                r: hex.1,
+            });
+        }
+
+        // Same checks as boards from a peer: a king each, and no more pieces
+        // than pawns could have promoted into
+        let mut board = Board::new();
+        let desc: Vec<u16> = pieces.iter().map(Piece::describe).collect();
+        board
+            .load_desc(&desc)
+            .map_err(|e| invalid(&e.to_string()))?;
+
+        if let Some((q, r)) = passant {
+            // The pawn that just moved skipped this hex, heading for the side to move
+            let pawn = if turn.is_light() {
+                r.checked_add(1)
+            } else {
+                r.checked_sub(1)
+            }
+            .and_then(|r| board.get_at(q, r))
+            .filter(|p| p.kind == PieceKind::Pawn && p.color != turn)
+            .ok_or_else(|| invalid("no pawn to take en passant"))?;
+            board.passant = Some((pawn.idx, q, r));
+        }
+        Ok(Position { board, turn })
+    }
+
+    // Same format parse reads. Captured pieces are left out.
+    pub fn notation(&self) -> String {
+        let mut tokens = vec![
+            if self.turn.is_light() { "w" } else { "b" }.to_owned(),
+            self.board
+                .passant
+                .and_then(|(_, q, r)| hex_name(q, r))
+                .unwrap_or_else(|| "-".to_owned()),
+        ];
+        for piece in self.board.pieces.iter().filter(|p| !p.is_captured()) {
+            let mut letter = letter(piece.kind);
+            if !piece.color.is_light() {
+                letter = letter.to_ascii_lowercase();
+            }
+            tokens.push(format!(
+                "{}{}",
+                letter,
+                hex_name(piece.q, piece.r).unwrap_or_default()
+            ));
+        }
+        tokens.join(" ")
+    }
+
+    // Where the piece on a hex may go. Empty if it isn't its turn.
+    pub fn legal_moves(&self, q: u8, r: u8) -> Vec<(u8, u8)> {
+        match self.board.get_at(q, r) {
+            Some(piece) if piece.color == self.turn && !piece.is_captured() => {
+                self.board.available_moves(piece)
+            }
+            _ => vec![],
+        }
+    }
+
+    pub fn play(
+        &mut self,
+        from: (u8, u8),
+        to: (u8, u8),
+        promotion: Option<PieceKind>,
+    ) -> Result<Played, PositionError> {
+        let status = self.status();
+        if status.winner.is_some() || status.stalemate || status.draw {
+            return Err(PositionError::GameOver);
+        }
+
+        let piece = match self.board.get_at(from.0, from.1) {
+            Some(piece) if !piece.is_captured() => *piece,
+            _ => {
+                let hex = hex_name(from.0, from.1).unwrap_or_default();
+                return Err(PositionError::NoPiece(hex));
+            }
+        };
+        if piece.color != self.turn {
+            return Err(PositionError::NotYourTurn);
+        }
+        if !self.board.can_move(&piece, to.0, to.1) {
+            return Err(PositionError::IllegalMove);
+        }
+
+        let promotes = Piece {
+            q: to.0,
+            r: to.1,
+            ..piece
+        }
+        .can_promote();
+        let promotion = match (promotes, promotion) {
+            (false, _) => None,
+            (true, None) => return Err(PositionError::PromotionRequired),
+            (true, Some(PieceKind::King | PieceKind::Pawn)) => {
+                return Err(PositionError::InvalidPromotion)
+            }
+            (true, Some(kind)) => Some(kind),
+        };
+
+        let before = self.board.pieces.clone();
+        let moved = self.board.move_piece(from, to);
+        // Captures come first
+        let captured = match moved.as_slice() {
+            [capture, _] => before.get((capture >> 8) as usize).copied(),
+            _ => None,
+        };
+        if let Some(kind) = promotion {
+            self.board.get_piece_mut(piece.idx).unwrap().promote(kind);
+        }
+        self.turn = self.turn.opposite();
+
+        Ok(Played {
+            name: move_name(&piece, to, captured.is_some()).unwrap_or_default(),
+            captured,
+            promotion,
+        })
+    }
+
+    pub fn status(&self) -> Status {
+        let check = self.board.checked_king(self.turn).is_some();
+        let can_move = self.any_move(|_| true);
+        let mate = check && !self.any_move(|after| after.checked_king(self.turn).is_none());
+
+        let winner = self
+            .board
+            .winner()
+            .or_else(|| mate.then(|| self.turn.opposite()));
+        Status {
+            turn: self.turn,
+            check,
+            mate,
+            stalemate: !check && !can_move,
+            draw: winner.is_none()
+                && !self.board.has_mating_material(Color::Light)
+                && !self.board.has_mating_material(Color::Dark),
+            winner,
+        }
+    }
+
+    // Whether the side to move has a move leaving the board as wanted
+    fn any_move(&self, wanted: impl Fn(&Board) -> bool) -> bool {
+        self.board
+            .pieces
+            .iter()
+            .filter(|p| p.color == self.turn && !p.is_captured())
+            .any(|piece| {
+                self.board.available_moves(piece).into_iter().any(|to| {
+                    let mut after = self.board.clone();
+                    after.move_piece((piece.q, piece.r), to);
+                    wanted(&after)
+                })
+            })
+    }
+}
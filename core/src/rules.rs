@@ -0,0 +1,85 @@
+use crate::{
+    board::Board,
+    piece::{Color, Piece, PieceKind},
+};
+
+impl Board {
+    // King of the given side, if it's in check
+    pub fn checked_king(&self, color: Color) -> Option<&Piece> {
+        self.get_king(color)
+            .filter(|king| self.is_threatened(king.q, king.r, color))
+    }
+
+    // Kings are captured rather than mated, so whoever loses theirs loses
+    pub fn winner(&self) -> Option<Color> {
+        let mut winner = None;
+        for color in [Color::Light, Color::Dark] {
+            if self.get_king(color).is_none() {
+                winner = Some(color.opposite());
+            }
+        }
+        winner
+    }
+
+    // Whether the given side could still mate at all, with help from the
+    // opponent's pieces hemming their own king in
+    pub fn has_mating_material(&self, color: Color) -> bool {
+        let ours = Material::of(self, color);
+        let theirs = Material::of(self, color.opposite());
+        if ours.heavy {
+            return true;
+        }
+
+        if ours.knights == 0 && ours.bishop_colours.count_ones() <= 1 {
+            // Bishops of one colour never reach the hexes around the king on
+            // the other two, so the opponent has to block those themselves
+            return ours.bishops > 0
+                && (theirs.heavy
+                    || theirs.knights > 0
+                    || theirs.bishop_colours & !ours.bishop_colours != 0);
+        }
+        // A lone minor piece needs the opponent to hem the king in, too
+        ours.minors() >= 2 || theirs.heavy || theirs.minors() > 0
+    }
+}
+
+// Hexes come in three colours, and bishops never leave theirs
+fn hex_colour(piece: &Piece) -> u8 {
+    (piece.q + 2 * piece.r) % 3
+}
+
+#[derive(Default)]
+struct Material {
+    // Any queen, rook or pawn
+    heavy: bool,
+    knights: u32,
+    bishops: u32,
+    // One bit per hex colour
+    bishop_colours: u8,
+}
+
+impl Material {
+    fn of(board: &Board, color: Color) -> Self {
+        let mut material = Material::default();
+        for piece in board.pieces.iter() {
+            if piece.color != color || piece.is_captured() {
+                continue;
+            }
+
+            match piece.kind {
+                PieceKind::King => {}
+                PieceKind::Knight => material.knights += 1,
+                PieceKind::Bishop => {
+                    material.bishops += 1;
+                    material.bishop_colours |= 1 << hex_colour(piece);
+                }
+                PieceKind::Queen | PieceKind::Rook | PieceKind::Pawn => material.heavy = true,
+            }
+        }
+        material
+    }
+
+    fn minors(&self) -> u32 {
+        self.knights + self.bishops
+    }
+}
@@ -0,0 +1,100 @@
+//! What signalling clients and servers say to each other, as JSON.
+
+use serde::{Deserialize, Serialize};
+use web_time::{SystemTime, UNIX_EPOCH};
+
+pub type IceCandidate = (String, Option<String>, Option<u16>);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IceServer {
+    pub urls: Vec<String>,
+    #[serde(default)]
+    pub username: String,
+    #[serde(default)]
+    pub credential: String,
+    // Unix time (in seconds) after which the credentials stop working
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires: Option<u64>,
+}
+
+impl IceServer {
+    pub fn is_expired(&self, now: SystemTime) -> bool {
+        let now = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        self.expires.is_some_and(|at| at <= now)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Signal {
+    // Sets the peer's SDP
+    SetSDP(String),
+    // Adds an ICE candidate, as offered by the peer
+    AddCandidate(IceCandidate),
+    // Joins a room
+    JoinRoom(String),
+    // Joins a room's spectator seat, so a game can be watched without
+    // taking the guest's place
+    WatchRoom(String),
+    // The room someone tried to join already has two players
+    RoomFull(String),
+    // Hosts a room again (used to resume a game)
+    HostRoom(RoomClaim),
+    // Opens a room's spectator seat; only its host may
+    HostSpectators(RoomClaim),
+    // Proves the host opened its room, to claim it back later. Sent along
+    // with a new room's code.
+    RoomSecret(String),
+    // When to attempt peer connection
+    ConnectAt(SystemTime),
+    // When to poll next
+    NextPoll(SystemTime),
+    // Signal service
+    SetService(String),
+    // Lists the host's waiting room publicly
+    Advertise(RoomInfo),
+    // Asks for the public rooms
+    ListRooms,
+    // Public rooms waiting for a guest
+    Rooms(Vec<PublicRoom>),
+}
+
+// A room's code, with the secret it was opened with
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoomClaim {
+    pub code: String,
+    pub secret: String,
+}
+
+// What a public room is about, as shown in the lobby
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoomInfo {
+    // Host's name
+    pub name: String,
+    // Seconds per player, or 0 if untimed
+    pub time: u16,
+    pub variant: u8,
+}
+
+impl RoomInfo {
+    // Whether someone looking for `other` would be happy here
+    pub fn matches(&self, other: &RoomInfo) -> bool {
+        self.time == other.time && self.variant == other.variant
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PublicRoom {
+    pub code: String,
+    pub info: RoomInfo,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct IdentResponse {
+    pub token: String,
+    // ICE servers the service wants peers to use
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ice_servers: Vec<IceServer>,
+    // WebSocket relay the service runs, for when WebRTC can't connect
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relay: Option<String>,
+}
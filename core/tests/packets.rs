@@ -0,0 +1,77 @@
+//! Wire format of the peer-to-peer protocol.
+
+use chessagon_core::{
+    buffer::Buffer,
+    packet::{ChessPacket, Handshake, ParseError, SetBoard, FEATURES, FEATURE_CLOCKS},
+};
+
+fn roundtrip(packet: ChessPacket) -> ChessPacket {
+    let data: Vec<u8> = packet.write().into();
+    ChessPacket::read(data.into()).unwrap()
+}
+
+#[test]
+fn board_survives_the_wire() {
+    let board = vec![0x0606, 0x0a4a, 0x0800];
+    match roundtrip(ChessPacket::SetBoard(SetBoard {
+        board: board.clone(),
+    })) {
+        ChessPacket::SetBoard(p) => assert_eq!(p.board, board),
+        p => panic!("unexpected packet {:?}", p),
+    }
+}
+
+#[test]
+fn handshake_negotiates_with_itself() {
+    match roundtrip(ChessPacket::Handshake(Handshake::new("light".to_owned()))) {
+        ChessPacket::Handshake(p) => {
+            assert_eq!(p.name, "light");
+            assert!(p.negotiate().is_some());
+        }
+        p => panic!("unexpected packet {:?}", p),
+    }
+}
+
+#[test]
+fn handshake_agrees_on_the_highest_shared_version() {
+    let range = |min_version, max_version| Handshake {
+        min_version,
+        max_version,
+        ..Handshake::new("dark".to_owned())
+    };
+    let (older, newer) = (range(3, 5), range(4, 7));
+    assert_eq!(older.negotiate_with(&newer), Some((5, FEATURES)));
+    assert_eq!(newer.negotiate_with(&older), Some((5, FEATURES)));
+    assert!(range(1, 2).negotiate_with(&newer).is_none());
+
+    // Only the features both have are used
+    let plain = Handshake {
+        features: FEATURE_CLOCKS,
+        ..range(4, 4)
+    };
+    assert_eq!(newer.negotiate_with(&plain), Some((4, FEATURE_CLOCKS)));
+
+    // Version 0 peers only sent their name
+    let mut data = Buffer::new();
+    data.write_u8(0).write_u8(0).write_string("dark");
+    match ChessPacket::read(data) {
+        Ok(ChessPacket::Handshake(p)) => assert!(p.negotiate().is_none()),
+        p => panic!("unexpected packet {:?}", p),
+    }
+}
+
+#[test]
+fn rejects_truncated_and_foreign_packets() {
+    let data: Vec<u8> = ChessPacket::SetBoard(SetBoard { board: vec![1, 2] })
+        .write()
+        .into();
+    let truncated = Buffer::from_slice(&data[..data.len() - 1]);
+    assert!(ChessPacket::read(truncated).is_err());
+
+    let mut foreign = data.clone();
+    foreign[0] = 0;
+    assert!(matches!(
+        ChessPacket::read(foreign.into()),
+        Err(ParseError::VersionMismatch(0, _))
+    ));
+}
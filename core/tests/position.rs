@@ -0,0 +1,145 @@
+//! Positions: reading and writing them, and playing on them.
+
+use chessagon_core::{
+    notation::parse_hex,
+    piece::{Color, PieceKind},
+    position::{Position, PositionError},
+};
+
+fn hex(name: &str) -> (u8, u8) {
+    parse_hex(name).unwrap()
+}
+
+#[test]
+fn notation_round_trips() {
+    let start = Position::default();
+    let text = start.notation();
+    assert!(text.starts_with("w - "));
+    assert_eq!(text.split_whitespace().count(), 2 + 36);
+    assert_eq!(Position::parse(&text).unwrap().notation(), text);
+
+    let text = "b - Kg1 kf11 Qa6 Pb5";
+    assert_eq!(Position::parse(text).unwrap().notation(), text);
+}
+
+#[test]
+fn rejects_bad_notation() {
+    for bad in [
+        "",
+        "x - Kg1",
+        "w",
+        "w j4 Kg1",
+        "w - Xg1",
+        "w - Kj1",
+        "w - Kg1 kg1",
+        "w f5 Kg1 kg10",
+        // No pieces, a missing king, an extra one, and more than could promote
+        "w -",
+        "w - Kg1",
+        "w - Kg1 kg10 kf11",
+        "w - Kg1 kg10 Qa6 Qb6 Qc6 Qd6 Qe6 Qf6 Qg6 Qh6 Qi6 Qk6 Qa5",
+    ] {
+        assert!(
+            matches!(Position::parse(bad), Err(PositionError::InvalidNotation(_))),
+            "{}",
+            bad
+        );
+    }
+}
+
+#[test]
+fn lists_moves_for_the_side_to_move() {
+    let start = Position::default();
+    let mut moves = start.legal_moves(4, 7);
+    moves.sort();
+    assert_eq!(moves, [(4, 5), (4, 6)]);
+
+    // Dark's pawns wait their turn, empty hexes have nothing
+    assert!(start.legal_moves(5, 4).is_empty());
+    assert!(start.legal_moves(5, 5).is_empty());
+}
+
+#[test]
+fn plays_and_reports_captures() {
+    let mut position = Position::parse("w - Kg1 kg10 Qe1 pe5").unwrap();
+    assert_eq!(
+        position.play(hex("e1"), hex("e6"), None),
+        Err(PositionError::IllegalMove)
+    );
+    assert_eq!(
+        position.play(hex("e2"), hex("e3"), None),
+        Err(PositionError::NoPiece("e2".to_owned()))
+    );
+    assert_eq!(
+        position.play(hex("e5"), hex("e4"), None),
+        Err(PositionError::NotYourTurn)
+    );
+
+    let played = position.play(hex("e1"), hex("e5"), None).unwrap();
+    assert_eq!(played.name, "Qe1xe5");
+    assert_eq!(played.captured.unwrap().kind, PieceKind::Pawn);
+    assert_eq!(position.turn, Color::Dark);
+    assert_eq!(position.notation(), "b - Kg1 kg10 Qe5");
+}
+
+#[test]
+fn pawns_need_a_promotion() {
+    let mut position = Position::parse("w - Kg1 kg10 Pb6").unwrap();
+    let (from, to) = (hex("b6"), hex("b7"));
+    assert_eq!(
+        position.play(from, to, None),
+        Err(PositionError::PromotionRequired)
+    );
+    assert_eq!(
+        position.play(from, to, Some(PieceKind::King)),
+        Err(PositionError::InvalidPromotion)
+    );
+
+    let played = position.play(from, to, Some(PieceKind::Knight)).unwrap();
+    assert_eq!(played.promotion, Some(PieceKind::Knight));
+    assert_eq!(position.notation(), "b - Kg1 kg10 Nb7");
+}
+
+#[test]
+fn tells_check_from_mate() {
+    let check = Position::parse("b - Kg1 kf11 Qf5").unwrap().status();
+    assert!(check.check);
+    assert!(!check.mate);
+    assert_eq!(check.winner, None);
+
+    let mut mated = Position::parse("b - Kg1 kf11 Qa6 Qf9").unwrap();
+    let status = mated.status();
+    assert!(status.mate);
+    assert_eq!(status.winner, Some(Color::Light));
+    assert_eq!(
+        mated.play(hex("f11"), hex("e10"), None),
+        Err(PositionError::GameOver)
+    );
+}
+
+#[test]
+fn bare_kings_are_a_draw() {
+    let status = Position::parse("w - Kg1 kg10").unwrap().status();
+    assert!(status.draw);
+    assert!(!status.check);
+    assert!(!status.stalemate);
+    assert!(!Position::default().status().draw);
+}
+
+#[test]
+fn nothing_is_played_after_a_stalemate_or_draw() {
+    let mut stalemate = Position::parse("b - Ka5 Qc2 ka3").unwrap();
+    let status = stalemate.status();
+    assert!(status.stalemate);
+    assert_eq!(status.winner, None);
+    assert_eq!(
+        stalemate.play(hex("a3"), hex("a4"), None),
+        Err(PositionError::GameOver)
+    );
+
+    let mut bare = Position::parse("w - Kg1 kg10").unwrap();
+    assert_eq!(
+        bare.play(hex("g1"), hex("g2"), None),
+        Err(PositionError::GameOver)
+    );
+}
@@ -0,0 +1,185 @@
+//! Board rules and notation, natively.
+
+use chessagon_core::{
+    board::{Board, BoardError},
+    notation::{hex_name, move_name, on_board, parse_hex},
+    piece::{Color, PieceKind},
+    position::Position,
+};
+
+fn default_board() -> Board {
+    let mut board = Board::new();
+    board.load_default();
+    board
+}
+
+fn at(board: &Board, name: &str) -> (u8, u8) {
+    let (q, r) = parse_hex(name).unwrap();
+    assert!(board.get_at(q, r).is_some(), "no piece at {}", name);
+    (q, r)
+}
+
+#[test]
+fn default_setup_matches_glinski() {
+    let board = default_board();
+    assert_eq!(board.pieces.len(), 36);
+
+    let king = board.get_king(Color::Light).unwrap();
+    assert_eq!(hex_name(king.q, king.r).unwrap(), "g1");
+    let king = board.get_king(Color::Dark).unwrap();
+    assert_eq!(hex_name(king.q, king.r).unwrap(), "g10");
+
+    let queen = board.get_at(4, 10).unwrap();
+    assert_eq!(queen.kind, PieceKind::Queen);
+    assert_eq!(hex_name(queen.q, queen.r).unwrap(), "e1");
+}
+
+#[test]
+fn names_every_hex_once() {
+    let mut names = vec![];
+    for q in 0..=10 {
+        for r in 0..=10 {
+            match hex_name(q, r) {
+                Some(name) => {
+                    assert!(on_board(q, r));
+                    assert_eq!(parse_hex(&name), Some((q, r)));
+                    names.push(name);
+                }
+                None => assert!(!on_board(q, r)),
+            }
+        }
+    }
+
+    assert_eq!(names.len(), 91);
+    names.sort();
+    names.dedup();
+    assert_eq!(names.len(), 91);
+
+    for bad in ["", "j1", "a0", "a7", "l12", "f", "fx"] {
+        assert_eq!(parse_hex(bad), None, "{}", bad);
+    }
+}
+
+#[test]
+fn pawns_advance_one_or_two_hexes() {
+    let board = default_board();
+    let (q, r) = at(&board, "e4");
+    let pawn = board.get_at(q, r).unwrap();
+    let moves = board.available_moves(pawn);
+    assert_eq!(moves.len(), 2);
+    let (q, r) = parse_hex("e6").unwrap();
+    assert!(board.can_move(pawn, q, r));
+}
+
+#[test]
+fn moves_are_named_and_tracked() {
+    let mut board = default_board();
+    let from = at(&board, "e4");
+    let to = parse_hex("e6").unwrap();
+    let pawn = *board.get_at(from.0, from.1).unwrap();
+    assert_eq!(move_name(&pawn, to, false).unwrap(), "e4-e6");
+
+    let before = board.checksum(Color::Light);
+    board.move_piece(from, to);
+    assert_ne!(board.checksum(Color::Dark), before);
+    // Jumping two hexes allows en passant
+    assert_eq!(board.passant.map(|(idx, ..)| idx), Some(pawn.idx));
+
+    let knight = *board.get_at(3, 10).unwrap();
+    assert_eq!(
+        move_name(&knight, parse_hex("d3").unwrap(), true).unwrap(),
+        "Nd1xd3"
+    );
+}
+
+#[test]
+fn captured_king_decides_the_winner() {
+    let mut board = default_board();
+    assert_eq!(board.winner(), None);
+    assert!(board.checked_king(Color::Light).is_none());
+
+    let king = board.get_king(Color::Dark).unwrap().idx;
+    board.get_piece_mut(king).unwrap().movement(0, 0);
+    assert_eq!(board.winner(), Some(Color::Light));
+}
+
+fn can_mate(position: &str) -> (bool, bool) {
+    let board = Position::parse(position).unwrap().board;
+    (
+        board.has_mating_material(Color::Light),
+        board.has_mating_material(Color::Dark),
+    )
+}
+
+#[test]
+fn lone_minor_piece_needs_help_to_mate() {
+    assert_eq!(can_mate("w - Kg1 kg10 Nd1"), (false, false));
+    assert_eq!(can_mate("w - Kg1 kg10 Bf1"), (false, false));
+    // Dark's own pawn may end up blocking its king
+    assert_eq!(can_mate("w - Kg1 kg10 Nd1 pe7"), (true, true));
+    assert_eq!(can_mate("w - Kg1 kg10 Nd1 nd9"), (true, true));
+}
+
+#[test]
+fn bishops_on_one_colour_cant_mate() {
+    // f1 and f4 share a colour, f2 doesn't
+    assert_eq!(can_mate("w - Kg1 kg10 Bf1 Bf4"), (false, false));
+    assert_eq!(can_mate("w - Kg1 kg10 Bf1 Bf2"), (true, false));
+    assert_eq!(can_mate("w - Kg1 kg10 Bf1 bf4"), (false, false));
+    assert_eq!(can_mate("w - Kg1 kg10 Bf1 bf5"), (true, true));
+    assert_eq!(can_mate("w - Kg1 kg10 Bf1 Nd1"), (true, false));
+}
+
+#[test]
+fn loads_only_boards_that_make_sense() {
+    let desc = default_board().describe();
+    let mut board = Board::new();
+    assert_eq!(board.load_desc(&desc), Ok(()));
+    assert_eq!(board.pieces.len(), 36);
+
+    // Captured pieces all share the same spot
+    let mut taken = desc.clone();
+    taken[0] &= !0xff;
+    taken[1] &= !0xff;
+    assert_eq!(board.load_desc(&taken), Ok(()));
+
+    let with = |idx: usize, piece: u16| {
+        let mut desc = desc.clone();
+        desc[idx] = piece;
+        desc
+    };
+    let light_king = default_board().get_king(Color::Light).unwrap().idx as usize;
+    let (q, r) = parse_hex("f6").unwrap();
+    let on_f6 = |kind: u16| kind << 8 | (q as u16) << 4 | r as u16;
+
+    let bad = [
+        (with(3, 7 << 8 | 0x55), BoardError::UnknownKind(3)),
+        // Cut off corner
+        (with(3, 1 << 8 | 0x11), BoardError::OffBoard(3)),
+        (with(3, desc[2]), BoardError::Overlapping(3)),
+        (
+            with(light_king, on_f6(1)),
+            BoardError::MissingKing(Color::Light),
+        ),
+        (
+            [&desc[..], &[on_f6(0)]].concat(),
+            BoardError::DuplicateKing(Color::Light),
+        ),
+        // A tenth pawn, where only nine fit
+        (
+            [&desc[..], &[on_f6(5)]].concat(),
+            BoardError::TooManyPieces(Color::Light),
+        ),
+    ];
+    for (desc, err) in bad {
+        assert_eq!(board.load_desc(&desc), Err(err));
+    }
+    // Still the board that last loaded
+    assert_eq!(board.describe(), taken);
+
+    // Pawns can become queens, though
+    let mut promoted = desc.clone();
+    let pawn = promoted.iter().position(|p| p >> 8 == 5).unwrap();
+    promoted[pawn] = (promoted[pawn] & !0x700) | 1 << 8;
+    assert_eq!(board.load_desc(&promoted), Ok(()));
+}
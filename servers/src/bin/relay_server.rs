@@ -0,0 +1,216 @@
+// Minimal WebSocket relay for local development:
+//   cargo run -p chessagon-servers --bin relay-server [address]
+// then open the game with ?relay=ws://<address>
+// Peers connecting to the same /<session> get their frames forwarded to
+// each other; both receive an "open" text frame once paired.
+use std::{
+    collections::HashMap,
+    env,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{Shutdown, TcpListener, TcpStream},
+    sync::{
+        mpsc::{channel, Sender},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use sha1_smol::Sha1;
+
+const DEFAULT_ADDRESS: &str = "127.0.0.1:8788";
+const WS_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+// Game packets are tiny; bigger frames drop the connection
+const MAX_FRAME: u64 = 64 * 1024;
+// Upgrade request line and headers
+const MAX_HANDSHAKE: u64 = 8 * 1024;
+
+const OP_TEXT: u8 = 0x1;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xa;
+
+// Session -> first peer's socket, and where to send it its partner
+type Sessions = Arc<Mutex<HashMap<String, (TcpStream, Sender<TcpStream>)>>>;
+
+struct Frame {
+    fin: bool,
+    opcode: u8,
+    payload: Vec<u8>,
+}
+
+fn read_frame(stream: &mut impl Read) -> io::Result<Frame> {
+    let mut head = [0; 2];
+    stream.read_exact(&mut head)?;
+
+    let len = match head[1] & 0x7f {
+        126 => {
+            let mut len = [0; 2];
+            stream.read_exact(&mut len)?;
+            u16::from_be_bytes(len).into()
+        }
+        127 => {
+            let mut len = [0; 8];
+            stream.read_exact(&mut len)?;
+            u64::from_be_bytes(len)
+        }
+        len => len.into(),
+    };
+    if len > MAX_FRAME {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "frame too large",
+        ));
+    }
+
+    let mut mask = [0; 4];
+    if head[1] & 0x80 != 0 {
+        stream.read_exact(&mut mask)?;
+    }
+
+    let mut payload = vec![0; len as usize];
+    stream.read_exact(&mut payload)?;
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+
+    Ok(Frame {
+        fin: head[0] & 0x80 != 0,
+        opcode: head[0] & 0x0f,
+        payload,
+    })
+}
+
+fn write_frame(stream: &mut impl Write, fin: bool, opcode: u8, payload: &[u8]) -> io::Result<()> {
+    let mut data = vec![if fin { 0x80 } else { 0 } | opcode];
+    match payload.len() {
+        len if len < 126 => data.push(len as u8),
+        len if len <= u16::MAX as usize => {
+            data.push(126);
+            data.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            data.push(127);
+            data.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    data.extend_from_slice(payload);
+    stream.write_all(&data)
+}
+
+// Answers the upgrade request, returning the requested session
+fn handshake(stream: &mut TcpStream) -> Option<String> {
+    let mut reader = BufReader::new(stream.try_clone().ok()?.take(MAX_HANDSHAKE));
+
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+    let session = line
+        .split_whitespace()
+        .nth(1)?
+        .trim_start_matches('/')
+        .to_owned();
+
+    let mut key = None;
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).ok()?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("sec-websocket-key") {
+                key = Some(value.trim().to_owned());
+            }
+        }
+    }
+
+    let accept = Sha1::from(format!("{}{}", key?, WS_GUID)).digest().bytes();
+    let resp = format!(
+        "HTTP/1.1 101 Switching Protocols\r\n\
+         Upgrade: websocket\r\n\
+         Connection: Upgrade\r\n\
+         Sec-WebSocket-Accept: {}\r\n\r\n",
+        STANDARD.encode(accept)
+    );
+    stream.write_all(resp.as_bytes()).ok()?;
+
+    if session.is_empty() {
+        return None;
+    }
+    Some(session)
+}
+
+fn forward(mut from: TcpStream, mut to: TcpStream) -> io::Result<()> {
+    write_frame(&mut from, true, OP_TEXT, b"open")?;
+
+    loop {
+        let frame = read_frame(&mut from)?;
+        match frame.opcode {
+            OP_CLOSE => {
+                let _ = write_frame(&mut from, true, OP_CLOSE, &frame.payload);
+                break;
+            }
+            OP_PING => write_frame(&mut from, true, OP_PONG, &frame.payload)?,
+            OP_PONG => {}
+            opcode => write_frame(&mut to, frame.fin, opcode, &frame.payload)?,
+        }
+    }
+    Ok(())
+}
+
+fn handle(mut stream: TcpStream, sessions: Sessions) {
+    let session = match handshake(&mut stream) {
+        Some(s) => s,
+        None => return,
+    };
+    let own = match stream.try_clone() {
+        Ok(s) => s,
+        Err(_) => return,
+    };
+
+    let waiting = sessions.lock().unwrap().remove(&session);
+    let partner = match waiting {
+        Some((partner, tx)) => {
+            if tx.send(own).is_err() {
+                return;
+            }
+            partner
+        }
+        None => {
+            let (tx, rx) = channel();
+            sessions.lock().unwrap().insert(session.clone(), (own, tx));
+            match rx.recv() {
+                Ok(partner) => partner,
+                Err(_) => return,
+            }
+        }
+    };
+
+    let closing = partner.try_clone();
+    if let Err(e) = forward(stream, partner) {
+        eprintln!("session {}: {}", session, e);
+    }
+    // Tell the other side we're gone
+    if let Ok(partner) = closing {
+        let _ = partner.shutdown(Shutdown::Both);
+    }
+}
+
+fn main() {
+    let address = env::args().nth(1).unwrap_or(DEFAULT_ADDRESS.to_owned());
+    let listener = TcpListener::bind(&address).expect("couldn't bind address");
+    println!("relaying on ws://{}", address);
+
+    let sessions: Sessions = Default::default();
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let sessions = sessions.clone();
+                thread::spawn(move || handle(stream, sessions));
+            }
+            Err(e) => eprintln!("couldn't accept connection: {}", e),
+        }
+    }
+}
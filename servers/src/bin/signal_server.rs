@@ -0,0 +1,140 @@
+// Minimal signalling server for local development:
+//   cargo run -p chessagon-servers --bin signal-server [address]
+// then open the game with ?signal=http://<address>
+// ICE servers for the peers can be given as a JSON list in $ICE_SERVERS, and
+// the WebSocket relay they fall back to in $RELAY_URL.
+use std::{
+    env,
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use chessagon_servers::RoomServer;
+
+const DEFAULT_ADDRESS: &str = "127.0.0.1:8787";
+// Polls carry a handful of signals; larger bodies are refused
+const MAX_BODY: usize = 64 * 1024;
+// Request line and headers
+const MAX_HEAD: u64 = 8 * 1024;
+
+struct Request {
+    method: String,
+    path: String,
+    auth: Option<String>,
+    body: String,
+}
+
+fn read_request(stream: &TcpStream) -> Option<Request> {
+    let mut reader = BufReader::new(stream.take(MAX_HEAD + MAX_BODY as u64));
+
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next()?.to_owned();
+    let path = parts.next()?.to_owned();
+
+    let mut auth = None;
+    let mut length = 0;
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).ok()?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+
+        let (name, value) = line.split_once(':')?;
+        match name.to_ascii_lowercase().as_str() {
+            "authorization" => auth = Some(value.trim().to_owned()),
+            "content-length" => length = value.trim().parse().ok()?,
+            _ => {}
+        }
+    }
+
+    if length > MAX_BODY {
+        return None;
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).ok()?;
+    Some(Request {
+        method,
+        path,
+        auth,
+        body: String::from_utf8(body).ok()?,
+    })
+}
+
+fn write_response(mut stream: &TcpStream, status: u16, body: &str) {
+    let reason = match status {
+        200 => "OK",
+        204 => "No Content",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        _ => "Not Found",
+    };
+    let resp = format!(
+        "HTTP/1.1 {} {}\r\n\
+         Access-Control-Allow-Origin: *\r\n\
+         Access-Control-Allow-Methods: POST, OPTIONS\r\n\
+         Access-Control-Allow-Headers: Authorization, Content-Type\r\n\
+         Content-Type: application/json\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\r\n{}",
+        status,
+        reason,
+        body.len(),
+        body
+    );
+    if let Err(e) = stream.write_all(resp.as_bytes()) {
+        eprintln!("couldn't write response: {}", e);
+    }
+}
+
+fn main() {
+    let address = env::args().nth(1).unwrap_or(DEFAULT_ADDRESS.to_owned());
+    let listener = TcpListener::bind(&address).expect("couldn't bind address");
+    println!("signalling on http://{}", address);
+
+    let seed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("time travel?")
+        .as_nanos() as u64;
+    let mut server = RoomServer::new(seed);
+    if let Ok(json) = env::var("ICE_SERVERS") {
+        server
+            .set_ice_servers(&json)
+            .expect("invalid ICE_SERVERS list");
+    }
+    if let Ok(url) = env::var("RELAY_URL") {
+        server.set_relay(&url);
+    }
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(s) => s,
+            Err(e) => {
+                eprintln!("couldn't accept connection: {}", e);
+                continue;
+            }
+        };
+
+        let req = match read_request(&stream) {
+            Some(r) => r,
+            None => {
+                write_response(&stream, 400, "bad request");
+                continue;
+            }
+        };
+
+        if req.method == "OPTIONS" {
+            // CORS preflight
+            write_response(&stream, 204, "");
+            continue;
+        }
+
+        let (status, body) =
+            server.respond(&req.path, req.auth.as_deref(), &req.body, SystemTime::now());
+        write_response(&stream, status, &body);
+    }
+}
@@ -0,0 +1,6 @@
+//! Reference servers for local development. They're kept out of the game
+//! itself, which only ever talks to them over the network.
+
+mod rooms;
+
+pub use rooms::RoomServer;
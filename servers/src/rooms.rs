@@ -0,0 +1,426 @@
+use std::collections::HashMap;
+
+use rand::{rngs::SmallRng, RngCore, SeedableRng};
+use web_time::{Duration, SystemTime};
+
+use chessagon_core::signalling::{
+    IceCandidate, IceServer, IdentResponse, PublicRoom, RoomClaim, RoomInfo, Signal,
+};
+
+// How often clients are told to poll
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+// Delay between pairing two peers and making them connect
+const CONNECT_DELAY: Duration = Duration::from_secs(1);
+// Peers that don't poll for this long are forgotten
+const PEER_TIMEOUT: Duration = Duration::from_secs(60);
+// How long a host that went away may still claim its room back
+const CLAIM_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+
+const CODE_CHARS: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+const CODE_LENGTH: usize = 6;
+// Most public rooms handed out at once
+const MAX_LISTED: usize = 50;
+
+// Every code has a seat for the guest, and one spectators take turns in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Seat {
+    Player,
+    Spectator,
+}
+
+type RoomKey = (String, Seat);
+
+struct Peer {
+    // Room this peer is waiting in, as a host
+    room: Option<RoomKey>,
+    partner: Option<String>,
+    offer: Option<String>,
+    // Set if the room should be listed publicly
+    info: Option<RoomInfo>,
+    // Candidates gathered before a partner showed up
+    pending_ice: Vec<IceCandidate>,
+    outbox: Vec<Signal>,
+    // Number of the last poll, and what it was answered, for retries
+    last_poll: Option<(u32, Vec<Signal>)>,
+    last_seen: SystemTime,
+}
+
+impl Peer {
+    fn new(now: SystemTime) -> Self {
+        Peer {
+            room: None,
+            partner: None,
+            offer: None,
+            info: None,
+            pending_ice: vec![],
+            outbox: vec![],
+            last_poll: None,
+            last_seen: now,
+        }
+    }
+}
+
+// What it takes to host a room again
+struct Claim {
+    secret: String,
+    host: String,
+    // Last time the host was around
+    seen: SystemTime,
+}
+
+// Reference implementation of the `/ident` + `/poll` room protocol
+pub struct RoomServer {
+    rng: SmallRng,
+    peers: HashMap<String, Peer>,
+    // Room -> token of the host waiting in it
+    rooms: HashMap<RoomKey, String>,
+    // Room -> token of the host, once someone joined
+    full: HashMap<RoomKey, String>,
+    // Room code -> who may host it again
+    claims: HashMap<String, Claim>,
+    ice_servers: Vec<IceServer>,
+    relay: Option<String>,
+}
+
+impl RoomServer {
+    pub fn new(seed: u64) -> Self {
+        RoomServer {
+            rng: SmallRng::seed_from_u64(seed),
+            peers: HashMap::new(),
+            rooms: HashMap::new(),
+            full: HashMap::new(),
+            claims: HashMap::new(),
+            ice_servers: vec![],
+            relay: None,
+        }
+    }
+
+    // ICE servers handed to peers when they ident, as a JSON list
+    pub fn set_ice_servers(&mut self, json: &str) -> Result<(), serde_json::Error> {
+        self.ice_servers = serde_json::from_str(json)?;
+        Ok(())
+    }
+
+    // WebSocket relay peers fall back to when WebRTC can't connect
+    pub fn set_relay(&mut self, url: &str) {
+        self.relay = Some(url.to_owned());
+    }
+
+    pub fn ident(&mut self, now: SystemTime) -> String {
+        self.prune(now);
+
+        let token = format!("{:016x}{:016x}", self.rng.next_u64(), self.rng.next_u64());
+        self.peers.insert(token.clone(), Peer::new(now));
+        token
+    }
+
+    // Returns None if the token is unknown. A poll numbered like the last
+    // one is a retry: it gets the same answer, and its signals are ignored.
+    pub fn poll(
+        &mut self,
+        token: &str,
+        seq: Option<u32>,
+        signals: Vec<Signal>,
+        now: SystemTime,
+    ) -> Option<Vec<Signal>> {
+        self.prune(now);
+        let peer = self.peers.get_mut(token)?;
+        peer.last_seen = now;
+        if let (Some(seq), Some((last, answer))) = (seq, &peer.last_poll) {
+            if seq == *last {
+                return Some(answer.clone());
+            }
+        }
+
+        for signal in signals {
+            match signal {
+                Signal::HostRoom(claim) => {
+                    if !self.reclaim(token, claim, Seat::Player, now) {
+                        // Whatever came with it was meant for that room
+                        break;
+                    }
+                }
+                Signal::HostSpectators(claim) => {
+                    if !self.reclaim(token, claim, Seat::Spectator, now) {
+                        break;
+                    }
+                }
+                Signal::JoinRoom(code) => self.join(token, (code, Seat::Player), now),
+                Signal::WatchRoom(code) => self.join(token, (code, Seat::Spectator), now),
+                Signal::SetSDP(sdp) => self.set_sdp(token, sdp, now),
+                Signal::AddCandidate(ice) => self.add_candidate(token, ice),
+                Signal::Advertise(info) => self.advertise(token, info),
+                Signal::ListRooms => {
+                    let rooms = self.public_rooms();
+                    if let Some(peer) = self.peers.get_mut(token) {
+                        peer.outbox.push(Signal::Rooms(rooms));
+                    }
+                }
+                Signal::RoomFull(_)
+                | Signal::RoomSecret(_)
+                | Signal::Rooms(_)
+                | Signal::ConnectAt(_)
+                | Signal::NextPoll(_)
+                | Signal::SetService(_) => {}
+            }
+        }
+
+        let peer = self.peers.get_mut(token)?;
+        let mut signals: Vec<_> = peer.outbox.drain(0..).collect();
+        signals.push(Signal::NextPoll(now + POLL_INTERVAL));
+        if let Some(seq) = seq {
+            peer.last_poll = Some((seq, signals.clone()));
+        }
+        Some(signals)
+    }
+
+    // Answers an HTTP request, as (status, body)
+    pub fn respond(
+        &mut self,
+        path: &str,
+        auth: Option<&str>,
+        body: &str,
+        now: SystemTime,
+    ) -> (u16, String) {
+        let (path, query) = path.split_once('?').unwrap_or((path, ""));
+        let seq = query
+            .split('&')
+            .find_map(|param| param.strip_prefix("seq="))
+            .and_then(|seq| seq.parse().ok());
+
+        match (path, auth) {
+            ("/ident", _) => {
+                let resp = IdentResponse {
+                    token: self.ident(now),
+                    ice_servers: self.ice_servers.clone(),
+                    relay: self.relay.clone(),
+                };
+                (200, serde_json::to_string(&resp).unwrap())
+            }
+            ("/poll", Some(token)) => {
+                let signals = match serde_json::from_str(body) {
+                    Ok(s) => s,
+                    Err(_) => return (400, "bad request".to_owned()),
+                };
+
+                match self.poll(token, seq, signals, now) {
+                    Some(resp) => (200, serde_json::to_string(&resp).unwrap()),
+                    None => (401, "unknown token".to_owned()),
+                }
+            }
+            ("/poll", None) => (401, "missing token".to_owned()),
+            _ => (404, "not found".to_owned()),
+        }
+    }
+
+    fn new_code(&mut self) -> String {
+        loop {
+            let code: String = (0..CODE_LENGTH)
+                .map(|_| {
+                    let idx = self.rng.next_u32() as usize % CODE_CHARS.len();
+                    CODE_CHARS[idx] as char
+                })
+                .collect();
+
+            let key = (code, Seat::Player);
+            if !self.rooms.contains_key(&key) && !self.claims.contains_key(&key.0) {
+                return key.0;
+            }
+        }
+    }
+
+    fn new_secret(&mut self) -> String {
+        format!("{:016x}{:016x}", self.rng.next_u64(), self.rng.next_u64())
+    }
+
+    // Only whoever opened a room gets to host it again, or let spectators in
+    fn reclaim(&mut self, token: &str, claim: RoomClaim, seat: Seat, now: SystemTime) -> bool {
+        let allowed = self
+            .claims
+            .get(&claim.code)
+            .is_some_and(|c| c.secret == claim.secret);
+        if !allowed {
+            if let Some(peer) = self.peers.get_mut(token) {
+                peer.outbox.push(Signal::RoomFull(claim.code));
+            }
+            return false;
+        }
+
+        if let Some(c) = self.claims.get_mut(&claim.code) {
+            if seat == Seat::Player {
+                c.host = token.to_owned();
+            }
+            c.seen = now;
+        }
+        self.host(token, (claim.code, seat));
+        true
+    }
+
+    fn host(&mut self, token: &str, key: RoomKey) {
+        // The latest host of a seat takes it over
+        self.full.remove(&key);
+        if let Some(old) = self.rooms.insert(key.clone(), token.to_owned()) {
+            if let Some(peer) = self.peers.get_mut(&old) {
+                peer.room = None;
+            }
+        }
+        if let Some(peer) = self.peers.get_mut(token) {
+            peer.room = Some(key);
+        }
+    }
+
+    fn advertise(&mut self, token: &str, info: RoomInfo) {
+        if let Some(peer) = self.peers.get_mut(token) {
+            peer.info = Some(info);
+        }
+    }
+
+    // Advertised rooms whose host is still waiting
+    fn public_rooms(&self) -> Vec<PublicRoom> {
+        let mut rooms: Vec<_> = self
+            .rooms
+            .iter()
+            .filter(|((_, seat), _)| *seat == Seat::Player)
+            .filter_map(|((code, _), host)| {
+                let peer = self.peers.get(host)?;
+                peer.offer.as_ref()?;
+                Some(PublicRoom {
+                    code: code.clone(),
+                    info: peer.info.clone()?,
+                })
+            })
+            .collect();
+        rooms.sort_by(|a, b| a.code.cmp(&b.code));
+        rooms.truncate(MAX_LISTED);
+        rooms
+    }
+
+    fn join(&mut self, token: &str, key: RoomKey, now: SystemTime) {
+        let host = match self.rooms.get(&key) {
+            Some(host) if host != token => host.clone(),
+            Some(_) => return,
+            None => {
+                if self.full.contains_key(&key) {
+                    if let Some(peer) = self.peers.get_mut(token) {
+                        peer.outbox.push(Signal::RoomFull(key.0));
+                    }
+                }
+                return;
+            }
+        };
+        let offer = match self.peers.get(&host).and_then(|p| p.offer.clone()) {
+            Some(offer) => offer,
+            None => return,
+        };
+        self.rooms.remove(&key);
+        self.full.insert(key.clone(), host.clone());
+
+        let pending = match self.peers.get_mut(&host) {
+            Some(peer) => {
+                peer.room = None;
+                peer.partner = Some(token.to_owned());
+                peer.pending_ice.drain(0..).collect::<Vec<_>>()
+            }
+            None => return,
+        };
+
+        if let Some(peer) = self.peers.get_mut(token) {
+            peer.partner = Some(host);
+            peer.last_seen = now;
+            peer.outbox.push(Signal::JoinRoom(key.0));
+            peer.outbox.push(Signal::SetSDP(offer));
+            peer.outbox
+                .extend(pending.into_iter().map(Signal::AddCandidate));
+        }
+    }
+
+    fn set_sdp(&mut self, token: &str, sdp: String, now: SystemTime) {
+        let partner = match self.peers.get(token) {
+            Some(peer) => peer.partner.clone(),
+            None => return,
+        };
+
+        match partner {
+            Some(partner) => {
+                // Guest's answer: both sides are ready to connect
+                let at = now + CONNECT_DELAY;
+                if let Some(peer) = self.peers.get_mut(&partner) {
+                    peer.outbox.push(Signal::SetSDP(sdp));
+                    peer.outbox.push(Signal::ConnectAt(at));
+                }
+                if let Some(peer) = self.peers.get_mut(token) {
+                    peer.outbox.push(Signal::ConnectAt(at));
+                }
+            }
+            None => {
+                // Host's offer: open a room unless one was requested
+                let code = match self.peers.get(token).and_then(|p| p.room.clone()) {
+                    Some((code, _)) => code,
+                    None => {
+                        let code = self.new_code();
+                        let secret = self.new_secret();
+                        self.claims.insert(
+                            code.clone(),
+                            Claim {
+                                secret: secret.clone(),
+                                host: token.to_owned(),
+                                seen: now,
+                            },
+                        );
+                        if let Some(peer) = self.peers.get_mut(token) {
+                            peer.outbox.push(Signal::RoomSecret(secret));
+                        }
+                        self.host(token, (code.clone(), Seat::Player));
+                        code
+                    }
+                };
+
+                if let Some(peer) = self.peers.get_mut(token) {
+                    peer.offer = Some(sdp);
+                    peer.outbox.push(Signal::JoinRoom(code));
+                }
+            }
+        }
+    }
+
+    fn add_candidate(&mut self, token: &str, ice: IceCandidate) {
+        let partner = match self.peers.get_mut(token) {
+            Some(peer) if peer.partner.is_none() => {
+                peer.pending_ice.push(ice);
+                return;
+            }
+            Some(peer) => peer.partner.clone().unwrap(),
+            None => return,
+        };
+
+        if let Some(peer) = self.peers.get_mut(&partner) {
+            peer.outbox.push(Signal::AddCandidate(ice));
+        }
+    }
+
+    fn prune(&mut self, now: SystemTime) {
+        let expired: Vec<String> = self
+            .peers
+            .iter()
+            .filter(|(_, peer)| {
+                now.duration_since(peer.last_seen).unwrap_or_default() > PEER_TIMEOUT
+            })
+            .map(|(token, _)| token.clone())
+            .collect();
+
+        for token in expired {
+            if let Some(key) = self.peers.remove(&token).and_then(|p| p.room) {
+                self.rooms.remove(&key);
+            }
+        }
+        self.full.retain(|_, host| self.peers.contains_key(host));
+
+        for claim in self.claims.values_mut() {
+            if self.peers.contains_key(&claim.host) {
+                claim.seen = now;
+            }
+        }
+        self.claims
+            .retain(|_, claim| now.duration_since(claim.seen).unwrap_or_default() <= CLAIM_TIMEOUT);
+    }
+}
@@ -0,0 +1,288 @@
+//! Room protocol of the reference signalling server.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use chessagon_servers::RoomServer;
+use serde_json::{json, Value};
+
+fn at(secs: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(1_000 + secs)
+}
+
+fn ident(server: &mut RoomServer) -> String {
+    let (status, body) = server.respond("/ident", None, "", at(0));
+    assert_eq!(status, 200);
+    let body: Value = serde_json::from_str(&body).unwrap();
+    body["token"].as_str().unwrap().to_owned()
+}
+
+fn poll(server: &mut RoomServer, token: &str, signals: Value, now: SystemTime) -> Vec<Value> {
+    let (status, body) = server.respond("/poll", Some(token), &signals.to_string(), now);
+    assert_eq!(status, 200, "{}", body);
+    serde_json::from_str(&body).unwrap()
+}
+
+fn find<'a>(signals: &'a [Value], kind: &str) -> Option<&'a Value> {
+    signals.iter().find_map(|s| s.get(kind))
+}
+
+#[test]
+fn pairs_host_and_guest() {
+    let mut server = RoomServer::new(1);
+    let host = ident(&mut server);
+    let guest = ident(&mut server);
+
+    let resp = poll(
+        &mut server,
+        &host,
+        json!([
+            {"SetSDP": "offer"},
+            {"AddCandidate": ["host-ice", "0", 0]},
+            {"SetService": "chessagon"},
+        ]),
+        at(0),
+    );
+    let code = find(&resp, "JoinRoom")
+        .unwrap()
+        .as_str()
+        .unwrap()
+        .to_owned();
+    assert!(find(&resp, "NextPoll").is_some());
+
+    let resp = poll(&mut server, &guest, json!([{"JoinRoom": code}]), at(1));
+    assert_eq!(find(&resp, "JoinRoom").unwrap(), &json!(code));
+    assert_eq!(find(&resp, "SetSDP").unwrap(), &json!("offer"));
+    assert_eq!(
+        find(&resp, "AddCandidate").unwrap(),
+        &json!(["host-ice", "0", 0])
+    );
+
+    let resp = poll(
+        &mut server,
+        &guest,
+        json!([{"SetSDP": "answer"}, {"AddCandidate": ["guest-ice", "0", 0]}]),
+        at(2),
+    );
+    let guest_at = find(&resp, "ConnectAt").unwrap().clone();
+
+    let resp = poll(&mut server, &host, json!([]), at(2));
+    assert_eq!(find(&resp, "SetSDP").unwrap(), &json!("answer"));
+    assert_eq!(find(&resp, "ConnectAt").unwrap(), &guest_at);
+    assert_eq!(
+        find(&resp, "AddCandidate").unwrap(),
+        &json!(["guest-ice", "0", 0])
+    );
+}
+
+#[test]
+fn rejects_unknown_rooms_and_tokens() {
+    let mut server = RoomServer::new(2);
+    let guest = ident(&mut server);
+
+    let resp = poll(&mut server, &guest, json!([{"JoinRoom": "NOPE"}]), at(0));
+    assert!(find(&resp, "JoinRoom").is_none());
+
+    let (status, _) = server.respond("/poll", Some("bogus"), "[]", at(0));
+    assert_eq!(status, 401);
+    let (status, _) = server.respond("/poll", Some(&guest), "not json", at(0));
+    assert_eq!(status, 400);
+}
+
+#[test]
+fn rehosts_a_code_once_paired() {
+    let mut server = RoomServer::new(3);
+    let host = ident(&mut server);
+    let guest = ident(&mut server);
+
+    let resp = poll(&mut server, &host, json!([{"SetSDP": "offer"}]), at(0));
+    let code = find(&resp, "JoinRoom").unwrap().clone();
+    let secret = find(&resp, "RoomSecret").unwrap().clone();
+    poll(&mut server, &guest, json!([{"JoinRoom": code}]), at(0));
+
+    // The same host opens the code again for someone else
+    let again = ident(&mut server);
+    let claim = json!({"code": code, "secret": secret});
+    let resp = poll(
+        &mut server,
+        &again,
+        json!([{"HostRoom": claim}, {"SetSDP": "offer-2"}]),
+        at(1),
+    );
+    assert_eq!(find(&resp, "JoinRoom").unwrap(), &code);
+
+    let other = ident(&mut server);
+    let resp = poll(&mut server, &other, json!([{"JoinRoom": code}]), at(1));
+    assert_eq!(find(&resp, "SetSDP").unwrap(), &json!("offer-2"));
+}
+
+#[test]
+fn only_the_host_gets_a_room_back() {
+    let mut server = RoomServer::new(8);
+    let host = ident(&mut server);
+    let guest = ident(&mut server);
+
+    let resp = poll(&mut server, &host, json!([{"SetSDP": "offer"}]), at(0));
+    let code = find(&resp, "JoinRoom").unwrap().clone();
+    poll(&mut server, &guest, json!([{"JoinRoom": code}]), at(0));
+
+    let thief = ident(&mut server);
+    for secret in [json!("guessed"), json!("")] {
+        let claim = json!({"code": code, "secret": secret});
+        let resp = poll(
+            &mut server,
+            &thief,
+            json!([{"HostRoom": claim}, {"SetSDP": "offer-2"}]),
+            at(1),
+        );
+        assert_eq!(find(&resp, "RoomFull").unwrap(), &code);
+        assert!(find(&resp, "JoinRoom").is_none());
+    }
+
+    // The game is still theirs
+    let late = ident(&mut server);
+    let resp = poll(&mut server, &late, json!([{"JoinRoom": code}]), at(2));
+    assert_eq!(find(&resp, "RoomFull").unwrap(), &code);
+}
+
+#[test]
+fn spectators_have_a_seat_of_their_own() {
+    let mut server = RoomServer::new(9);
+    let host = ident(&mut server);
+    let guest = ident(&mut server);
+
+    let resp = poll(&mut server, &host, json!([{"SetSDP": "offer"}]), at(0));
+    let code = find(&resp, "JoinRoom").unwrap().clone();
+    let secret = find(&resp, "RoomSecret").unwrap().clone();
+    poll(&mut server, &guest, json!([{"JoinRoom": code}]), at(0));
+
+    let seat = ident(&mut server);
+    let claim = json!({"code": code, "secret": secret});
+    let resp = poll(
+        &mut server,
+        &seat,
+        json!([{"HostSpectators": claim}, {"SetSDP": "watch-offer"}]),
+        at(1),
+    );
+    assert_eq!(find(&resp, "JoinRoom").unwrap(), &code);
+
+    // A guest coming back isn't mistaken for a spectator
+    let back = ident(&mut server);
+    let resp = poll(&mut server, &back, json!([{"JoinRoom": code}]), at(2));
+    assert_eq!(find(&resp, "RoomFull").unwrap(), &code);
+    assert!(find(&resp, "SetSDP").is_none());
+
+    let viewer = ident(&mut server);
+    let resp = poll(&mut server, &viewer, json!([{"WatchRoom": code}]), at(2));
+    assert_eq!(find(&resp, "SetSDP").unwrap(), &json!("watch-offer"));
+
+    // Nor can anyone else open the seat
+    let thief = ident(&mut server);
+    let claim = json!({"code": code, "secret": "guessed"});
+    let resp = poll(
+        &mut server,
+        &thief,
+        json!([{"HostSpectators": claim}, {"SetSDP": "offer-3"}]),
+        at(3),
+    );
+    assert_eq!(find(&resp, "RoomFull").unwrap(), &code);
+}
+
+#[test]
+fn retried_polls_are_only_applied_once() {
+    let mut server = RoomServer::new(10);
+    let host = ident(&mut server);
+    let body = json!([{"SetSDP": "offer"}]).to_string();
+
+    let first = server.respond("/poll?seq=1", Some(&host), &body, at(0));
+    // The answer got lost, so the same poll comes again. It's answered as
+    // before, down to when to poll next.
+    let retry = server.respond("/poll?seq=1", Some(&host), &body, at(1));
+    assert_eq!(first, retry);
+
+    let (_, next) = server.respond("/poll?seq=2", Some(&host), "[]", at(2));
+    let next: Vec<Value> = serde_json::from_str(&next).unwrap();
+    assert!(find(&next, "JoinRoom").is_none());
+}
+
+#[test]
+fn reports_full_rooms() {
+    let mut server = RoomServer::new(6);
+    let host = ident(&mut server);
+    let guest = ident(&mut server);
+    let late = ident(&mut server);
+
+    let resp = poll(&mut server, &host, json!([{"SetSDP": "offer"}]), at(0));
+    let code = find(&resp, "JoinRoom").unwrap().clone();
+    poll(&mut server, &guest, json!([{"JoinRoom": code}]), at(1));
+
+    let resp = poll(&mut server, &late, json!([{"JoinRoom": code}]), at(2));
+    assert!(find(&resp, "JoinRoom").is_none());
+    assert_eq!(find(&resp, "RoomFull").unwrap(), &code);
+}
+
+#[test]
+fn lists_advertised_rooms() {
+    let mut server = RoomServer::new(7);
+    let public = ident(&mut server);
+    let private = ident(&mut server);
+    let guest = ident(&mut server);
+
+    let info = json!({"name": "host", "time": 300, "variant": 0});
+    let resp = poll(
+        &mut server,
+        &public,
+        json!([{"SetSDP": "offer"}, {"Advertise": info}]),
+        at(0),
+    );
+    let code = find(&resp, "JoinRoom").unwrap().clone();
+    poll(&mut server, &private, json!([{"SetSDP": "offer"}]), at(0));
+
+    let resp = poll(&mut server, &guest, json!(["ListRooms"]), at(1));
+    assert_eq!(
+        find(&resp, "Rooms").unwrap(),
+        &json!([{"code": code, "info": info}])
+    );
+
+    // Taken rooms aren't listed anymore
+    poll(&mut server, &guest, json!([{"JoinRoom": code}]), at(2));
+    let resp = poll(&mut server, &private, json!(["ListRooms"]), at(3));
+    assert_eq!(find(&resp, "Rooms").unwrap(), &json!([]));
+}
+
+#[test]
+fn forgets_idle_hosts() {
+    let mut server = RoomServer::new(4);
+    let host = ident(&mut server);
+    let guest = ident(&mut server);
+
+    let resp = poll(&mut server, &host, json!([{"SetSDP": "offer"}]), at(0));
+    let code = find(&resp, "JoinRoom").unwrap().clone();
+
+    // Only the guest keeps polling
+    poll(&mut server, &guest, json!([]), at(50));
+    poll(&mut server, &guest, json!([]), at(100));
+    let resp = poll(&mut server, &guest, json!([{"JoinRoom": code}]), at(101));
+    assert!(find(&resp, "SetSDP").is_none());
+}
+
+#[test]
+fn hands_out_ice_servers_and_a_relay() {
+    let mut server = RoomServer::new(5);
+    let (_, body) = server.respond("/ident", None, "", at(0));
+    let body: Value = serde_json::from_str(&body).unwrap();
+    assert!(body.get("ice_servers").is_none());
+    assert!(body.get("relay").is_none());
+
+    server
+        .set_ice_servers(r#"[{"urls": ["turn:example.org"], "username": "a", "credential": "b", "expires": 60}]"#)
+        .unwrap();
+    let (_, body) = server.respond("/ident", None, "", at(0));
+    let body: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(body["ice_servers"][0]["urls"], json!(["turn:example.org"]));
+    assert_eq!(body["ice_servers"][0]["expires"], json!(60));
+
+    server.set_relay("wss://relay.example.org");
+    let (_, body) = server.respond("/ident", None, "", at(0));
+    let body: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(body["relay"], json!("wss://relay.example.org"));
+}
@@ -36,7 +36,6 @@ export class Board {
 
     this.resize();
 
-    this.timers.onExpired = () => ctx.timerExpired();
     this.main.onClick = (q, r) => ctx.hexClicked(q, r);
     this.promotion.onClick = (q, r) => {
       this.main.nextFullUpdate = true;
@@ -46,6 +46,17 @@ const templates = [
   "new-peer",
   "connected",
   "disconnected",
+  "draw",
+  "reconnecting",
+  "reconnected",
+  "lagging",
+  "stable",
+  "abandoned",
+  "spectator-joined",
+  "spectating",
+  "incompatible",
+  "desynced",
+  "direct",
 ];
 
 /**
@@ -1,7 +1,8 @@
 import { board } from "./state";
 
 export { setScene, setPlayerName } from "./scene";
-export { joinResponse } from "../menus/online.js";
+export { joinResponse, showManualCode } from "../menus/online.js";
+export { lobbyResponse, showRooms } from "../menus/lobby.js";
 export { addChatMessage, showChat, hideChat } from "./chat.js";
 export { addRTT } from "./render.js";
 export { showButtons } from "./buttons.js";
@@ -2,4 +2,23 @@ import "../scss/styles.scss";
 import { onReady } from "./loader";
 import { ctx } from "./state";
 
+// Lets a local signalling server be used, e.g. ?signal=http://localhost:8787
+const params = new URLSearchParams(location.search);
+const signal = params.get("signal");
+if (signal) ctx.setSignalServer(signal);
+
+// WebSocket relay for when WebRTC can't connect, e.g. ?relay=ws://localhost:8788.
+// Without one, the signalling server's relay is used if it offers one.
+const relay = params.get("relay") ?? window.chessagon?.relay;
+if (relay) ctx.setRelayServer(relay);
+
+// Pages embedding the game can bring their own STUN/TURN servers by setting
+// window.chessagon = { iceServers: [...] } before this script runs
+if (window.chessagon?.iceServers) ctx.setIceServers(window.chessagon.iceServers);
+
+// Seconds to keep trying to reach a room before giving up
+if (window.chessagon?.connectTimeout) {
+  ctx.setConnectTimeout(window.chessagon.connectTimeout);
+}
+
 onReady().then(() => ctx.start());
@@ -6,6 +6,7 @@ import { menu as gamemodeMenu } from "../menus/gamemode.js";
 import { menu as registerMenu, setName } from "../menus/register.js";
 import { menu as onlineMenu } from "../menus/online.js";
 import { menu as settingsMenu } from "../menus/settings.js";
+import { menu as lobbyMenu } from "../menus/lobby.js";
 
 const menuEvt = document.getElementById("menu-events");
 const spinner = document.getElementById("loading");
@@ -19,7 +20,15 @@ const CANVAS = -1;
 let currentScene = LOADING;
 
 // Set up modals
-[gamemodeMenu, registerMenu, onlineMenu, settingsMenu].forEach((menu, idx) => {
+// Indexed by scene
+const sceneMenus = [
+  gamemodeMenu,
+  registerMenu,
+  onlineMenu,
+  settingsMenu,
+  lobbyMenu,
+];
+sceneMenus.forEach((menu, idx) => {
   const modal = new Modal(menu);
   menus.push(modal);
 
@@ -86,8 +95,26 @@ menuEvt.addEventListener("chess.gamemode", (evt) => {
 menuEvt.addEventListener("chess.register", (evt) => {
   ctx.register(evt.detail);
 });
-menuEvt.addEventListener("chess.join", (evt) => ctx.joinRoom(evt.detail));
+menuEvt.addEventListener("chess.join", (evt) =>
+  ctx.joinRoom(evt.detail.code, evt.detail.password),
+);
 menuEvt.addEventListener("chess.create", () => ctx.createRoom());
+menuEvt.addEventListener("chess.offer", () => ctx.createOffer());
+menuEvt.addEventListener("chess.code", (evt) => ctx.acceptCode(evt.detail));
+menuEvt.addEventListener("chess.lobby", () => ctx.openLobby());
+menuEvt.addEventListener("chess.quickmatch", (evt) =>
+  ctx.quickMatch(evt.detail),
+);
+menuEvt.addEventListener("chess.public", (evt) =>
+  ctx.createPublicRoom(evt.detail),
+);
 menuEvt.addEventListener("chess.settings", (evt) => {
-  ctx.setSettings(evt.detail.timer, evt.detail.start === "light");
+  // Guests waiting to get in are checked against it
+  ctx.setPassword(evt.detail.password);
+  ctx.setSettings(
+    evt.detail.timer,
+    evt.detail.start === "light",
+    evt.detail.reconnect,
+    evt.detail.pauseClock,
+  );
 });
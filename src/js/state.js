@@ -26,22 +26,26 @@ class JsContext {
     wasm.dispatch_empty(JsEvent.CreateRoom);
   }
 
-  joinRoom(code) {
+  joinRoom(code, password = "") {
+    this.setPassword(password);
     wasm.dispatch(JsEvent.JoinRoom, this.text.encode(code));
   }
 
-  setSettings(time, hostAsLight) {
-    const buf = new Uint8Array(3);
+  setPassword(password) {
+    wasm.dispatch(JsEvent.SetPassword, this.text.encode(password));
+  }
+
+  setSettings(time, hostAsLight, reconnectWindow, pauseClock) {
+    const buf = new Uint8Array(6);
     buf[0] = (time >> 8) & 0xff;
     buf[1] = time & 0xff;
     buf[2] = hostAsLight ? 1 : 0;
+    buf[3] = (reconnectWindow >> 8) & 0xff;
+    buf[4] = reconnectWindow & 0xff;
+    buf[5] = pauseClock ? 1 : 0;
     wasm.dispatch(JsEvent.SetSettings, buf);
   }
 
-  timerExpired() {
-    wasm.dispatch_empty(JsEvent.TimerExpired);
-  }
-
   sendMessage(msg) {
     wasm.dispatch(JsEvent.SendMessage, this.text.encode(msg));
   }
@@ -63,6 +67,47 @@ class JsContext {
   promotionResponse(kind) {
     wasm.dispatch(JsEvent.PromotionResponse, new Uint8Array([kind]));
   }
+
+  openLobby() {
+    wasm.dispatch_empty(JsEvent.OpenLobby);
+  }
+
+  quickMatch(time) {
+    wasm.dispatch(JsEvent.QuickMatch, new Uint8Array([time >> 8, time]));
+  }
+
+  createPublicRoom(time) {
+    wasm.dispatch(JsEvent.CreatePublicRoom, new Uint8Array([time >> 8, time]));
+  }
+
+  createOffer() {
+    wasm.dispatch_empty(JsEvent.CreateOffer);
+  }
+
+  acceptCode(code) {
+    wasm.dispatch(JsEvent.AcceptCode, this.text.encode(code));
+  }
+
+  /**
+   * @param {{urls: string[], username?: string, credential?: string, expires?: number}[]} servers
+   */
+  setIceServers(servers) {
+    const json = JSON.stringify(servers);
+    wasm.dispatch(JsEvent.SetIceServers, this.text.encode(json));
+  }
+
+  setRelayServer(url) {
+    wasm.dispatch(JsEvent.SetRelayServer, this.text.encode(url));
+  }
+
+  setConnectTimeout(secs) {
+    const buf = new Uint8Array([secs >> 8, secs]);
+    wasm.dispatch(JsEvent.SetConnectTimeout, buf);
+  }
+
+  setSignalServer(url) {
+    wasm.dispatch(JsEvent.SetSignalServer, this.text.encode(url));
+  }
 }
 
 export const ctx = new JsContext();
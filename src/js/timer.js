@@ -13,11 +13,6 @@ class Timer {
     this.lastTime = null;
   }
 
-  get expired() {
-    if (this.activeAt === null) return false;
-    return this.left === 0;
-  }
-
   get elapsed() {
     if (this.activeAt === null) return 0;
 
@@ -107,7 +102,6 @@ export class Timers {
     this.dark = new Timer();
     this.hidden = true;
     this.flipped = false;
-    this.sentExpiration = false;
   }
 
   /**
@@ -116,13 +110,10 @@ export class Timers {
    * @param {number} active
    */
   setState(light, dark, active) {
-    this.sentExpiration = false;
     this.light.update(light, active === 0);
     this.dark.update(dark, active === 1);
   }
 
-  onExpired() {}
-
   /**
    * @param {HTMLCanvasElement} ctx
    * @param {number} x
@@ -160,11 +151,5 @@ export class Timers {
       timerHeight,
       force,
     );
-
-    if (this.sentExpiration) return;
-    if (this.light.expired || this.dark.expired) {
-      this.sentExpiration = true;
-      this.onTimerExpired();
-    }
   }
 }
@@ -0,0 +1,106 @@
+const evtTarget = document.getElementById("menu-events");
+export const menu = document.getElementById("menu-lobby");
+
+const timer = menu.querySelector("[data-lobby=timer]");
+const list = menu.querySelector("[data-lobby=rooms]");
+const empty = menu.querySelector("[data-lobby=empty]");
+const template = menu.querySelector("[data-lobby=room]");
+const lobbyErr = menu.querySelector("[data-lobby=error]");
+const publicBtn = menu.querySelector("[data-lobby=public]");
+const quickBtn = menu.querySelector("[data-lobby=quick]");
+let timeout;
+
+const formatTime = (secs) => {
+  if (secs === 0) return "no timer";
+  const min = Math.floor(secs / 60)
+    .toString()
+    .padStart(2, "0");
+  const sec = (secs % 60).toString().padStart(2, "0");
+  return `${min}:${sec}`;
+};
+
+const setBusy = (btn) => {
+  for (const el of menu.querySelectorAll("button.btn")) {
+    el.disabled = true;
+  }
+  lobbyErr.hidden = true;
+  btn.querySelector(".spinner-border")?.removeAttribute("hidden");
+
+  clearTimeout(timeout);
+  timeout = setTimeout(() => lobbyResponse("timeout"), 60000);
+};
+
+menu.addEventListener("show.bs.modal", () => {
+  list.replaceChildren();
+  empty.hidden = false;
+});
+
+publicBtn.addEventListener("click", () => {
+  if (publicBtn.disabled) return;
+  setBusy(publicBtn);
+  const time = parseInt(timer.value, 10) || 0;
+  evtTarget.dispatchEvent(new CustomEvent("chess.public", { detail: time }));
+});
+
+quickBtn.addEventListener("click", () => {
+  if (quickBtn.disabled) return;
+  setBusy(quickBtn);
+  const time = parseInt(timer.value, 10) || 0;
+  evtTarget.dispatchEvent(
+    new CustomEvent("chess.quickmatch", { detail: time }),
+  );
+});
+
+/**
+ * @param {string} json
+ */
+export const showRooms = (json) => {
+  /** @type {{code: string, info: {name: string, time: number, variant: number}}[]} */
+  const rooms = JSON.parse(json);
+  empty.hidden = rooms.length > 0;
+
+  list.replaceChildren(
+    ...rooms.map((room) => {
+      const item = template.content.firstElementChild.cloneNode(true);
+      item.querySelector("[data-room=name]").textContent = room.info.name;
+      item.querySelector("[data-room=time]").textContent = formatTime(
+        room.info.time,
+      );
+
+      const btn = item.querySelector("button");
+      btn.disabled = quickBtn.disabled;
+      btn.addEventListener("click", () => {
+        setBusy(btn);
+        evtTarget.dispatchEvent(
+          new CustomEvent("chess.join", {
+            detail: { code: room.code, password: "" },
+          }),
+        );
+      });
+      return item;
+    }),
+  );
+};
+
+/**
+ * @param {"success" | string} resp
+ */
+export const lobbyResponse = (resp) => {
+  for (const el of menu.querySelectorAll("button.btn")) {
+    el.disabled = false;
+  }
+  for (const spinner of menu.querySelectorAll(".spinner-border")) {
+    spinner.hidden = true;
+  }
+  lobbyErr.hidden = resp === "success";
+
+  clearTimeout(timeout);
+
+  if (resp === "success") return;
+
+  for (const text of lobbyErr.querySelectorAll("[data-lobby-error]")) {
+    text.hidden = text.dataset.lobbyError !== resp;
+  }
+
+  timeout = setTimeout(() => lobbyResponse("success"), 5000);
+};
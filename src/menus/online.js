@@ -4,16 +4,25 @@ export const menu = document.getElementById("menu-online");
 const joinForm = menu.querySelector("[data-online=join]");
 const joinBtn = joinForm.querySelector("button");
 const codeInput = joinForm.querySelector("input");
+const passwordInput = joinForm.querySelector("[data-online=password]");
 const joinSpinner = joinBtn.querySelector(".spinner-border");
 const joinErr = menu.querySelector("[data-online=error]");
 const createBtn = menu.querySelector("[data-online=create]");
 const createSpinner = createBtn.querySelector(".spinner-border");
+const manualForm = menu.querySelector("[data-online=manual]");
+const manualOutput = manualForm.querySelector("[data-manual=output]");
+const manualCode = manualOutput.querySelector("textarea");
+const manualInput = manualForm.querySelector(":scope > textarea");
+const offerBtn = manualForm.querySelector("[data-online=offer]");
+const lobbyBtn = menu.querySelector("[data-online=lobby]");
 let timeout;
 
 const params = new URLSearchParams(location.search);
 
 let readCode = false;
 menu.addEventListener("show.bs.modal", () => {
+  manualOutput.hidden = true;
+  manualInput.value = "";
   if (readCode) return;
   readCode = true;
 
@@ -34,10 +43,10 @@ joinForm.addEventListener("submit", (evt) => {
   joinSpinner.hidden = false;
 
   clearTimeout(timeout);
-  timeout = setTimeout(() => joinResponse("timeout"), 15000);
+  timeout = setTimeout(() => joinResponse("timeout"), 60000);
 
-  const code = codeInput.value;
-  evtTarget.dispatchEvent(new CustomEvent("chess.join", { detail: code }));
+  const detail = { code: codeInput.value, password: passwordInput.value };
+  evtTarget.dispatchEvent(new CustomEvent("chess.join", { detail }));
 });
 
 createBtn.addEventListener("click", (evt) => {
@@ -50,11 +59,41 @@ createBtn.addEventListener("click", (evt) => {
   createSpinner.hidden = false;
 
   clearTimeout(timeout);
-  timeout = setTimeout(() => joinResponse("timeout"), 15000);
+  timeout = setTimeout(() => joinResponse("timeout"), 60000);
 
   evtTarget.dispatchEvent(new Event("chess.create"));
 });
 
+lobbyBtn.addEventListener("click", (evt) => {
+  evt.preventDefault();
+  joinErr.hidden = true;
+  evtTarget.dispatchEvent(new Event("chess.lobby"));
+});
+
+offerBtn.addEventListener("click", (evt) => {
+  evt.preventDefault();
+  joinErr.hidden = true;
+  manualOutput.hidden = true;
+  evtTarget.dispatchEvent(new Event("chess.offer"));
+});
+
+manualForm.addEventListener("submit", (evt) => {
+  evt.preventDefault();
+  joinErr.hidden = true;
+  evtTarget.dispatchEvent(
+    new CustomEvent("chess.code", { detail: manualInput.value }),
+  );
+});
+
+/**
+ * @param {string} code
+ */
+export const showManualCode = (code) => {
+  manualOutput.hidden = false;
+  manualCode.value = code;
+  manualCode.select();
+};
+
 /**
  * @param {"success" | string} resp
  */
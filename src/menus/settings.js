@@ -4,14 +4,32 @@ export const menu = document.getElementById("menu-settings");
 const timer = menu.querySelector("[data-sett=timer]");
 const startGroup = menu.querySelector("[data-sett=start]");
 const start = startGroup.querySelector("select");
+const reconnectGroup = menu.querySelector("[data-sett=reconnect]");
+const reconnect = reconnectGroup.querySelector("select");
+const pauseGroup = menu.querySelector("[data-sett=pause]");
+const pause = pauseGroup.querySelector("select");
+const passwordGroup = menu.querySelector("[data-sett=password]");
+const password = passwordGroup.querySelector("input");
 const nextBtn = menu.querySelector("[data-sett=continue]");
 
 evtTarget.addEventListener("chess.gamemode", (evt) => {
   startGroup.hidden = evt.detail === "local";
+  reconnectGroup.hidden = evt.detail === "local";
+  pauseGroup.hidden = evt.detail === "local";
+  passwordGroup.hidden = evt.detail === "local";
+  password.value = "";
 });
 
+// Public rooms were advertised with a timer, keep it
+const keepTimer = (evt) => {
+  timer.value = evt.detail.toString();
+};
+evtTarget.addEventListener("chess.public", keepTimer);
+evtTarget.addEventListener("chess.quickmatch", keepTimer);
+
 nextBtn.addEventListener("click", () => {
   const time = parseInt(timer.value, 10) || 0;
+  const reconnectWindow = parseInt(reconnect.value, 10) || 0;
   let startColor = start.value;
 
   if (startColor === "random") {
@@ -23,6 +41,9 @@ nextBtn.addEventListener("click", () => {
       detail: {
         timer: time,
         start: startColor,
+        reconnect: reconnectWindow,
+        pauseClock: pause.value === "pause",
+        password: passwordGroup.hidden ? "" : password.value,
       },
     }),
   );
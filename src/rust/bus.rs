@@ -0,0 +1,101 @@
+use std::{
+    cell::{Cell, RefCell, RefMut},
+    rc::{Rc, Weak},
+};
+
+use crate::glue::{Event, EventKind};
+
+pub struct Listener {
+    id: u64,
+    priority: i32,
+    // None to hear everything
+    kinds: Option<Vec<EventKind>>,
+    handler: Box<dyn FnMut(&Event)>,
+}
+
+// Everyone listening to a context. Listeners added or dropped while an
+// event is being handled only take effect from the next event on.
+#[derive(Default)]
+pub struct Listeners {
+    active: RefCell<Vec<Listener>>,
+    added: RefCell<Vec<Listener>>,
+    removed: RefCell<Vec<u64>>,
+    next_id: Cell<u64>,
+}
+
+impl Listeners {
+    pub fn add(
+        self: &Rc<Self>,
+        priority: i32,
+        kinds: Option<Vec<EventKind>>,
+        handler: Box<dyn FnMut(&Event)>,
+    ) -> Subscription {
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+        self.added.borrow_mut().push(Listener {
+            id,
+            priority,
+            kinds,
+            handler,
+        });
+
+        Subscription {
+            id,
+            listeners: Some(Rc::downgrade(self)),
+        }
+    }
+
+    fn remove(&self, id: u64) {
+        self.added.borrow_mut().retain(|l| l.id != id);
+        self.removed.borrow_mut().push(id);
+    }
+
+    // None while an event is being handled
+    pub fn lock(&self) -> Option<RefMut<'_, Vec<Listener>>> {
+        let mut active = self.active.try_borrow_mut().ok()?;
+
+        let removed = std::mem::take(&mut *self.removed.borrow_mut());
+        active.retain(|l| !removed.contains(&l.id));
+
+        let added = std::mem::take(&mut *self.added.borrow_mut());
+        if !added.is_empty() {
+            active.extend(added);
+            // Stable: same priority keeps subscription order
+            active.sort_by_key(|l| std::cmp::Reverse(l.priority));
+        }
+        Some(active)
+    }
+}
+
+pub fn notify(listeners: &mut [Listener], evt: &Event) {
+    let kind = evt.kind();
+    for listener in listeners.iter_mut() {
+        if let Some(kinds) = &listener.kinds {
+            if !kinds.contains(&kind) {
+                continue;
+            }
+        }
+        (listener.handler)(evt);
+    }
+}
+
+// Keeps a listener attached. Dropping it detaches the listener.
+pub struct Subscription {
+    id: u64,
+    listeners: Option<Weak<Listeners>>,
+}
+
+impl Subscription {
+    // Stays attached for as long as the context lives
+    pub fn keep(mut self) {
+        self.listeners = None;
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        if let Some(listeners) = self.listeners.take().and_then(|l| l.upgrade()) {
+            listeners.remove(self.id);
+        }
+    }
+}
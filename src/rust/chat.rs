@@ -1,44 +1,97 @@
-use crate::glue::addChatMessage;
+use std::rc::Rc;
 
-pub struct Chat {}
+use crate::ui::Ui;
+
+pub struct Chat {
+    ui: Rc<dyn Ui>,
+}
 
 impl Chat {
-    pub fn player_message(is_light: bool, name: &str, content: &str) {
-        addChatMessage(
+    pub fn new(ui: Rc<dyn Ui>) -> Self {
+        Chat { ui }
+    }
+
+    pub fn player_message(&self, is_light: bool, name: &str, content: &str) {
+        self.ui.add_chat_message(
             if is_light { 0 } else { 1 },
             vec![name.to_owned(), content.to_owned()],
         );
     }
 
-    pub fn join_room(code: &str) {
-        addChatMessage(2, vec![code.to_owned()]);
+    pub fn join_room(&self, code: &str) {
+        self.ui.add_chat_message(2, vec![code.to_owned()]);
+    }
+
+    pub fn game_start(&self) {
+        self.ui.add_chat_message(3, vec![]);
+    }
+
+    pub fn game_end(&self, won_light: Option<bool>) {
+        let kind = match won_light {
+            Some(true) => 4,
+            Some(false) => 5,
+            None => 13,
+        };
+        self.ui.add_chat_message(kind, vec![]);
+    }
+
+    pub fn timer_expired(&self, is_light: bool) {
+        self.ui
+            .add_chat_message(if is_light { 6 } else { 7 }, vec![]);
+    }
+
+    pub fn resign(&self, is_light: bool) {
+        self.ui
+            .add_chat_message(if is_light { 8 } else { 9 }, vec![]);
+    }
+
+    pub fn new_peer(&self) {
+        self.ui.add_chat_message(10, vec![]);
+    }
+
+    pub fn connected(&self, name: &str) {
+        self.ui.add_chat_message(11, vec![name.to_owned()]);
+    }
+
+    pub fn disconnected(&self) {
+        self.ui.add_chat_message(12, vec![]);
+    }
+
+    pub fn reconnecting(&self) {
+        self.ui.add_chat_message(14, vec![]);
+    }
+
+    pub fn reconnected(&self) {
+        self.ui.add_chat_message(15, vec![]);
     }
 
-    pub fn game_start() {
-        addChatMessage(3, vec![]);
+    pub fn peer_lagging(&self, is_lagging: bool) {
+        self.ui
+            .add_chat_message(if is_lagging { 16 } else { 17 }, vec![]);
     }
 
-    pub fn game_end(won_light: bool) {
-        addChatMessage(if won_light { 4 } else { 5 }, vec![]);
+    pub fn abandoned(&self) {
+        self.ui.add_chat_message(18, vec![]);
     }
 
-    pub fn timer_expired(is_light: bool) {
-        addChatMessage(if is_light { 6 } else { 7 }, vec![]);
+    pub fn spectator_joined(&self, name: &str) {
+        self.ui.add_chat_message(19, vec![name.to_owned()]);
     }
 
-    pub fn resign(is_light: bool) {
-        addChatMessage(if is_light { 8 } else { 9 }, vec![]);
+    pub fn spectating(&self, light_name: &str, dark_name: &str) {
+        self.ui
+            .add_chat_message(20, vec![light_name.to_owned(), dark_name.to_owned()]);
     }
 
-    pub fn new_peer() {
-        addChatMessage(10, vec![]);
+    pub fn incompatible(&self) {
+        self.ui.add_chat_message(21, vec![]);
     }
 
-    pub fn connected(name: &str) {
-        addChatMessage(11, vec![name.to_owned()]);
+    pub fn desynced(&self) {
+        self.ui.add_chat_message(22, vec![]);
     }
 
-    pub fn disconnected() {
-        addChatMessage(12, vec![]);
+    pub fn direct_connection(&self) {
+        self.ui.add_chat_message(23, vec![]);
     }
 }
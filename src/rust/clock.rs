@@ -0,0 +1,108 @@
+use std::cell::{Cell, RefCell};
+
+use wasm_bindgen::prelude::*;
+use web_time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[wasm_bindgen]
+extern "C" {
+    fn setTimeout(handler: &JsValue, millis: u32) -> f64;
+}
+
+// Where the game gets its time from, and how it waits for it
+pub trait Clock {
+    fn now(&self) -> Instant;
+    // Wall time, for what the signalling service sends
+    fn system_now(&self) -> SystemTime;
+    // Calls the handler once `at` comes
+    fn schedule(&self, at: Instant, handler: Box<dyn FnOnce()>);
+}
+
+// Real time, scheduled on the browser's event loop
+pub struct WebClock;
+
+impl Clock for WebClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn system_now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn schedule(&self, at: Instant, handler: Box<dyn FnOnce()>) {
+        let delay = at.saturating_duration_since(Instant::now()).as_millis();
+        let handler = Closure::once_into_js(handler);
+        setTimeout(&handler, delay.try_into().unwrap_or(u32::MAX));
+    }
+}
+
+type Scheduled = (Instant, Box<dyn FnOnce()>);
+
+// Time that only moves when told to, for tests
+pub struct ManualClock {
+    start: Instant,
+    now: Cell<Instant>,
+    pending: RefCell<Vec<Scheduled>>,
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        ManualClock::new()
+    }
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        let start = Instant::now();
+        ManualClock {
+            start,
+            now: Cell::new(start),
+            pending: RefCell::new(vec![]),
+        }
+    }
+
+    // Moves time forward, running everything that comes due in order
+    pub fn advance(&self, by: Duration) {
+        let target = self.now.get() + by;
+        loop {
+            let next = {
+                let mut pending = self.pending.borrow_mut();
+                pending
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, (at, _))| *at <= target)
+                    .min_by_key(|(_, (at, _))| *at)
+                    .map(|(idx, _)| idx)
+                    .map(|idx| pending.remove(idx))
+            };
+
+            let (at, handler) = match next {
+                Some(next) => next,
+                None => break,
+            };
+            self.now.set(self.now.get().max(at));
+            handler();
+        }
+        self.now.set(target);
+    }
+
+    // Calls waiting to come due
+    pub fn pending(&self) -> usize {
+        self.pending.borrow().len()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.now.get()
+    }
+
+    fn system_now(&self) -> SystemTime {
+        // Fixed epoch, so runs are reproducible
+        UNIX_EPOCH + Duration::from_secs(1_700_000_000) + (self.now.get() - self.start)
+    }
+
+    fn schedule(&self, at: Instant, handler: Box<dyn FnOnce()>) {
+        self.pending.borrow_mut().push((at, handler));
+    }
+}
@@ -1,4 +1,4 @@
-use web_time::{Duration, SystemTime};
+use web_time::{Duration, Instant};
 
 use wasm_bindgen::prelude::wasm_bindgen;
 #[wasm_bindgen]
@@ -8,35 +8,42 @@ extern "C" {
 }
 
 use super::{
-    board::Board,
+    board::{Board, BoardError},
     highlight::{Effect, HighlightController},
     piece::{Color, PieceKind},
 };
 use crate::{
-    chat::Chat,
-    glue::{
-        hideChat, movePieces, removeTimers, setBoardPerspective, setPieces, setTimers, showButtons,
-        showChat, showPromotionPrompt, Button, Event,
-    },
-    utils::Gamemode,
+    glue::{Button, Event},
+    network::packet::{SyncState, FEATURE_CLOCKS},
+    utils::{Gamemode, Timeout},
     Context,
 };
 
 struct Side {
     color: Color,
     time_left: Option<Duration>,
-    time_active_at: Option<SystemTime>,
+    time_active_at: Option<Instant>,
 }
 
 impl Side {
-    fn update_timer(&mut self) {
+    fn remaining(&self, now: Instant) -> Option<Duration> {
+        let left = self.time_left?;
+        Some(match self.time_active_at {
+            Some(since) => left.saturating_sub(now - since),
+            None => left,
+        })
+    }
+
+    fn update_timer(&mut self, now: Instant) {
         if let Some(since) = self.time_active_at.take() {
-            let duration = SystemTime::now()
-                .duration_since(since)
-                .expect("time travel");
-            self.time_left = Some(self.time_left.unwrap() - duration);
+            let duration = now - since;
+            self.time_left = Some(self.time_left.unwrap().saturating_sub(duration));
         }
     }
+
+    fn deadline(&self) -> Option<Instant> {
+        Some(self.time_active_at? + self.time_left?)
+    }
 }
 
 pub struct Controller {
@@ -44,13 +51,28 @@ pub struct Controller {
     board: Board,
     is_host: bool,
     is_solo: bool,
+    is_spectator: bool,
     is_connected: bool,
+    // Waiting for the connection to come back, mid-game
+    resuming: bool,
     loaded_board: bool,
     color: Color,
     turn: Option<Color>,
     timer: Option<Duration>,
+    // Peer can run game clocks
+    peer_clocks: bool,
+    reconnect_window: Duration,
+    pause_on_disconnect: bool,
+    forfeit: Option<Timeout>,
     light: Side,
     dark: Side,
+    flag: Option<Timeout>,
+    // Active side's clock was stopped while the peer was away
+    clock_paused: bool,
+    history: Vec<u16>,
+    // Moves taken from the peer and the checksum of the position each was
+    // played from, to spot copies the network delivers again
+    peer_moves: Vec<(u8, (u8, u8), u32)>,
     name: String,
     opp_name: String,
     highlight: HighlightController,
@@ -65,11 +87,17 @@ impl Controller {
             board: Board::new(),
             is_host: false,
             is_solo: false,
+            is_spectator: false,
             is_connected: false,
+            resuming: false,
             loaded_board: false,
             color: Color::Light,
             turn: None,
             timer: None,
+            peer_clocks: true,
+            reconnect_window: Duration::ZERO,
+            pause_on_disconnect: true,
+            forfeit: None,
             light: Side {
                 color: Color::Light,
                 time_left: None,
@@ -80,9 +108,13 @@ impl Controller {
                 time_left: None,
                 time_active_at: None,
             },
+            flag: None,
+            clock_paused: false,
+            history: vec![],
+            peer_moves: vec![],
             name: "".to_owned(),
             opp_name: "".to_owned(),
-            highlight: HighlightController::new(),
+            highlight: HighlightController::new(ctx.ui.clone()),
             selected_hex: None,
             promoting: None,
         }
@@ -119,29 +151,20 @@ impl Controller {
     }
 
     fn check_winner(&mut self) -> Option<Color> {
-        let mut winner = None;
         self.highlight.remove(Effect::Check);
-
-        for color in [Color::Light, Color::Dark].iter() {
-            match self.board.get_king(*color) {
-                Some(king) => {
-                    if self.board.is_threatened(king.q, king.r, *color) {
-                        self.highlight.add(Effect::Check, [(king.q, king.r)].iter());
-                    }
-                }
-                None => {
-                    winner = Some(color.opposite());
-                }
-            };
+        for color in [Color::Light, Color::Dark] {
+            if let Some(king) = self.board.checked_king(color) {
+                self.highlight.add(Effect::Check, [(king.q, king.r)].iter());
+            }
         }
 
         self.highlight.send();
-        winner
+        self.board.winner()
     }
 
     fn send_timers(&self) {
         if self.timer.is_none() {
-            removeTimers();
+            self.ctx.ui().remove_timers();
             return;
         }
 
@@ -158,14 +181,26 @@ impl Controller {
             .map(|s| if s.color.is_light() { 0 } else { 1 })
             .unwrap_or(-1);
 
-        setTimers(
+        self.ctx.ui().set_timers(
             self.light.time_left.unwrap().as_secs().try_into().unwrap(),
             self.dark.time_left.unwrap().as_secs().try_into().unwrap(),
             active,
         );
+
+        if self.is_host && !self.is_solo {
+            // Keep spectators' clocks in sync
+            let millis = |t: Duration| t.as_millis().try_into().unwrap_or(u32::MAX);
+            self.ctx.handle(Event::ClockUpdate {
+                light_ms: millis(self.light.time_left.unwrap()),
+                dark_ms: millis(self.dark.time_left.unwrap()),
+                active,
+                is_local: true,
+            });
+        }
     }
 
     fn switch_turns(&mut self) {
+        let now = self.ctx.now();
         let (active, inactive) = match self.turn.unwrap() {
             Color::Light => (&mut self.light, &mut self.dark),
             Color::Dark => (&mut self.dark, &mut self.light),
@@ -179,37 +214,305 @@ impl Controller {
             return;
         }
 
-        inactive.time_active_at = Some(SystemTime::now());
-        active.update_timer();
+        inactive.time_active_at = Some(now);
+        active.update_timer(now);
+        self.send_timers();
+        self.schedule_flag();
+    }
+
+    fn active_side(&self) -> Option<&Side> {
+        match self.turn {
+            Some(Color::Light) => Some(&self.light),
+            Some(Color::Dark) => Some(&self.dark),
+            None => None,
+        }
+    }
+
+    fn active_side_mut(&mut self) -> Option<&mut Side> {
+        match self.turn {
+            Some(Color::Light) => Some(&mut self.light),
+            Some(Color::Dark) => Some(&mut self.dark),
+            None => None,
+        }
+    }
+
+    fn pause_clock(&mut self) {
+        self.flag = None;
+        let now = self.ctx.now();
+        if let Some(side) = self.active_side_mut() {
+            if side.time_active_at.is_some() {
+                side.update_timer(now);
+                self.clock_paused = true;
+            }
+        }
+        self.send_timers();
+    }
+
+    fn resume_clock(&mut self) {
+        if !std::mem::take(&mut self.clock_paused) {
+            return;
+        }
+
+        let now = self.ctx.now();
+        if let Some(side) = self.active_side_mut() {
+            side.time_active_at = Some(now);
+        }
         self.send_timers();
+        self.schedule_flag();
+    }
+
+    fn snapshot(&self) -> SyncState {
+        let now = self.ctx.now();
+        let millis = |side: &Side| {
+            side.remaining(now)
+                .map(|t| t.as_millis().try_into().unwrap_or(u32::MAX))
+                .unwrap_or(0)
+        };
+
+        SyncState {
+            board: self.board.describe(),
+            passant: self.board.passant,
+            running: self.turn.is_some(),
+            light_turn: self.turn != Some(Color::Dark),
+            light_ms: millis(&self.light),
+            dark_ms: millis(&self.dark),
+            clock_active: self.clock_paused
+                || self
+                    .active_side()
+                    .is_some_and(|s| s.time_active_at.is_some()),
+            history: self.history.clone(),
+        }
+    }
+
+    fn restore(&mut self, state: &SyncState) -> Result<(), BoardError> {
+        self.board.load_desc(&state.board)?;
+        self.board.passant = state.passant;
+        self.ctx.ui().set_pieces(state.board.as_slice());
+
+        self.turn = match (state.running, state.light_turn) {
+            (false, _) => None,
+            (true, true) => Some(Color::Light),
+            (true, false) => Some(Color::Dark),
+        };
+        if self.timer.is_some() {
+            self.light.time_left = Some(Duration::from_millis(state.light_ms.into()));
+            self.dark.time_left = Some(Duration::from_millis(state.dark_ms.into()));
+        }
+        self.light.time_active_at = None;
+        self.dark.time_active_at = None;
+        self.clock_paused = state.clock_active;
+        self.history = state.history.clone();
+        // Positions before the sync may never have been played here
+        self.peer_moves.clear();
+
+        self.highlight.reset();
+        if let Some(last) = self.history.last() {
+            let from = ((last >> 12) as u8, (last >> 8 & 0xf) as u8);
+            let to = ((last >> 4 & 0xf) as u8, (last & 0xf) as u8);
+            self.highlight.add(Effect::Movement, [from, to].iter());
+        }
+        self.check_winner();
+
+        if self.turn == Some(self.color) && self.promoting.is_none() && !self.is_spectator {
+            // We may have dropped while choosing a promotion
+            let pending = self
+                .board
+                .pieces
+                .iter()
+                .find(|p| p.color == self.color && p.can_promote())
+                .map(|p| p.idx);
+            if let Some(idx) = pending {
+                self.ctx.handle(Event::PromotionPrompt(idx));
+            }
+        }
+        Ok(())
+    }
+
+    fn resync(&mut self) {
+        if !self.is_host {
+            self.ctx.handle(Event::RequestSync { is_local: true });
+            return;
+        }
+
+        self.selected_hex = None;
+        let state = self.snapshot();
+        self.ctx.handle(Event::StateSync {
+            state: state.clone(),
+            is_local: true,
+        });
+        self.ctx.handle(Event::SpectatorSync { id: None, state });
+    }
+
+    fn schedule_flag(&mut self) {
+        if self.is_spectator {
+            // Host tells us when the game ends
+            return;
+        }
+
+        let side = match self.active_side() {
+            Some(side) => side,
+            None => return,
+        };
+        let deadline = match side.deadline() {
+            Some(at) => at,
+            None => return,
+        };
+
+        let ctx = self.ctx.clone();
+        let is_light = side.color.is_light();
+        self.flag = Some(self.ctx.timeout_at(deadline, move || {
+            ctx.handle(Event::TimerExpired { is_light });
+        }));
     }
 
     pub fn on_event(&mut self, evt: &Event) {
         match evt {
             Event::Connected(..) => {
                 self.is_connected = true;
+                if self.resuming && self.is_host {
+                    self.resuming = false;
+                    self.forfeit = None;
+                    self.ctx.handle(Event::StateSync {
+                        state: self.snapshot(),
+                        is_local: true,
+                    });
+                    self.resume_clock();
+                    self.ctx.chat().reconnected();
+                }
                 self.try_start();
             }
-            Event::Disconnected => {
-                if self.is_connected {
-                    self.is_connected = false;
-                    self.turn = None;
-                    Chat::disconnected();
-                    hideChat();
+            Event::ConnectionLost if self.is_connected => {
+                if self.turn.is_none() || self.is_spectator {
+                    // Nothing to resume
+                    self.ctx.handle(Event::Disconnected);
+                    return;
                 }
+
+                self.is_connected = false;
+                self.resuming = true;
+                self.selected_hex = None;
+                if self.pause_on_disconnect || self.turn == Some(self.color) {
+                    // We can't move until the peer is back, so only their clock may run
+                    self.pause_clock();
+                }
+
+                let ctx = self.ctx.clone();
+                self.forfeit = Some(self.ctx.timeout_at(
+                    self.ctx.now() + self.reconnect_window,
+                    move || {
+                        ctx.handle(Event::ReconnectTimeout);
+                    },
+                ));
+                self.ctx.chat().reconnecting();
+                self.ctx.handle(Event::Reconnect);
+            }
+            Event::ReconnectTimeout if self.resuming => {
+                self.ctx.chat().abandoned();
+                if !self.is_host {
+                    // Both ends timed out; only the host gets to call the result
+                    self.ctx.handle(Event::Disconnected);
+                    return;
+                }
+                self.ctx.handle(Event::GameEnded {
+                    won_light: Some(self.color.is_light()),
+                });
+            }
+            Event::Spectate {
+                light_name,
+                dark_name,
+                timer,
+            } => {
+                self.is_spectator = true;
+                self.color = Color::Light;
+                self.timer = if *timer > 0 {
+                    Some(Duration::from_secs((*timer).into()))
+                } else {
+                    None
+                };
+                self.ctx.ui().set_board_perspective(true);
+                self.ctx.chat().spectating(light_name, dark_name);
+                self.ctx.ui().hide_chat();
+                self.ctx.ui().show_buttons(&[Button::LeaveRoom.into()]);
+            }
+            Event::SpectatorJoined { id, .. } if self.is_host && self.loaded_board => {
+                self.ctx.handle(Event::SpectatorSync {
+                    id: Some(*id),
+                    state: self.snapshot(),
+                });
+            }
+            Event::ClockUpdate {
+                light_ms,
+                dark_ms,
+                active,
+                is_local: false,
+            } if self.is_spectator => {
+                if self.timer.is_none() {
+                    return;
+                }
+
+                self.light.time_left = Some(Duration::from_millis((*light_ms).into()));
+                self.dark.time_left = Some(Duration::from_millis((*dark_ms).into()));
+                let now = self.ctx.now();
+                self.light.time_active_at = (*active == 0).then_some(now);
+                self.dark.time_active_at = (*active == 1).then_some(now);
+                self.send_timers();
+            }
+            Event::PeerLagging(is_lagging) if self.is_connected => {
+                self.ctx.chat().peer_lagging(*is_lagging);
+            }
+            Event::RequestSync { is_local: false } if self.is_host => {
+                self.resync();
+            }
+            Event::StateSync {
+                state,
+                is_local: false,
+            } => {
+                if self.is_host {
+                    return;
+                }
+
+                if self.restore(state).is_err() {
+                    // Invalid board.
+                    self.ctx.handle(Event::Disconnected);
+                    return;
+                }
+                self.resume_clock();
+                self.forfeit = None;
+                if std::mem::take(&mut self.resuming) {
+                    self.ctx.chat().reconnected();
+                }
+            }
+            Event::Disconnected if self.is_connected || self.resuming => {
+                self.is_connected = false;
+                self.is_spectator = false;
+                self.peer_clocks = true;
+                self.resuming = false;
+                self.forfeit = None;
+                self.clock_paused = false;
+                self.turn = None;
+                self.flag = None;
+                self.ctx.chat().disconnected();
+                self.ctx.ui().hide_chat();
             }
             Event::SetGamemode(mode) => {
-                let mode: Gamemode = (*mode).into();
-                self.is_solo = mode == Gamemode::Solo;
-                hideChat();
+                self.is_solo = Gamemode::try_from(*mode) == Ok(Gamemode::Solo);
+                self.is_spectator = false;
+                self.ctx.ui().hide_chat();
             }
             Event::Register(name) => {
                 self.name = name.clone();
             }
-            Event::Handshake(name) => {
-                Chat::connected(name);
-                showChat();
+            Event::Handshake { name, features } => {
+                self.ctx.chat().connected(name);
+                self.ctx.ui().show_chat();
                 self.opp_name = name.clone();
+                self.peer_clocks = features & FEATURE_CLOCKS != 0;
+                if !self.peer_clocks && self.turn.is_none() {
+                    self.timer = None;
+                }
+            }
+            Event::IncompatiblePeer => {
+                self.ctx.chat().incompatible();
             }
             Event::JoinedRoom { is_host, .. } => {
                 self.is_host = *is_host;
@@ -217,7 +520,11 @@ impl Controller {
             Event::SetSettings {
                 timer,
                 host_as_light,
+                reconnect_window,
+                pause_on_disconnect,
             } => {
+                self.reconnect_window = Duration::from_secs((*reconnect_window).into());
+                self.pause_on_disconnect = *pause_on_disconnect;
                 self.color = if *host_as_light {
                     Color::Light
                 } else {
@@ -226,12 +533,14 @@ impl Controller {
                 if !self.is_solo && !self.is_host {
                     self.color = self.color.opposite();
                 }
-                self.timer = if *timer > 0 {
+                self.timer = if *timer > 0 && (self.is_solo || self.peer_clocks) {
                     Some(Duration::from_secs((*timer).into()))
                 } else {
                     None
                 };
-                setBoardPerspective(self.is_solo || self.color.is_light());
+                self.ctx
+                    .ui()
+                    .set_board_perspective(self.is_solo || self.color.is_light());
 
                 if self.is_host || self.is_solo {
                     self.board.load_default();
@@ -239,17 +548,19 @@ impl Controller {
                 }
             }
             Event::LoadedBoard(board) => {
-                if !self.is_host {
-                    self.board.load_desc(board.clone());
+                if !self.is_host && self.board.load_desc(board).is_err() {
+                    // Invalid board.
+                    self.ctx.handle(Event::Disconnected);
+                    return;
                 }
-                setPieces(board.as_slice());
+                self.ctx.ui().set_pieces(board.as_slice());
                 self.highlight.reset();
                 self.loaded_board = true;
                 self.try_start();
             }
             Event::HexClicked { q, r } => {
                 self.highlight.remove(Effect::Light);
-                if self.promoting.is_some() {
+                if self.promoting.is_some() || self.resuming || self.is_spectator {
                     return;
                 }
                 let turn = match self.turn {
@@ -274,6 +585,7 @@ impl Controller {
                         self.ctx.handle(Event::Movement {
                             piece: piece.idx,
                             to: (*q, *r),
+                            checksum: self.board.checksum(self.color),
                             is_local: true,
                         });
                     }
@@ -294,8 +606,21 @@ impl Controller {
             Event::Movement {
                 piece: idx,
                 to,
+                checksum,
                 is_local,
             } => {
+                if !is_local && self.peer_moves.contains(&(*idx, *to, *checksum)) {
+                    // Only a position that comes round again can take the same move twice
+                    let repeated = self.turn.is_some_and(|turn| {
+                        (turn != self.color || self.is_spectator)
+                            && *checksum == self.board.checksum(turn)
+                    });
+                    if !repeated {
+                        // Already played it
+                        return;
+                    }
+                }
+
                 let turn = match self.turn {
                     Some(color) => color,
                     None => {
@@ -305,12 +630,19 @@ impl Controller {
                         return;
                     }
                 };
-                if !is_local && turn == self.color {
+                if !is_local && turn == self.color && !self.is_spectator {
                     // Not peer's turn.
                     self.ctx.handle(Event::Disconnected);
                     return;
                 }
 
+                if !is_local && !self.is_spectator && *checksum != self.board.checksum(turn) {
+                    // Boards diverged, the host's one wins
+                    self.ctx.chat().desynced();
+                    self.resync();
+                    return;
+                }
+
                 let piece = match self.board.get_piece(*idx) {
                     Some(p) if p.color == turn => p,
                     _ => {
@@ -330,11 +662,27 @@ impl Controller {
                 self.highlight
                     .add(Effect::Movement, [(piece.q, piece.r), *to].iter());
                 self.highlight.send();
-                movePieces(self.board.move_piece((piece.q, piece.r), *to).as_slice());
+                self.history.push(
+                    u16::from(piece.q) << 12
+                        | u16::from(piece.r) << 8
+                        | u16::from(to.0) << 4
+                        | u16::from(to.1),
+                );
+                if !is_local {
+                    self.peer_moves.push((*idx, *to, self.board.checksum(turn)));
+                }
+                self.ctx
+                    .ui()
+                    .move_pieces(self.board.move_piece((piece.q, piece.r), *to).as_slice());
+                self.ctx.handle(Event::MovePlayed {
+                    piece: *idx,
+                    to: *to,
+                    checksum: *checksum,
+                });
 
                 if let Some(winner) = self.check_winner() {
                     self.ctx.handle(Event::GameEnded {
-                        won_light: winner.is_light(),
+                        won_light: Some(winner.is_light()),
                     });
                     return;
                 }
@@ -362,21 +710,25 @@ impl Controller {
                         return;
                     }
                 };
-                if !is_local && turn == self.color {
+                if !is_local && turn == self.color && !self.is_spectator {
                     // Not peer's turn.
                     self.ctx.handle(Event::Disconnected);
                     return;
                 }
 
-                let kind: PieceKind = (*kind).into();
-                if !matches!(
-                    kind,
-                    PieceKind::Queen | PieceKind::Knight | PieceKind::Rook | PieceKind::Bishop
-                ) {
-                    // Invalid promotion.
-                    self.ctx.handle(Event::Disconnected);
-                    return;
-                }
+                let kind = match PieceKind::try_from(*kind) {
+                    Ok(
+                        kind @ (PieceKind::Queen
+                        | PieceKind::Knight
+                        | PieceKind::Rook
+                        | PieceKind::Bishop),
+                    ) => kind,
+                    _ => {
+                        // Invalid promotion.
+                        self.ctx.handle(Event::Disconnected);
+                        return;
+                    }
+                };
 
                 let piece = match self.board.get_piece_mut(*piece) {
                     Some(p) if p.color == turn => p,
@@ -392,21 +744,39 @@ impl Controller {
                     self.ctx.handle(Event::Disconnected);
                     return;
                 }
-                piece.promote(kind);
+                let idx = piece.idx;
+                self.ctx.ui().promote_pieces(&[piece.promote(kind)]);
+                self.ctx.handle(Event::PiecePromoted {
+                    piece: idx,
+                    kind: kind as u8,
+                });
                 self.switch_turns();
             }
-            Event::TimerExpired => {
-                if let Some(loser) = self.turn {
-                    let loser = loser.is_light();
-                    Chat::timer_expired(loser);
-                    self.ctx.handle(Event::GameEnded { won_light: !loser });
+            Event::TimerExpired { .. } if self.resuming && !self.is_host => {
+                // The host's clocks win once we're back, let it decide
+            }
+            Event::TimerExpired { is_light } => {
+                let side = if *is_light { &self.light } else { &self.dark };
+                if self.turn != Some(side.color) || side.time_active_at.is_none() {
+                    // Clock isn't running for this side anymore.
+                    return;
                 }
+
+                self.ctx.chat().timer_expired(*is_light);
+                let winner = side.color.opposite();
+                let won_light = if self.board.has_mating_material(winner) {
+                    Some(winner.is_light())
+                } else {
+                    // Opponent can't deliver mate: timeout is a draw.
+                    None
+                };
+                self.ctx.handle(Event::GameEnded { won_light });
             }
             Event::Resign(local) => {
                 let is_light = self.get_color(*local).is_light();
-                Chat::resign(is_light);
+                self.ctx.chat().resign(is_light);
                 self.ctx.handle(Event::GameEnded {
-                    won_light: !is_light,
+                    won_light: Some(!is_light),
                 });
             }
             Event::ChatMessage { is_local, content } => {
@@ -416,37 +786,64 @@ impl Controller {
                 } else {
                     &self.opp_name
                 };
-                Chat::player_message(color.is_light(), name, content);
+                self.ctx
+                    .chat()
+                    .player_message(color.is_light(), name, content);
             }
             Event::GameStart => {
-                removeTimers();
+                self.ctx.ui().remove_timers();
+                self.flag = None;
+                self.clock_paused = false;
+                self.history.clear();
+                self.peer_moves.clear();
                 self.light.time_left = self.timer;
+                self.light.time_active_at = None;
                 self.dark.time_left = self.timer;
+                self.dark.time_active_at = None;
                 self.send_timers();
                 self.promoting = None;
                 self.turn = Some(Color::Light);
 
-                Chat::game_start();
+                self.ctx.chat().game_start();
+                if self.is_host {
+                    self.ctx.handle(Event::SpectatorSync {
+                        id: None,
+                        state: self.snapshot(),
+                    });
+                }
                 if !self.is_solo {
-                    showButtons(&[Button::LeaveRoom.into(), Button::Resign.into()]);
+                    self.ctx
+                        .ui()
+                        .show_buttons(&[Button::LeaveRoom.into(), Button::Resign.into()]);
                 } else {
                     self.color = Color::Light;
-                    showButtons(&[Button::LeaveRoom.into()]);
+                    self.ctx.ui().show_buttons(&[Button::LeaveRoom.into()]);
                 }
             }
+            Event::GameEnded { .. } if self.is_spectator && self.turn.is_none() => {
+                // Already saw the game end locally
+            }
             Event::GameEnded { won_light } => {
                 self.turn = None;
                 self.promoting = None;
+                self.flag = None;
 
-                self.light.update_timer();
-                self.dark.update_timer();
+                let now = self.ctx.now();
+                self.light.update_timer(now);
+                self.dark.update_timer(now);
                 self.send_timers();
 
-                Chat::game_end(*won_light);
+                self.ctx.chat().game_end(*won_light);
+                if self.resuming {
+                    // No game left to resume
+                    self.ctx.handle(Event::Disconnected);
+                }
                 if self.is_solo || self.is_host {
-                    showButtons(&[Button::LeaveRoom.into(), Button::PlayAgain.into()]);
+                    self.ctx
+                        .ui()
+                        .show_buttons(&[Button::LeaveRoom.into(), Button::PlayAgain.into()]);
                 } else {
-                    showButtons(&[Button::LeaveRoom.into()]);
+                    self.ctx.ui().show_buttons(&[Button::LeaveRoom.into()]);
                 }
             }
             Event::GameButtonClick(btn) => match btn {
@@ -456,6 +853,7 @@ impl Controller {
                 Button::LeaveRoom => {
                     self.turn = None;
                     self.promoting = None;
+                    self.flag = None;
                 }
                 _ => {}
             },
@@ -466,7 +864,9 @@ impl Controller {
                 };
 
                 self.promoting = Some(*idx);
-                showPromotionPrompt(piece.color as u8, piece.q, piece.r);
+                self.ctx
+                    .ui()
+                    .show_promotion_prompt(piece.color as u8, piece.q, piece.r);
             }
             Event::PromotionResponse(kind) => {
                 let piece = match self.promoting.take().and_then(|i| self.board.get_piece(i)) {
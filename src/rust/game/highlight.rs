@@ -1,4 +1,6 @@
-use crate::glue::highlight;
+use std::rc::Rc;
+
+use crate::ui::Ui;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 #[repr(u8)]
@@ -8,39 +10,39 @@ pub enum Effect {
     Movement = 2,
 }
 
-pub struct HighlightController([[u8; 11]; 11]);
-
-impl Default for HighlightController {
-    fn default() -> Self {
-        HighlightController::new()
-    }
+pub struct HighlightController {
+    hexes: [[u8; 11]; 11],
+    ui: Rc<dyn Ui>,
 }
 
 impl HighlightController {
-    pub fn new() -> Self {
-        HighlightController(Default::default())
+    pub fn new(ui: Rc<dyn Ui>) -> Self {
+        HighlightController {
+            hexes: Default::default(),
+            ui,
+        }
     }
 
     pub fn send(&self) {
         let mut packet = vec![];
         for q in 0..11 {
             for r in 0..11 {
-                if self.0[q][r] == 0 {
+                if self.hexes[q][r] == 0 {
                     continue;
                 }
 
-                let effects = self.0[q][r] as u16;
+                let effects = self.hexes[q][r] as u16;
                 let q = q as u16;
                 let r = r as u16;
                 packet.push(effects << 8 | q << 4 | r);
             }
         }
-        highlight(packet.as_slice());
+        self.ui.highlight(packet.as_slice());
     }
 
     pub fn reset(&mut self) {
-        self.0 = Default::default();
-        highlight(&[]);
+        self.hexes = Default::default();
+        self.ui.highlight(&[]);
     }
 
     pub fn add<'a, I>(&mut self, effect: Effect, hexes: I)
@@ -49,7 +51,7 @@ impl HighlightController {
     {
         let effect: u8 = 1 << (effect as u8);
         for (q, r) in hexes {
-            self.0[*q as usize][*r as usize] |= effect;
+            self.hexes[*q as usize][*r as usize] |= effect;
         }
     }
 
@@ -57,7 +59,7 @@ impl HighlightController {
         let mask: u8 = !(1 << (effect as u8));
         for q in 0..11 {
             for r in 0..11 {
-                self.0[q][r] &= mask;
+                self.hexes[q][r] &= mask;
             }
         }
     }
@@ -1,7 +1,6 @@
-mod board;
 mod controller;
-mod directions;
 mod highlight;
-mod piece;
+
+use chessagon_core::{board, piece};
 
 pub use controller::Controller;
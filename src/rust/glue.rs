@@ -1,4 +1,15 @@
-use crate::network::{buffer::Buffer, p2p::Connection, packet::ChessPacket};
+use std::fmt::Display;
+
+use chessagon_core::notation::on_board;
+
+use crate::interface::Scene;
+use crate::network::{
+    buffer::Buffer,
+    packet::{ChessPacket, SyncState},
+    transport::Connection,
+    NetError, PublicRoom,
+};
+use crate::utils::Gamemode;
 use wasm_bindgen::prelude::*;
 
 #[wasm_bindgen(module = "/src/rust/glue.js")]
@@ -8,6 +19,9 @@ extern "C" {
     pub fn hideChat();
     pub fn setPlayerName(is_self: bool, name: String);
     pub fn joinResponse(resp: String);
+    pub fn showManualCode(code: String);
+    pub fn showRooms(rooms: String);
+    pub fn lobbyResponse(resp: String);
     pub fn addChatMessage(kind: u8, slots: Vec<String>);
     pub fn setPieces(pieces: &[u16]);
     pub fn movePieces(pieces: &[u16]);
@@ -22,19 +36,22 @@ extern "C" {
 }
 
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "trace", derive(serde::Serialize, serde::Deserialize))]
 pub enum Button {
     Resign,
     PlayAgain,
     LeaveRoom,
 }
 
-impl From<u8> for Button {
-    fn from(value: u8) -> Self {
+impl TryFrom<u8> for Button {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, u8> {
         match value {
-            0 => Self::Resign,
-            1 => Self::PlayAgain,
-            2 => Self::LeaveRoom,
-            _ => panic!("invalid button"),
+            0 => Ok(Self::Resign),
+            1 => Ok(Self::PlayAgain),
+            2 => Ok(Self::LeaveRoom),
+            _ => Err(value),
         }
     }
 }
@@ -50,7 +67,7 @@ impl From<Button> for u8 {
 }
 
 #[wasm_bindgen]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum JsEvent {
     Start,
     SetGamemode,
@@ -61,22 +78,49 @@ pub enum JsEvent {
     SendMessage,
     MenuHidden,
     HexClicked,
-    TimerExpired,
     GameButtonClick,
     PromotionResponse,
+    SetSignalServer,
+    CreateOffer,
+    AcceptCode,
+    SetIceServers,
+    SetRelayServer,
+    SetConnectTimeout,
+    OpenLobby,
+    QuickMatch,
+    CreatePublicRoom,
+    SetPassword,
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "trace", derive(serde::Serialize, serde::Deserialize))]
 pub enum Event {
     Start,
     SetGamemode(u8),
     Register(String),
-    Handshake(String),
+    SetSignalServer(String),
+    // JSON list of ICE servers to try before the default ones
+    SetIceServers(String),
+    // WebSocket relay to fall back to when WebRTC can't connect
+    SetRelayServer(String),
+    // Seconds a connection attempt may take
+    SetConnectTimeout(u16),
+    Handshake {
+        name: String,
+        // Features both peers support
+        features: u8,
+    },
+    // Peer speaks no protocol version we understand
+    IncompatiblePeer,
     CreateRoom,
     JoinRoom(String),
+    // Room password; empty for open rooms
+    SetPassword(String),
     SetSettings {
         timer: u16,
         host_as_light: bool,
+        reconnect_window: u16,
+        pause_on_disconnect: bool,
     },
     ChatMessage {
         is_local: bool,
@@ -88,25 +132,100 @@ pub enum Event {
         r: u8,
     },
     JoinedRoom {
-        code: String,
+        // None if connected without a room
+        code: Option<String>,
+        is_host: bool,
+        // Host of a new room: lets it claim the room back. Kept out of traces.
+        #[cfg_attr(feature = "trace", serde(skip))]
+        secret: Option<String>,
+    },
+    // Serverless signalling: host creates an offer code, guest pastes it,
+    // and the host pastes back the guest's answer
+    CreateOffer,
+    AcceptCode(String),
+    // Code to hand to the other peer
+    ManualCode(String),
+    InvalidCode,
+    // Public rooms: browse them, join any that fits, or host one.
+    // Time controls are in seconds.
+    OpenLobby,
+    QuickMatch(u16),
+    CreatePublicRoom(u16),
+    RoomList(Vec<PublicRoom>),
+    NetError(NetError),
+    // Peer reached without any signalling, e.g. in the same process
+    Linked {
+        #[cfg_attr(feature = "trace", serde(skip, default = "Connection::detached"))]
+        conn: Connection,
         is_host: bool,
     },
-    NetError(JsValue),
-    Connected(Connection),
+    // Transport is up, but the peer isn't authenticated yet
+    TransportOpened(
+        #[cfg_attr(feature = "trace", serde(skip, default = "Connection::detached"))] Connection,
+    ),
+    Connected(
+        #[cfg_attr(feature = "trace", serde(skip, default = "Connection::detached"))] Connection,
+    ),
     Disconnected,
+    // Connection dropped unexpectedly; the game may still be resumed
+    ConnectionLost,
+    Reconnect,
+    // Peer didn't come back within the reconnection window
+    ReconnectTimeout,
+    PeerLagging(bool),
+    SpectatorJoined {
+        id: u32,
+        #[cfg_attr(feature = "trace", serde(skip, default = "Connection::detached"))]
+        conn: Connection,
+    },
+    SpectatorLeft(u32),
+    // Snapshot for a single spectator, or all of them
+    SpectatorSync {
+        id: Option<u32>,
+        state: SyncState,
+    },
+    // Host placed us as a spectator
+    Spectate {
+        light_name: String,
+        dark_name: String,
+        timer: u16,
+    },
+    ClockUpdate {
+        light_ms: u32,
+        dark_ms: u32,
+        active: i8,
+        is_local: bool,
+    },
+    StateSync {
+        state: SyncState,
+        is_local: bool,
+    },
     LoadedBoard(Vec<u16>),
     Movement {
         piece: u8,
         to: (u8, u8),
+        // Board checksum before the move
+        checksum: u32,
         is_local: bool,
     },
-    TimerExpired,
+    // Boards diverged, guest asks the host for its state
+    RequestSync {
+        is_local: bool,
+    },
+    TimerExpired {
+        is_light: bool,
+    },
     GameStart,
     GameEnded {
-        won_light: bool,
+        // None if the game ended in a draw
+        won_light: Option<bool>,
     },
     PingRequest,
-    PacketReceived(ChessPacket),
+    PacketReceived {
+        packet: ChessPacket,
+        // Set if sent by one of our spectators
+        spectator: Option<u32>,
+    },
     Resign(bool),
     GameButtonClick(Button),
     PromotionPrompt(u8),
@@ -116,33 +235,177 @@ pub enum Event {
         kind: u8,
         is_local: bool,
     },
+    // Moves the board took, once checked. Spectators only ever see these.
+    MovePlayed {
+        piece: u8,
+        to: (u8, u8),
+        checksum: u32,
+    },
+    PiecePromoted {
+        piece: u8,
+        kind: u8,
+    },
+}
+
+// Defines a payload-free twin of every event, to filter them by
+macro_rules! event_kinds {
+    ($($kind: ident),* $(,)?) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum EventKind {
+            $($kind),*
+        }
+
+        impl Event {
+            pub fn kind(&self) -> EventKind {
+                match self {
+                    $(Event::$kind { .. } => EventKind::$kind),*
+                }
+            }
+        }
+    };
+}
+
+event_kinds!(
+    Start,
+    SetGamemode,
+    Register,
+    SetSignalServer,
+    SetIceServers,
+    SetRelayServer,
+    SetConnectTimeout,
+    Handshake,
+    IncompatiblePeer,
+    CreateRoom,
+    JoinRoom,
+    SetPassword,
+    SetSettings,
+    ChatMessage,
+    MenuHidden,
+    HexClicked,
+    JoinedRoom,
+    CreateOffer,
+    AcceptCode,
+    ManualCode,
+    InvalidCode,
+    OpenLobby,
+    QuickMatch,
+    CreatePublicRoom,
+    RoomList,
+    NetError,
+    Linked,
+    TransportOpened,
+    Connected,
+    Disconnected,
+    ConnectionLost,
+    Reconnect,
+    ReconnectTimeout,
+    PeerLagging,
+    SpectatorJoined,
+    SpectatorLeft,
+    SpectatorSync,
+    Spectate,
+    ClockUpdate,
+    StateSync,
+    LoadedBoard,
+    Movement,
+    RequestSync,
+    TimerExpired,
+    GameStart,
+    GameEnded,
+    PingRequest,
+    PacketReceived,
+    Resign,
+    GameButtonClick,
+    PromotionPrompt,
+    PromotionResponse,
+    Promotion,
+    MovePlayed,
+    PiecePromoted,
+);
+
+// Why the data sent along with a JS event couldn't be read
+#[derive(Debug, Clone, PartialEq)]
+pub enum EventError {
+    // Ran out of data before this field
+    Missing { evt: JsEvent, field: &'static str },
+    // Read fine, but isn't a value the field can take
+    OutOfRange { evt: JsEvent, field: &'static str },
+}
+
+impl Display for EventError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Missing { evt, field } => write!(f, "{:?} is missing its {}", evt, field),
+            Self::OutOfRange { evt, field } => write!(f, "{:?} has an invalid {}", evt, field),
+        }
+    }
 }
 
 impl Event {
-    pub fn from_js(evt: JsEvent, data: &[u8]) -> Self {
+    pub fn from_js(evt: JsEvent, data: &[u8]) -> Result<Self, EventError> {
         let mut buf = Buffer::from_slice(data);
-        match evt {
+        let missing = |field| EventError::Missing { evt, field };
+        let invalid = |field| EventError::OutOfRange { evt, field };
+        macro_rules! read {
+            ($method: ident, $field: literal) => {
+                buf.$method().ok_or_else(|| missing($field))?
+            };
+        }
+
+        Ok(match evt {
             JsEvent::Start => Self::Start,
-            JsEvent::SetGamemode => Self::SetGamemode(buf.read_u8().unwrap()),
-            JsEvent::Register => Self::Register(buf.read_js_string().unwrap()),
+            JsEvent::SetGamemode => {
+                let mode = read!(read_u8, "gamemode");
+                Gamemode::try_from(mode).map_err(|_| invalid("gamemode"))?;
+                Self::SetGamemode(mode)
+            }
+            JsEvent::Register => Self::Register(read!(read_js_string, "name")),
             JsEvent::CreateRoom => Self::CreateRoom,
-            JsEvent::JoinRoom => Self::JoinRoom(buf.read_js_string().unwrap()),
+            JsEvent::JoinRoom => Self::JoinRoom(read!(read_js_string, "room code")),
             JsEvent::SetSettings => Self::SetSettings {
-                timer: buf.read_u16().unwrap(),
-                host_as_light: buf.read_bool().unwrap(),
+                timer: read!(read_u16, "timer"),
+                host_as_light: read!(read_bool, "host side"),
+                reconnect_window: read!(read_u16, "reconnect window"),
+                pause_on_disconnect: read!(read_bool, "pause flag"),
             },
             JsEvent::SendMessage => Self::ChatMessage {
                 is_local: true,
-                content: buf.read_js_string().unwrap(),
-            },
-            JsEvent::MenuHidden => Self::MenuHidden(buf.read_u8().unwrap()),
-            JsEvent::HexClicked => Self::HexClicked {
-                q: buf.read_u8().unwrap(),
-                r: buf.read_u8().unwrap(),
+                content: read!(read_js_string, "message"),
             },
-            JsEvent::TimerExpired => Self::TimerExpired,
-            JsEvent::GameButtonClick => Self::GameButtonClick(buf.read_u8().unwrap().into()),
-            JsEvent::PromotionResponse => Self::PromotionResponse(buf.read_u8().unwrap()),
-        }
+            JsEvent::MenuHidden => {
+                let menu = read!(read_u8, "menu");
+                Scene::try_from(menu as i8).map_err(|_| invalid("menu"))?;
+                Self::MenuHidden(menu)
+            }
+            JsEvent::HexClicked => {
+                let (q, r) = (read!(read_u8, "q"), read!(read_u8, "r"));
+                if !on_board(q, r) {
+                    return Err(invalid("hex"));
+                }
+                Self::HexClicked { q, r }
+            }
+            JsEvent::GameButtonClick => {
+                let button = read!(read_u8, "button");
+                Self::GameButtonClick(Button::try_from(button).map_err(|_| invalid("button"))?)
+            }
+            JsEvent::PromotionResponse => {
+                let kind = read!(read_u8, "piece kind");
+                // Queen to knight
+                if !(1..=4).contains(&kind) {
+                    return Err(invalid("piece kind"));
+                }
+                Self::PromotionResponse(kind)
+            }
+            JsEvent::SetSignalServer => Self::SetSignalServer(read!(read_js_string, "url")),
+            JsEvent::CreateOffer => Self::CreateOffer,
+            JsEvent::AcceptCode => Self::AcceptCode(read!(read_js_string, "code")),
+            JsEvent::SetIceServers => Self::SetIceServers(read!(read_js_string, "servers")),
+            JsEvent::SetRelayServer => Self::SetRelayServer(read!(read_js_string, "url")),
+            JsEvent::SetConnectTimeout => Self::SetConnectTimeout(read!(read_u16, "timeout")),
+            JsEvent::OpenLobby => Self::OpenLobby,
+            JsEvent::QuickMatch => Self::QuickMatch(read!(read_u16, "timer")),
+            JsEvent::CreatePublicRoom => Self::CreatePublicRoom(read!(read_u16, "timer")),
+            JsEvent::SetPassword => Self::SetPassword(read!(read_js_string, "password")),
+        })
     }
 }
@@ -1,11 +1,12 @@
 mod names;
 
+#[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
 
 use crate::{
-    chat::Chat,
-    glue::{joinResponse, setPlayerName, setScene, showButtons, Button, Event},
-    utils::{new_rng, Gamemode},
+    glue::{Button, Event},
+    network::NetError,
+    utils::Gamemode,
     Context,
 };
 use names::new_name;
@@ -18,18 +19,22 @@ pub enum Scene {
     Register,
     Online,
     Settings,
+    Lobby,
 }
 
-impl From<i8> for Scene {
-    fn from(value: i8) -> Self {
+impl TryFrom<i8> for Scene {
+    type Error = i8;
+
+    fn try_from(value: i8) -> Result<Self, i8> {
         match value {
-            -2 => Scene::Loading,
-            -1 => Scene::Canvas,
-            0 => Scene::Gamemode,
-            1 => Scene::Register,
-            2 => Scene::Online,
-            3 => Scene::Settings,
-            _ => panic!("invalid scene"),
+            -2 => Ok(Scene::Loading),
+            -1 => Ok(Scene::Canvas),
+            0 => Ok(Scene::Gamemode),
+            1 => Ok(Scene::Register),
+            2 => Ok(Scene::Online),
+            3 => Ok(Scene::Settings),
+            4 => Ok(Scene::Lobby),
+            _ => Err(value),
         }
     }
 }
@@ -43,16 +48,24 @@ impl From<Scene> for i8 {
             Scene::Register => 1,
             Scene::Online => 2,
             Scene::Settings => 3,
+            Scene::Lobby => 4,
         }
     }
 }
 
+#[cfg(target_arch = "wasm32")]
 #[wasm_bindgen]
 extern "C" {
     #[wasm_bindgen(js_namespace = console)]
     fn error(s: &str);
 }
 
+// No console outside the browser
+#[cfg(not(target_arch = "wasm32"))]
+fn error(s: &str) {
+    eprintln!("{}", s);
+}
+
 pub struct InterfacesManager {
     gamemode: Gamemode,
     scene: Scene,
@@ -65,16 +78,16 @@ impl InterfacesManager {
         InterfacesManager {
             gamemode: Gamemode::Solo,
             scene: Scene::Gamemode,
-            name: new_name(&mut new_rng()),
+            name: new_name(&mut ctx.new_rng()),
             ctx: ctx.clone(),
         }
     }
 
     fn set_scene(&mut self, scene: Scene) {
         self.scene = scene;
-        setScene(self.scene.into());
+        self.ctx.ui().set_scene(self.scene.into());
         if scene != Scene::Canvas {
-            showButtons(&[]);
+            self.ctx.ui().show_buttons(&[]);
         }
     }
 
@@ -85,6 +98,7 @@ impl InterfacesManager {
                 Scene::Gamemode
             }
             Scene::Online => Scene::Register,
+            Scene::Lobby => Scene::Online,
             Scene::Settings => {
                 if self.gamemode == Gamemode::Solo {
                     Scene::Gamemode
@@ -92,18 +106,33 @@ impl InterfacesManager {
                     Scene::Online
                 }
             }
-            _ => panic!("wasn't supposed to close this menu"),
+            scene => {
+                error(&format!("no menu to close in {:?}", scene));
+                return;
+            }
         });
     }
 
+    // Connection results go to whichever menu started the attempt
+    fn respond(&self, resp: &str) {
+        if self.scene == Scene::Lobby {
+            self.ctx.ui().lobby_response(resp.to_owned());
+        } else {
+            self.ctx.ui().join_response(resp.to_owned());
+        }
+    }
+
     pub fn on_event(&mut self, evt: &Event) {
         match evt {
             Event::Start => {
-                setPlayerName(true, self.name.clone());
+                self.ctx.ui().set_player_name(true, self.name.clone());
                 self.set_scene(self.scene);
             }
             Event::SetGamemode(mode) => {
-                self.gamemode = (*mode).into();
+                self.gamemode = match Gamemode::try_from(*mode) {
+                    Ok(mode) => mode,
+                    Err(_) => return,
+                };
                 if self.gamemode == Gamemode::Solo {
                     self.set_scene(Scene::Settings);
                 } else {
@@ -115,7 +144,7 @@ impl InterfacesManager {
             }
             Event::Register(name) => {
                 self.name = name.to_string();
-                setPlayerName(true, name.to_string());
+                self.ctx.ui().set_player_name(true, name.to_string());
                 self.set_scene(Scene::Online);
             }
             Event::SetSettings { .. } => {
@@ -134,17 +163,44 @@ impl InterfacesManager {
                 }
                 _ => {}
             },
-            Event::JoinedRoom { code, is_host } => {
-                Chat::join_room(code);
-                joinResponse("success".to_owned());
+            Event::JoinedRoom { code, is_host, .. } => {
+                match code {
+                    Some(code) => self.ctx.chat().join_room(code),
+                    None => self.ctx.chat().direct_connection(),
+                }
+                self.respond("success");
                 if !is_host {
                     self.set_scene(Scene::Canvas);
                 } else {
                     self.set_scene(Scene::Settings);
                 }
             }
-            Event::NetError(_) => {
-                joinResponse("not-found".to_owned());
+            Event::ManualCode(code) => {
+                self.ctx.ui().show_manual_code(code.clone());
+            }
+            Event::InvalidCode => {
+                self.respond("invalid-code");
+            }
+            Event::OpenLobby => {
+                self.set_scene(Scene::Lobby);
+            }
+            Event::RoomList(rooms) if self.scene == Scene::Lobby => {
+                self.ctx
+                    .ui()
+                    .show_rooms(serde_json::to_string(rooms).unwrap());
+            }
+            // Cancelled on purpose, nothing to report
+            Event::NetError(NetError::Killed) => {}
+            Event::NetError(err @ (NetError::WrongPassword | NetError::InvalidState(_))) => {
+                // Guests are already looking at the board when they're turned down
+                if self.scene == Scene::Canvas {
+                    self.set_scene(Scene::Online);
+                }
+                self.respond(err.reason());
+            }
+            Event::NetError(err) => {
+                error(&err.to_string());
+                self.respond(err.reason());
             }
             _ => {}
         };
@@ -1,51 +1,146 @@
+mod bus;
 mod chat;
+pub mod clock;
 mod game;
 mod glue;
 mod interface;
 mod network;
+mod position;
+pub mod rng;
+#[cfg(feature = "trace")]
+pub mod trace;
+pub mod ui;
 mod utils;
 
 use std::{cell::RefCell, collections::VecDeque, rc::Rc};
 
+use bus::Listeners;
+use chat::Chat;
+use clock::{Clock, WebClock};
 use game::Controller;
-use glue::{Event, JsEvent};
 use interface::InterfacesManager;
 use network::Client;
+use rand::rngs::SmallRng;
+use rng::{RngSource, TimeSeeded};
+use ui::{Ui, WebUi};
+use utils::Timeout;
 use wasm_bindgen::prelude::*;
+use web_time::Instant;
+
+pub use bus::Subscription;
+pub use glue::{Button, Event, EventError, EventKind, JsEvent};
+#[cfg(feature = "testing")]
+pub use network::{loopback, simulator};
+pub use network::{signalling, transport::Connection, NetError};
+pub use position::JsPosition;
+
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_namespace = console)]
+    fn error(s: &str);
+}
+
+// No console outside the browser
+#[cfg(not(target_arch = "wasm32"))]
+fn error(s: &str) {
+    eprintln!("{}", s);
+}
 
 macro_rules! attach {
     ($ctx: expr, $obj: expr) => {{
         let mut handler = $obj;
-        $ctx.add_listener(Box::new(move |evt| {
+        $ctx.subscribe(0, move |evt| {
             handler.on_event(evt);
-        }));
+        })
+        .keep();
     };};
 }
 
-type Handler = Box<dyn FnMut(&Event)>;
-
 #[wasm_bindgen]
 #[derive(Clone)]
 pub struct Context {
-    handlers: Rc<RefCell<Vec<Handler>>>,
+    listeners: Rc<Listeners>,
     queue: Rc<RefCell<VecDeque<Event>>>,
+    ui: Rc<dyn Ui>,
+    clock: Rc<dyn Clock>,
+    rng: Rc<dyn RngSource>,
+    #[cfg(feature = "trace")]
+    tracer: Rc<RefCell<trace::Tracer>>,
 }
 
 impl Default for Context {
     fn default() -> Self {
-        Context::new()
+        Context::new(Rc::new(WebUi), Rc::new(WebClock), Rc::new(TimeSeeded))
     }
 }
 
-#[wasm_bindgen]
 impl Context {
-    fn new() -> Self {
+    fn new(ui: Rc<dyn Ui>, clock: Rc<dyn Clock>, rng: Rc<dyn RngSource>) -> Self {
         Context {
-            handlers: Rc::new(RefCell::new(vec![])),
+            listeners: Rc::default(),
             queue: Rc::new(RefCell::new(VecDeque::new())),
+            ui,
+            #[cfg(feature = "trace")]
+            tracer: Rc::new(RefCell::new(trace::Tracer::new(clock.now()))),
+            clock,
+            rng,
         }
     }
 
+    fn now(&self) -> Instant {
+        self.clock.now()
+    }
+
+    fn timeout_at<F>(&self, at: Instant, handler: F) -> Timeout
+    where
+        F: FnOnce() + 'static,
+    {
+        utils::timeout_at(self.clock.as_ref(), at, handler)
+    }
+
+    fn new_rng(&self) -> SmallRng {
+        self.rng.new_rng()
+    }
+
+    fn secret(&self) -> [u8; 16] {
+        self.rng.secret()
+    }
+
+    fn ui(&self) -> &dyn Ui {
+        self.ui.as_ref()
+    }
+
+    fn chat(&self) -> Chat {
+        Chat::new(self.ui.clone())
+    }
+
+    // Hears every event until the subscription is dropped. Higher
+    // priorities hear them first; the game itself listens at 0.
+    pub fn subscribe<F>(&self, priority: i32, handler: F) -> Subscription
+    where
+        F: FnMut(&Event) + 'static,
+    {
+        self.listeners.add(priority, None, Box::new(handler))
+    }
+
+    // Same, but only for events of the given kinds
+    pub fn subscribe_to<F>(&self, kinds: &[EventKind], priority: i32, handler: F) -> Subscription
+    where
+        F: FnMut(&Event) + 'static,
+    {
+        self.listeners
+            .add(priority, Some(kinds.to_vec()), Box::new(handler))
+    }
+
+    // Plays over an already open connection, skipping signalling
+    pub fn link(&self, conn: Connection, is_host: bool) {
+        self.handle(Event::Linked { conn, is_host });
+    }
+}
+
+#[wasm_bindgen]
+impl Context {
     #[wasm_bindgen]
     pub fn dispatch_empty(&self, evt: JsEvent) {
         self.dispatch(evt, &[]);
@@ -53,18 +148,33 @@ impl Context {
 
     #[wasm_bindgen]
     pub fn dispatch(&self, evt: JsEvent, data: &[u8]) {
-        self.handle(Event::from_js(evt, data));
+        // A bad event from the page isn't worth losing the game over
+        match Event::from_js(evt, data) {
+            Ok(evt) => self.handle(evt),
+            Err(e) => error(&format!("dropped event: {}", e)),
+        }
     }
 
     fn handle(&self, evt: Event) {
-        match self.handlers.try_borrow_mut() {
-            Ok(mut h) => {
+        self.run(evt, true);
+    }
+
+    // Queued events aren't external: another event raised them
+    #[cfg_attr(not(feature = "trace"), allow(unused_variables))]
+    fn run(&self, evt: Event, external: bool) {
+        match self.listeners.lock() {
+            Some(mut listeners) => {
                 // Called outside event handler
-                for handler in h.iter_mut() {
-                    handler(&evt);
-                }
+                #[cfg(feature = "trace")]
+                self.tracer.borrow_mut().record(
+                    self.now(),
+                    &evt,
+                    self.queue.borrow().len(),
+                    external,
+                );
+                bus::notify(&mut listeners, &evt);
             }
-            Err(_) => {
+            None => {
                 // Called inside event handler
                 // Queue event
                 self.queue.borrow_mut().push_back(evt);
@@ -75,18 +185,29 @@ impl Context {
         // Resolve event queue
         let opt = self.queue.borrow_mut().pop_front();
         if let Some(evt) = opt {
-            self.handle(evt);
+            self.run(evt, false);
         }
     }
+}
 
-    fn add_listener(&self, handler: Handler) {
-        self.handlers.borrow_mut().push(handler);
+#[cfg(feature = "trace")]
+#[wasm_bindgen]
+impl Context {
+    // Everything dispatched so far, as JSON to attach to a bug report
+    #[wasm_bindgen]
+    pub fn trace_json(&self) -> String {
+        serde_json::to_string(self.tracer.borrow().entries()).unwrap()
     }
 }
 
 #[wasm_bindgen]
 pub fn setup() -> Context {
-    let ctx = Context::new();
+    setup_with(Rc::new(WebUi), Rc::new(WebClock), Rc::new(TimeSeeded))
+}
+
+// Same game, with whichever frontend, time and randomness are given
+pub fn setup_with(ui: Rc<dyn Ui>, clock: Rc<dyn Clock>, rng: Rc<dyn RngSource>) -> Context {
+    let ctx = Context::new(ui, clock, rng);
     attach!(ctx, InterfacesManager::new(&ctx));
     attach!(ctx, Client::new(&ctx));
     attach!(ctx, Controller::new(&ctx));
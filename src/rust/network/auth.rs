@@ -0,0 +1,10 @@
+use chessagon_core::auth::nonce;
+
+use crate::Context;
+
+pub use chessagon_core::auth::{guest_proof, host_proof};
+
+// Random challenge, so proofs can't be replayed
+pub fn new_nonce(ctx: &Context) -> String {
+    nonce(ctx.secret())
+}
@@ -1,35 +1,136 @@
+use std::{cell::Cell, rc::Rc};
+
 use futures::SinkExt;
 use futures_channel::mpsc::UnboundedSender;
 use rand::rngs::SmallRng;
 use rand::RngCore;
+#[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
 use wasm_bindgen_futures::spawn_local;
-use web_time::Instant;
+use web_time::{Duration, Instant};
+
+use chessagon_core::{
+    board::Board,
+    notation::on_board,
+    piece::{Color, PieceKind},
+};
 
-use super::connector::Connector;
-use super::p2p::Connection;
+use super::auth::{guest_proof, host_proof, new_nonce};
+use super::buffer::Buffer;
+use super::connector::{Connector, NetConfig, Seat};
+use super::error::NetError;
+use super::lobby::Lobby;
+use super::manual::ManualCode;
 use super::packet::{
-    ChatMessage, ChessPacket, Handshake, Movement, Ping, Promote, Resign, SetBoard, SetSettings,
-    Start,
+    AuthChallenge, AuthProof, AuthResult, ChatMessage, ChessPacket, Clocks, GameOver, Handshake,
+    Movement, ParseError, Ping, Promote, RelayChat, RequestSync, Resign, SetBoard, SetSettings,
+    Spectate, Start, SyncState,
 };
-use crate::chat::Chat;
-use crate::glue::{addRTT, setPlayerName, Button, Event};
+use super::signal::{HttpBackend, RoomClaim, RoomInfo};
+use super::transport::Connection;
+use crate::glue::{Button, Event};
 use crate::interface::Scene;
-use crate::utils::new_rng;
+use crate::utils::Timeout;
 use crate::Context;
 
+#[cfg(target_arch = "wasm32")]
 #[wasm_bindgen]
 extern "C" {
-    fn setInterval(closure: &Closure<dyn FnMut()>, millis: u32) -> f64;
-
     #[wasm_bindgen(js_namespace = console)]
     fn error(s: &str);
 }
 
+// No console outside the browser
+#[cfg(not(target_arch = "wasm32"))]
+fn error(s: &str) {
+    eprintln!("{}", s);
+}
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(3);
+const PING_INTERVAL: Duration = Duration::from_secs(5);
+// Unanswered pings before considering the connection lost
+const MAX_MISSED_PINGS: u8 = 3;
+// Time a rejected peer gets to read why before the connection goes away
+const CLOSE_DELAY: Duration = Duration::from_secs(1);
+// Longest game timer and reconnect window a host may ask for, in seconds
+const MAX_TIMER: u16 = 60 * 60;
+const MAX_RECONNECT_WINDOW: u16 = 10 * 60;
+
 async fn send(mut channel: UnboundedSender<()>) {
     let _ = channel.send(()).await;
 }
 
+// Whether a board from the peer is one we can play on
+fn valid_board(desc: &[u16]) -> bool {
+    match Board::new().load_desc(desc) {
+        Ok(()) => true,
+        Err(e) => {
+            error(&format!("invalid board: {}", e));
+            false
+        }
+    }
+}
+
+// Whether a game from the host is one we can pick up from
+fn check_state(state: &SyncState) -> Result<(), NetError> {
+    let invalid = |e: &str| Err(NetError::InvalidState(e.to_owned()));
+    let mut board = Board::new();
+    if let Err(e) = board.load_desc(&state.board) {
+        return invalid(&e.to_string());
+    }
+
+    if let Some((idx, q, r)) = state.passant {
+        // The pawn that just moved skipped this hex, heading for the side to move
+        let (moved, behind) = if state.light_turn {
+            (Color::Dark, r.checked_add(1))
+        } else {
+            (Color::Light, r.checked_sub(1))
+        };
+        let pawn = board.get_piece(idx).filter(|p| {
+            p.kind == PieceKind::Pawn && p.color == moved && p.q == q && Some(p.r) == behind
+        });
+        if !on_board(q, r) || pawn.is_none() {
+            return invalid("no pawn to take en passant");
+        }
+    }
+
+    let max_ms = u32::from(MAX_TIMER) * 1000;
+    if state.light_ms > max_ms || state.dark_ms > max_ms {
+        return invalid("clock over the longest timer");
+    }
+
+    // Moves are packed as from and to hexes, a nibble per coordinate
+    let off_board = |hex: u16| !on_board((hex >> 4 & 0xf) as u8, (hex & 0xf) as u8);
+    if state
+        .history
+        .iter()
+        .any(|&m| off_board(m >> 8) || off_board(m))
+    {
+        return invalid("move off the board in history");
+    }
+    Ok(())
+}
+
+// Hands a packet from the peer over to the context
+fn receive(ctx: &Context, data: Buffer) {
+    let packet = match ChessPacket::read(data) {
+        Ok(p) => p,
+        Err(e) => {
+            error(&e.to_string());
+            if let ParseError::VersionMismatch(..) = e {
+                ctx.handle(Event::IncompatiblePeer);
+            }
+            ctx.handle(Event::Disconnected);
+            return;
+        }
+    };
+
+    ctx.handle(Event::PacketReceived {
+        packet,
+        spectator: None,
+    });
+}
+
 struct PingRequest {
     id: u16,
     sent_at: Instant,
@@ -38,12 +139,65 @@ struct PingRequest {
 pub struct Client {
     ctx: Context,
     conn: Option<Connection>,
+    // Transport that's open but not authenticated yet
+    pending: Option<Connection>,
+    // Room password. The host only checks it once set in the settings.
+    password: String,
+    password_ready: bool,
+    // Challenge sent to the pending peer
+    nonce: Option<String>,
+    // Host: the guest's answer, and the challenge it sent back
+    proof: Option<(String, String)>,
     name: String,
     is_host: bool,
+    // Protocol version agreed on in the handshake. Game packets wait in the
+    // queue until there is one.
+    version: Option<u8>,
     queue: Vec<ChessPacket>,
     ping: Option<PingRequest>,
+    missed_pings: u8,
+    // Next ping, every few seconds
+    pinger: Option<Timeout>,
     rng: SmallRng,
     killer: Option<UnboundedSender<()>>,
+    // Incremented whenever the running connector is killed, to ignore its errors
+    attempt: Rc<Cell<u32>>,
+    room: Option<String>,
+    // Host: proves the room is ours when resuming. Signalling servers that
+    // don't hand one out can't resume games.
+    room_secret: Option<String>,
+    resuming: bool,
+    retry: Option<Timeout>,
+    opp_name: String,
+    timer: u16,
+    host_as_light: bool,
+    // Host: peers watching the game, with the version they agreed on once
+    // they've sent their handshake, and the connector waiting for the next one
+    spectators: Vec<(u32, Connection, Option<u8>)>,
+    // Spectators that haven't answered their challenge yet
+    pending_spectators: Vec<(u32, Connection, String)>,
+    next_spectator: u32,
+    spectator_killer: Option<UnboundedSender<()>>,
+    // Guest: host placed us as a spectator
+    is_spectator: bool,
+    names: (String, String),
+    net: NetConfig,
+    // Host of a serverless game: where the pasted answer goes
+    answers: Option<UnboundedSender<ManualCode>>,
+    // Keeps the public room list fresh while browsing. Dropping it stops it.
+    lobby: Option<UnboundedSender<()>>,
+    // Time control we're quick matching for, until the room list comes in
+    quick_match: Option<u16>,
+}
+
+// How a connector finds its peer
+#[derive(Clone, Copy, PartialEq)]
+enum ConnKind {
+    Room,
+    Resume,
+    Manual,
+    // Joining a public room; hosts one instead if it's gone
+    QuickMatch(u16),
 }
 
 impl Client {
@@ -51,80 +205,388 @@ impl Client {
         Client {
             ctx: ctx.clone(),
             conn: None,
+            pending: None,
+            password: "".to_owned(),
+            password_ready: false,
+            nonce: None,
+            proof: None,
             name: "unknown".to_owned(),
             is_host: false,
+            version: None,
             queue: vec![],
             ping: None,
-            rng: new_rng(),
+            missed_pings: 0,
+            pinger: None,
+            rng: ctx.new_rng(),
             killer: None,
+            attempt: Rc::new(Cell::new(0)),
+            room: None,
+            room_secret: None,
+            resuming: false,
+            retry: None,
+            opp_name: "unknown".to_owned(),
+            timer: 0,
+            host_as_light: true,
+            spectators: vec![],
+            pending_spectators: vec![],
+            next_spectator: 0,
+            spectator_killer: None,
+            is_spectator: false,
+            names: ("".to_owned(), "".to_owned()),
+            net: NetConfig {
+                clock: ctx.clock.clone(),
+                ..Default::default()
+            },
+            answers: None,
+            lobby: None,
+            quick_match: None,
+        }
+    }
+
+    fn relay(&self, packet: ChessPacket) {
+        if self.spectators.is_empty() {
+            return;
+        }
+
+        for (_, conn, version) in self.spectators.iter() {
+            if let Some(version) = version {
+                conn.send(packet.write_as(*version));
+            }
+        }
+    }
+
+    fn spectate_packet(&self) -> ChessPacket {
+        let (light_name, dark_name) = if self.host_as_light {
+            (self.name.clone(), self.opp_name.clone())
+        } else {
+            (self.opp_name.clone(), self.name.clone())
+        };
+
+        ChessPacket::Spectate(Spectate {
+            light_name,
+            dark_name,
+            timer: self.timer,
+        })
+    }
+
+    // What it takes to host our room again
+    fn claim(&self) -> Option<RoomClaim> {
+        Some(RoomClaim {
+            code: self.room.clone()?,
+            secret: self.room_secret.clone()?,
+        })
+    }
+
+    fn listen_spectators(&mut self) {
+        let claim = match self.claim() {
+            Some(claim) if self.is_host => claim,
+            _ => return,
+        };
+        self.stop_spectators();
+
+        let id = self.next_spectator;
+        self.next_spectator = self.next_spectator.wrapping_add(1);
+
+        let mut net = Connector::new(self.net.clone());
+        net.set_seat(Seat::Spectator);
+        let ctx = self.ctx.clone();
+        net.set_onopen(Box::new(move |conn| {
+            ctx.handle(Event::SpectatorJoined {
+                id,
+                conn: conn.clone(),
+            });
+        }));
+
+        let ctx = self.ctx.clone();
+        net.set_onmessage(Box::new(move |_, data| match ChessPacket::read(data) {
+            Ok(packet) => ctx.handle(Event::PacketReceived {
+                packet,
+                spectator: Some(id),
+            }),
+            Err(e) => {
+                error(&e.to_string());
+                ctx.handle(Event::SpectatorLeft(id));
+            }
+        }));
+
+        let ctx = self.ctx.clone();
+        net.set_onclose(Box::new(move || {
+            ctx.handle(Event::SpectatorLeft(id));
+        }));
+
+        net.set_onerror(Box::new(move |err| {
+            error(&format!("spectator connector: {}", err));
+        }));
+
+        self.spectator_killer = Some(net.start_as_host(Some(claim)));
+    }
+
+    fn stop_spectators(&mut self) {
+        if let Some(channel) = self.spectator_killer.take() {
+            spawn_local(send(channel));
         }
     }
 
+    fn handle_spectator_packet(&mut self, id: u32, packet: &ChessPacket) {
+        if let Some(idx) = self.pending_spectators.iter().position(|(s, ..)| *s == id) {
+            let (_, conn, nonce) = self.pending_spectators.remove(idx);
+            let result = match packet {
+                ChessPacket::AuthProof(p) if p.proof == guest_proof(&self.password, &nonce) => {
+                    AuthResult {
+                        accepted: true,
+                        proof: host_proof(&self.password, &p.nonce),
+                    }
+                }
+                _ => AuthResult {
+                    accepted: false,
+                    proof: String::new(),
+                },
+            };
+
+            let accepted = result.accepted;
+            conn.send(ChessPacket::AuthResult(result).write());
+            if !accepted {
+                error("spectator gave a wrong room password");
+                self.close_later(conn);
+                return;
+            }
+
+            self.spectators.push((id, conn, None));
+            return;
+        }
+
+        let idx = match self.spectators.iter().position(|(s, ..)| *s == id) {
+            Some(idx) => idx,
+            None => return,
+        };
+
+        match packet {
+            ChessPacket::Handshake(p) => {
+                let version = match p.negotiate() {
+                    Some((version, _)) => version,
+                    None => {
+                        error("incompatible spectator");
+                        let (_, conn, _) = self.spectators.remove(idx);
+                        conn.close();
+                        return;
+                    }
+                };
+
+                let spectate = self.spectate_packet().write_as(version);
+                let (_, conn, agreed) = &mut self.spectators[idx];
+                *agreed = Some(version);
+                conn.send(spectate);
+                self.ctx.chat().spectator_joined(&p.name);
+            }
+            ChessPacket::Ping(p) => {
+                if let Some(id) = p.request {
+                    self.spectators[idx].1.send(
+                        ChessPacket::Ping(Ping {
+                            request: None,
+                            reply_to: Some(id),
+                        })
+                        .write(),
+                    );
+                }
+            }
+            _ => {
+                // Spectators are read-only
+                error("spectator sent a game packet");
+                let (_, conn, _) = self.spectators.remove(idx);
+                conn.close();
+            }
+        };
+    }
+
+    fn close_later(&self, conn: Connection) {
+        self.ctx
+            .clock
+            .schedule(self.ctx.now() + CLOSE_DELAY, Box::new(move || conn.close()));
+    }
+
+    fn schedule_ping(&mut self) {
+        let ctx = self.ctx.clone();
+        self.pinger = Some(
+            self.ctx
+                .timeout_at(self.ctx.now() + PING_INTERVAL, move || {
+                    ctx.handle(Event::PingRequest);
+                }),
+        );
+    }
+
+    // Game packets go out in the version agreed on, so they wait for the handshake
     fn send_when_ready(&mut self, packet: ChessPacket) {
-        match &self.conn {
-            Some(c) => c.send(packet.write()),
-            None => self.queue.push(packet),
+        match (&self.conn, self.version) {
+            (Some(c), Some(version)) => c.send(packet.write_as(version)),
+            _ => self.queue.push(packet),
         };
     }
 
+    // Same, but only while there's a peer to send to
+    fn send_to_peer(&mut self, packet: ChessPacket) {
+        if self.conn.is_some() {
+            self.send_when_ready(packet);
+        }
+    }
+
     fn kill(&mut self) {
+        self.answers = None;
         if let Some(channel) = self.killer.take() {
+            self.attempt.set(self.attempt.get().wrapping_add(1));
             spawn_local(send(channel));
         }
     }
 
-    fn new_conn(&self, is_host: bool) -> Connector {
-        let mut net = Connector::new();
+    fn start_lobby(&mut self) {
+        let ctx = self.ctx.clone();
+        let onrooms = Box::new(move |rooms| ctx.handle(Event::RoomList(rooms)));
+        let ctx = self.ctx.clone();
+        let onerror = Box::new(move |err| ctx.handle(Event::NetError(err)));
+        self.lobby = Some(Lobby::start(self.net.clone(), onrooms, onerror));
+    }
+
+    fn room_info(&self, time: u16) -> RoomInfo {
+        RoomInfo {
+            name: self.name.clone(),
+            time,
+            variant: 0,
+        }
+    }
+
+    fn host_public(&mut self, time: u16) {
+        self.is_host = true;
+        self.kill();
+        let mut net = self.new_conn(true, ConnKind::Room);
+        net.set_advertise(self.room_info(time));
+        self.killer = Some(net.start_as_host(None));
+    }
+
+    fn reconnect(&mut self) {
+        let code = match &self.room {
+            Some(code) => code.clone(),
+            None => return,
+        };
+        let claim = self.claim();
+        if self.is_host && claim.is_none() {
+            // Nothing to win the room back with: let the window run out
+            error("signalling server didn't let us keep the room");
+            return;
+        }
 
+        self.kill();
+        let net = self.new_conn(self.is_host, ConnKind::Resume);
+        let channel = if self.is_host {
+            net.start_as_host(claim)
+        } else {
+            net.start_as_guest(code)
+        };
+        self.killer = Some(channel);
+    }
+
+    fn new_conn(&self, is_host: bool, kind: ConnKind) -> Connector {
+        let mut net = Connector::new(self.net.clone());
+
+        let chat = self.ctx.chat();
         net.set_onestablishing(Box::new(move || {
-            Chat::new_peer();
+            chat.new_peer();
         }));
 
-        let name = self.name.clone();
         let ctx = self.ctx.clone();
         net.set_onopen(Box::new(move |conn| {
-            ctx.handle(Event::Connected(conn.clone()));
-            conn.send(ChessPacket::Handshake(Handshake { name: name.clone() }).write());
+            if kind == ConnKind::Manual {
+                // There's no room to join, codes were swapped by hand
+                ctx.handle(Event::JoinedRoom {
+                    code: None,
+                    is_host,
+                    secret: None,
+                });
+            }
+            ctx.handle(Event::TransportOpened(conn.clone()));
         }));
 
         let ctx = self.ctx.clone();
-        net.set_onroom(Box::new(move |code| {
-            ctx.handle(Event::JoinedRoom { code, is_host });
+        net.set_onroom(Box::new(move |code, secret| {
+            if matches!(kind, ConnKind::Room | ConnKind::QuickMatch(_)) {
+                ctx.handle(Event::JoinedRoom {
+                    code: Some(code),
+                    is_host,
+                    secret,
+                });
+            }
+        }));
+
+        let ctx = self.ctx.clone();
+        net.set_oncode(Box::new(move |code| {
+            ctx.handle(Event::ManualCode(code));
         }));
 
         let ctx = self.ctx.clone();
+        let attempt = self.attempt.clone();
+        let current = attempt.get();
         net.set_onerror(Box::new(move |err| {
-            ctx.handle(Event::NetError(err));
+            if attempt.get() != current {
+                return;
+            }
+
+            match (kind, err) {
+                // Someone else got there first, wait for others instead
+                (ConnKind::QuickMatch(time), NetError::RoomFull | NetError::RoomNotFound) => {
+                    ctx.handle(Event::CreatePublicRoom(time));
+                }
+                (_, err) => ctx.handle(Event::NetError(err)),
+            }
         }));
 
         let ctx = self.ctx.clone();
         net.set_onclose(Box::new(move || {
-            ctx.handle(Event::Disconnected);
+            ctx.handle(Event::ConnectionLost);
         }));
 
         let ctx = self.ctx.clone();
-        net.set_onmessage(Box::new(move |_, data| {
-            let packet = match ChessPacket::read(data) {
-                Ok(p) => p,
-                Err(e) => {
-                    error(&e.to_string());
-                    ctx.handle(Event::Disconnected);
-                    return;
-                }
-            };
-
-            ctx.handle(Event::PacketReceived(packet));
-        }));
+        net.set_onmessage(Box::new(move |_, data| receive(&ctx, data)));
 
         net
     }
 
-    pub fn handle_packet(&mut self, packet: &ChessPacket) {
-        let conn = self.conn.as_ref().unwrap();
+    pub fn handle_packet(&mut self, packet: &ChessPacket, spectator: Option<u32>) {
+        if let Some(id) = spectator {
+            self.handle_spectator_packet(id, packet);
+            return;
+        }
+
+        let conn = match &self.conn {
+            Some(conn) => conn,
+            None => {
+                self.handle_auth_packet(packet);
+                return;
+            }
+        };
         match packet {
             ChessPacket::Handshake(p) => {
-                setPlayerName(false, p.name.clone());
-                self.ctx.handle(Event::Handshake(p.name.clone()));
+                let (version, features) = match p.negotiate() {
+                    Some(agreed) => agreed,
+                    None => {
+                        error(&format!(
+                            "incompatible peer: speaks versions {} to {}",
+                            p.min_version, p.max_version
+                        ));
+                        self.ctx.handle(Event::IncompatiblePeer);
+                        self.ctx.handle(Event::Disconnected);
+                        return;
+                    }
+                };
+
+                self.version = Some(version);
+                for packet in self.queue.drain(0..) {
+                    conn.send(packet.write_as(version));
+                }
+
+                self.opp_name = p.name.clone();
+                self.ctx.ui().set_player_name(false, p.name.clone());
+                self.ctx.handle(Event::Handshake {
+                    name: p.name.clone(),
+                    features,
+                });
             }
             ChessPacket::Start(_) => {
                 if self.is_host {
@@ -145,6 +607,7 @@ impl Client {
                 self.ctx.handle(Event::Movement {
                     piece: p.idx,
                     to: (p.q, p.r),
+                    checksum: p.checksum,
                     is_local: false,
                 });
             }
@@ -166,9 +629,13 @@ impl Client {
                     // Ping response
                     if let Some(req) = &self.ping {
                         if req.id == id {
-                            let ping = Instant::now() - req.sent_at;
+                            let ping = self.ctx.now() - req.sent_at;
                             self.ping.take();
-                            addRTT(ping.as_millis().try_into().unwrap());
+                            self.ctx.ui().add_rtt(ping.as_millis().try_into().unwrap());
+
+                            if std::mem::take(&mut self.missed_pings) > 0 {
+                                self.ctx.handle(Event::PeerLagging(false));
+                            }
                         }
                     }
                 }
@@ -179,6 +646,10 @@ impl Client {
                     self.ctx.handle(Event::Disconnected);
                     return;
                 }
+                if !valid_board(&p.board) {
+                    self.ctx.handle(Event::Disconnected);
+                    return;
+                }
 
                 self.ctx.handle(Event::LoadedBoard(p.board.clone()));
             }
@@ -188,10 +659,17 @@ impl Client {
                     self.ctx.handle(Event::Disconnected);
                     return;
                 }
+                if p.timer > MAX_TIMER || p.reconnect_window > MAX_RECONNECT_WINDOW {
+                    error("invalid settings");
+                    self.ctx.handle(Event::Disconnected);
+                    return;
+                }
 
                 self.ctx.handle(Event::SetSettings {
                     timer: p.timer,
                     host_as_light: p.host_as_light,
+                    reconnect_window: p.reconnect_window,
+                    pause_on_disconnect: p.pause_on_disconnect,
                 });
             }
             ChessPacket::Promote(p) => {
@@ -201,88 +679,460 @@ impl Client {
                     is_local: false,
                 });
             }
+            ChessPacket::SyncState(p) => {
+                if self.is_host {
+                    error("guest can't sync state");
+                    self.ctx.handle(Event::Disconnected);
+                    return;
+                }
+                if let Err(e) = check_state(p) {
+                    error(&e.to_string());
+                    self.ctx.handle(Event::Disconnected);
+                    self.ctx.handle(Event::NetError(e));
+                    return;
+                }
+
+                self.ctx.handle(Event::StateSync {
+                    state: p.clone(),
+                    is_local: false,
+                });
+            }
+            ChessPacket::Spectate(_)
+            | ChessPacket::RelayChat(_)
+            | ChessPacket::Clocks(_)
+            | ChessPacket::GameOver(_)
+                if self.is_host =>
+            {
+                error("guest can't send spectator packets");
+                self.ctx.handle(Event::Disconnected);
+            }
+            ChessPacket::Spectate(p) => {
+                if p.timer > MAX_TIMER {
+                    error("invalid settings");
+                    self.ctx.handle(Event::Disconnected);
+                    return;
+                }
+
+                self.is_spectator = true;
+                self.names = (p.light_name.clone(), p.dark_name.clone());
+                self.ctx.ui().set_player_name(true, p.light_name.clone());
+                self.ctx.ui().set_player_name(false, p.dark_name.clone());
+                self.ctx.handle(Event::Spectate {
+                    light_name: p.light_name.clone(),
+                    dark_name: p.dark_name.clone(),
+                    timer: p.timer,
+                });
+            }
+            ChessPacket::RelayChat(p) => {
+                let name = if p.is_light {
+                    &self.names.0
+                } else {
+                    &self.names.1
+                };
+                self.ctx.chat().player_message(p.is_light, name, &p.content);
+            }
+            ChessPacket::Clocks(p) => {
+                self.ctx.handle(Event::ClockUpdate {
+                    light_ms: p.light_ms,
+                    dark_ms: p.dark_ms,
+                    active: p.active,
+                    is_local: false,
+                });
+            }
+            ChessPacket::GameOver(p) => {
+                self.ctx.handle(Event::GameEnded {
+                    won_light: p.won_light,
+                });
+            }
+            ChessPacket::RequestSync(_) => {
+                if !self.is_host {
+                    error("host can't request a sync");
+                    self.ctx.handle(Event::Disconnected);
+                    return;
+                }
+
+                self.ctx.handle(Event::RequestSync { is_local: false });
+            }
+            ChessPacket::AuthChallenge(_)
+            | ChessPacket::AuthProof(_)
+            | ChessPacket::AuthResult(_) => {
+                error("peer authenticated twice");
+                self.ctx.handle(Event::Disconnected);
+            }
+        };
+    }
+
+    // Packets exchanged before the peer is let in
+    fn handle_auth_packet(&mut self, packet: &ChessPacket) {
+        let conn = match &self.pending {
+            Some(conn) => conn,
+            None => return,
+        };
+
+        match packet {
+            ChessPacket::AuthChallenge(p) if !self.is_host => {
+                // Whoever holds the room code must know the password too
+                let nonce = new_nonce(&self.ctx);
+                conn.send(
+                    ChessPacket::AuthProof(AuthProof {
+                        proof: guest_proof(&self.password, &p.nonce),
+                        nonce: nonce.clone(),
+                    })
+                    .write(),
+                );
+                self.nonce = Some(nonce);
+            }
+            ChessPacket::AuthProof(p) if self.is_host => {
+                self.proof = Some((p.proof.clone(), p.nonce.clone()));
+                self.admit();
+            }
+            ChessPacket::AuthResult(p) if !self.is_host => {
+                if !p.accepted {
+                    error("host rejected our room password");
+                    self.ctx.handle(Event::Disconnected);
+                    self.ctx.handle(Event::NetError(NetError::WrongPassword));
+                    return;
+                }
+
+                let genuine = self
+                    .nonce
+                    .as_ref()
+                    .is_some_and(|nonce| p.proof == host_proof(&self.password, nonce));
+                if !genuine {
+                    error("host doesn't know the room password");
+                    self.ctx.handle(Event::Disconnected);
+                    self.ctx.handle(Event::NetError(NetError::ImpostorHost));
+                    return;
+                }
+                self.authenticated();
+            }
+            ChessPacket::Handshake(p) if p.negotiate().is_none() => {
+                // Older peers skip authentication altogether
+                error(&format!(
+                    "incompatible peer: speaks versions {} to {}",
+                    p.min_version, p.max_version
+                ));
+                self.ctx.handle(Event::IncompatiblePeer);
+                self.ctx.handle(Event::Disconnected);
+            }
+            _ => {
+                error("peer skipped authentication");
+                self.ctx.handle(Event::Disconnected);
+            }
+        };
+    }
+
+    // Host: lets the pending guest in once both its proof and our password are known
+    fn admit(&mut self) {
+        if !self.is_host || !self.password_ready || self.pending.is_none() {
+            return;
+        }
+        let (nonce, (given, challenge)) = match (&self.nonce, &self.proof) {
+            (Some(nonce), Some(answer)) => (nonce, answer),
+            _ => return,
+        };
+
+        let accepted = *given == guest_proof(&self.password, nonce);
+        let proof = if accepted {
+            host_proof(&self.password, challenge)
+        } else {
+            String::new()
         };
+        let conn = self.pending.as_ref().unwrap();
+        conn.send(ChessPacket::AuthResult(AuthResult { accepted, proof }).write());
+        if accepted {
+            self.authenticated();
+            return;
+        }
+
+        error("guest gave a wrong room password");
+        self.nonce = None;
+        self.proof = None;
+        let conn = self.pending.take().unwrap();
+        // It's no longer our peer, losing it is expected
+        conn.set_onclose(Box::new(|| {}));
+        self.close_later(conn);
+
+        // Keep the room open for whoever knows the password
+        if let Some(claim) = self.claim() {
+            self.kill();
+            let channel = self
+                .new_conn(true, ConnKind::Resume)
+                .start_as_host(Some(claim));
+            self.killer = Some(channel);
+        }
+    }
+
+    fn authenticated(&mut self) {
+        let conn = match self.pending.take() {
+            Some(conn) => conn,
+            None => return,
+        };
+        self.nonce = None;
+        self.proof = None;
+
+        self.ctx.handle(Event::Connected(conn.clone()));
+        conn.send(ChessPacket::Handshake(Handshake::new(self.name.clone())).write());
     }
 
     pub fn on_event(&mut self, evt: &Event) {
         match evt {
             Event::Start => {
-                let ctx = self.ctx.clone();
-                let closure: Closure<dyn FnMut()> =
-                    Closure::new(move || ctx.handle(Event::PingRequest {}));
-                setInterval(&closure, 5_000);
-                closure.forget();
+                self.schedule_ping();
             }
             Event::JoinRoom(code) => {
                 self.is_host = false;
                 self.kill();
-                let channel = self.new_conn(false).start_as_guest(code.to_owned());
-                self.killer = Some(channel);
+                let mut net = self.new_conn(false, ConnKind::Room);
+                net.set_seat(Seat::Any);
+                self.killer = Some(net.start_as_guest(code.to_owned()));
             }
             Event::MenuHidden(menu) => {
-                let menu: i8 = (*menu) as i8;
-                let menu: Scene = menu.into();
-                if menu == Scene::Settings {
+                let menu = Scene::try_from(*menu as i8);
+                if menu == Ok(Scene::Settings) {
                     self.kill();
                 }
+                if menu == Ok(Scene::Lobby) {
+                    self.kill();
+                    self.lobby = None;
+                    self.quick_match = None;
+                }
             }
             Event::GameButtonClick(Button::LeaveRoom) => {
                 self.ctx.handle(Event::Disconnected);
             }
+            Event::SetSignalServer(url) => {
+                self.net.signal = Rc::new(HttpBackend::new(url));
+            }
+            Event::SetIceServers(json) => match serde_json::from_str(json) {
+                Ok(servers) => self.net.ice_servers = servers,
+                Err(e) => error(&format!("invalid ICE servers: {}", e)),
+            },
+            Event::SetRelayServer(url) => {
+                self.net.relay = Some(url.clone());
+            }
+            Event::SetConnectTimeout(secs) => {
+                self.net.connect_timeout = Duration::from_secs(*secs as u64);
+            }
             Event::CreateRoom => {
                 self.is_host = true;
                 self.kill();
-                let channel = self.new_conn(true).start_as_host();
+                let channel = self.new_conn(true, ConnKind::Room).start_as_host(None);
                 self.killer = Some(channel);
             }
-            Event::Connected(conn) => {
-                self.conn = Some(conn.clone());
+            Event::OpenLobby => {
+                self.start_lobby();
+            }
+            Event::QuickMatch(time) => {
+                // Decided once a fresh room list comes in
+                self.quick_match = Some(*time);
+                self.start_lobby();
+            }
+            Event::CreatePublicRoom(time) => {
+                self.quick_match = None;
+                self.host_public(*time);
+            }
+            Event::RoomList(rooms) => {
+                let time = match self.quick_match.take() {
+                    Some(time) => time,
+                    None => return,
+                };
 
-                for packet in self.queue.drain(0..) {
-                    conn.send(packet.write());
+                let info = self.room_info(time);
+                match rooms.iter().find(|room| room.info.matches(&info)) {
+                    Some(room) => {
+                        self.is_host = false;
+                        self.kill();
+                        let channel = self
+                            .new_conn(false, ConnKind::QuickMatch(time))
+                            .start_as_guest(room.code.clone());
+                        self.killer = Some(channel);
+                    }
+                    None => self.host_public(time),
+                }
+            }
+            Event::CreateOffer => {
+                self.is_host = true;
+                self.kill();
+                let (channel, answers) = self.new_conn(true, ConnKind::Manual).start_manual_host();
+                self.killer = Some(channel);
+                self.answers = Some(answers);
+            }
+            Event::AcceptCode(code) => {
+                let code = match ManualCode::decode(code) {
+                    Some(code) => code,
+                    None => {
+                        self.ctx.handle(Event::InvalidCode);
+                        return;
+                    }
+                };
+
+                match (&self.answers, code.is_offer) {
+                    (Some(answers), false) => {
+                        let _ = answers.unbounded_send(code);
+                    }
+                    (None, true) => {
+                        self.is_host = false;
+                        self.kill();
+                        let channel = self
+                            .new_conn(false, ConnKind::Manual)
+                            .start_manual_guest(code);
+                        self.killer = Some(channel);
+                    }
+                    _ => {
+                        // An offer pasted back to its host, or an answer nobody waits for
+                        self.ctx.handle(Event::InvalidCode);
+                    }
+                }
+            }
+            Event::SetPassword(password) => {
+                self.password = password.clone();
+                self.password_ready = true;
+                self.admit();
+            }
+            Event::Linked { conn, is_host } => {
+                self.is_host = *is_host;
+                self.kill();
+
+                let ctx = self.ctx.clone();
+                conn.set_onmessage(Box::new(move |data| receive(&ctx, data)));
+                let ctx = self.ctx.clone();
+                conn.set_onclose(Box::new(move || ctx.handle(Event::ConnectionLost)));
+
+                self.ctx.handle(Event::JoinedRoom {
+                    code: None,
+                    is_host: *is_host,
+                    secret: None,
+                });
+                self.ctx.handle(Event::TransportOpened(conn.clone()));
+            }
+            Event::TransportOpened(conn) => {
+                self.pending = Some(conn.clone());
+                if !self.is_host {
+                    return;
                 }
+
+                let nonce = new_nonce(&self.ctx);
+                conn.send(
+                    ChessPacket::AuthChallenge(AuthChallenge {
+                        nonce: nonce.clone(),
+                    })
+                    .write(),
+                );
+                self.nonce = Some(nonce);
+                self.proof = None;
+            }
+            Event::Connected(conn) => {
+                self.conn = Some(conn.clone());
+                self.resuming = false;
+                self.retry = None;
+                self.version = None;
+                self.listen_spectators();
             }
             Event::Register(name) => {
                 self.name = name.clone();
             }
             Event::ChatMessage { is_local, content } => {
+                if self.is_host {
+                    self.relay(ChessPacket::RelayChat(RelayChat {
+                        is_light: self.host_as_light == *is_local,
+                        content: content.clone(),
+                    }));
+                }
+
                 if !is_local {
                     return;
                 }
-                if let Some(conn) = &self.conn {
-                    conn.send(
-                        ChessPacket::ChatMessage(ChatMessage {
-                            content: content.clone(),
-                        })
-                        .write(),
-                    );
-                }
+                self.send_to_peer(ChessPacket::ChatMessage(ChatMessage {
+                    content: content.clone(),
+                }));
+            }
+            Event::JoinedRoom { code, secret, .. } => {
+                self.room = code.clone();
+                self.room_secret = secret.clone();
+                self.lobby = None;
             }
             Event::Disconnected => {
                 self.kill();
+                self.version = None;
+                self.stop_spectators();
+                for (_, conn, _) in self.spectators.drain(0..) {
+                    conn.close();
+                }
+                for (_, conn, _) in self.pending_spectators.drain(0..) {
+                    conn.close();
+                }
+                self.ping = None;
+                self.missed_pings = 0;
+                self.room = None;
+                self.room_secret = None;
+                self.resuming = false;
+                self.retry = None;
+                self.is_spectator = false;
                 if let Some(conn) = self.conn.take() {
                     conn.close();
                 }
+                if let Some(conn) = self.pending.take() {
+                    conn.close();
+                }
+                self.password = "".to_owned();
+                self.password_ready = false;
+                self.nonce = None;
+                self.proof = None;
             }
-            Event::GameStart => {
-                if self.is_host {
-                    self.conn
-                        .as_ref()
-                        .unwrap()
-                        .send(ChessPacket::Start(Start {}).write());
+            Event::ConnectionLost => {
+                self.kill();
+                // Room code is needed to reconnect; spectators can come back later
+                self.stop_spectators();
+                self.ping = None;
+                self.missed_pings = 0;
+                if let Some(conn) = self.conn.take() {
+                    conn.close();
                 }
+                if let Some(conn) = self.pending.take() {
+                    conn.close();
+                }
+                self.nonce = None;
+                self.proof = None;
+            }
+            Event::Reconnect => {
+                self.resuming = true;
+                self.retry = None;
+                self.reconnect();
+            }
+            Event::NetError(_) if self.resuming => {
+                // Peer might not be back yet, try again later
+                let ctx = self.ctx.clone();
+                let at = self.ctx.now() + RECONNECT_DELAY;
+                self.retry = Some(self.ctx.timeout_at(at, move || {
+                    ctx.handle(Event::Reconnect);
+                }));
+            }
+            Event::NetError(_) => {
+                self.quick_match = None;
+            }
+            Event::GameStart if self.is_host => {
+                self.send_when_ready(ChessPacket::Start(Start {}));
             }
             Event::SetSettings {
                 timer,
                 host_as_light,
+                reconnect_window,
+                pause_on_disconnect,
             } => {
                 if !self.is_host {
                     return;
                 }
 
+                self.timer = *timer;
+                self.host_as_light = *host_as_light;
+                self.relay(self.spectate_packet());
+
                 self.send_when_ready(ChessPacket::SetSettings(SetSettings {
                     timer: *timer,
                     host_as_light: *host_as_light,
+                    reconnect_window: *reconnect_window,
+                    pause_on_disconnect: *pause_on_disconnect,
                 }));
             }
             Event::LoadedBoard(board) => {
@@ -295,21 +1145,29 @@ impl Client {
                 }));
             }
             Event::PingRequest => {
+                self.schedule_ping();
                 let conn = match &self.conn {
                     Some(c) => c,
                     None => return,
                 };
 
                 if self.ping.is_some() {
-                    error("unhandled ping: is peer disconnected?");
-                    self.ctx.handle(Event::Disconnected);
+                    // Still waiting for a reply. The channel is reliable, so
+                    // it's either late or never coming.
+                    self.missed_pings += 1;
+                    if self.missed_pings >= MAX_MISSED_PINGS {
+                        error("unhandled ping: is peer disconnected?");
+                        self.ctx.handle(Event::ConnectionLost);
+                    } else if self.missed_pings == 1 {
+                        self.ctx.handle(Event::PeerLagging(true));
+                    }
                     return;
                 }
 
                 let id = self.rng.next_u32() as u16;
                 self.ping = Some(PingRequest {
                     id,
-                    sent_at: Instant::now(),
+                    sent_at: self.ctx.now(),
                 });
                 conn.send(
                     ChessPacket::Ping(Ping {
@@ -319,38 +1177,72 @@ impl Client {
                     .write(),
                 );
             }
-            Event::PacketReceived(packet) => {
-                self.handle_packet(packet);
+            Event::PacketReceived { packet, spectator } => {
+                self.handle_packet(packet, *spectator);
+            }
+            Event::SpectatorJoined { id, conn } => {
+                if !self.is_host {
+                    conn.close();
+                    return;
+                }
+
+                // Spectators need the password too
+                let nonce = new_nonce(&self.ctx);
+                conn.send(
+                    ChessPacket::AuthChallenge(AuthChallenge {
+                        nonce: nonce.clone(),
+                    })
+                    .write(),
+                );
+                self.pending_spectators.push((*id, conn.clone(), nonce));
+                // Wait for the next one
+                self.listen_spectators();
+            }
+            Event::SpectatorLeft(id) => {
+                if let Some(idx) = self.spectators.iter().position(|(s, ..)| s == id) {
+                    let (_, conn, _) = self.spectators.remove(idx);
+                    conn.close();
+                }
+                if let Some(idx) = self.pending_spectators.iter().position(|(s, ..)| s == id) {
+                    let (_, conn, _) = self.pending_spectators.remove(idx);
+                    conn.close();
+                }
+            }
+            Event::SpectatorSync { id, state } => {
+                let packet = ChessPacket::SyncState(state.clone());
+                for (spectator, conn, version) in self.spectators.iter() {
+                    match version {
+                        Some(version) if id.is_none() || *id == Some(*spectator) => {
+                            conn.send(packet.write_as(*version));
+                        }
+                        _ => {}
+                    }
+                }
             }
             Event::Movement {
                 piece,
                 to,
+                checksum,
                 is_local,
             } => {
                 if !*is_local {
                     return;
                 }
 
-                if let Some(conn) = &self.conn {
-                    conn.send(
-                        ChessPacket::Movement(Movement {
-                            idx: *piece,
-                            q: to.0,
-                            r: to.1,
-                            time_left: None,
-                        })
-                        .write(),
-                    );
-                }
+                self.send_to_peer(ChessPacket::Movement(Movement {
+                    idx: *piece,
+                    q: to.0,
+                    r: to.1,
+                    time_left: None,
+                    checksum: *checksum,
+                }));
             }
             Event::Resign(local) => {
                 if !local {
                     return;
                 }
 
-                if let Some(conn) = &self.conn {
-                    conn.send(ChessPacket::Resign(Resign {}).write());
-                }
+                self.send_to_peer(ChessPacket::Resign(Resign {}));
             }
             Event::Promotion {
                 piece,
@@ -361,15 +1253,56 @@ impl Client {
                     return;
                 }
 
-                if let Some(conn) = &self.conn {
-                    conn.send(
-                        ChessPacket::Promote(Promote {
-                            idx: *piece,
-                            kind: *kind,
-                        })
-                        .write(),
-                    )
+                self.send_to_peer(ChessPacket::Promote(Promote {
+                    idx: *piece,
+                    kind: *kind,
+                }));
+            }
+            Event::MovePlayed {
+                piece,
+                to,
+                checksum,
+            } if self.is_host => {
+                self.relay(ChessPacket::Movement(Movement {
+                    idx: *piece,
+                    q: to.0,
+                    r: to.1,
+                    time_left: None,
+                    checksum: *checksum,
+                }));
+            }
+            Event::PiecePromoted { piece, kind } if self.is_host => {
+                self.relay(ChessPacket::Promote(Promote {
+                    idx: *piece,
+                    kind: *kind,
+                }));
+            }
+            Event::ClockUpdate {
+                light_ms,
+                dark_ms,
+                active,
+                is_local: true,
+            } if self.is_host => {
+                self.relay(ChessPacket::Clocks(Clocks {
+                    light_ms: *light_ms,
+                    dark_ms: *dark_ms,
+                    active: *active,
+                }));
+            }
+            Event::GameEnded { won_light } if self.is_host => {
+                self.relay(ChessPacket::GameOver(GameOver {
+                    won_light: *won_light,
+                }));
+            }
+            Event::RequestSync { is_local: true } if !self.is_host => {
+                self.send_to_peer(ChessPacket::RequestSync(RequestSync {}));
+            }
+            Event::StateSync { state, is_local } => {
+                if !*is_local || !self.is_host {
+                    return;
                 }
+
+                self.send_when_ready(ChessPacket::SyncState(state.clone()));
             }
             _ => {}
         };
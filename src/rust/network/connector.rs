@@ -1,20 +1,71 @@
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
+
 use futures::Future;
-use futures_channel::mpsc::{UnboundedReceiver, UnboundedSender};
-use wasm_bindgen::JsValue;
+use futures_channel::mpsc::{TryRecvError, UnboundedReceiver, UnboundedSender};
 use wasm_bindgen_futures::spawn_local;
 use web_sys::RtcSdpType;
+use web_time::Duration;
 
+use crate::clock::{Clock, WebClock};
 use crate::utils::wait_until;
 
 use super::buffer::Buffer;
-use super::p2p::Connection;
-use super::signal::SignalClient;
+use super::error::NetError;
+use super::manual::ManualCode;
+use super::p2p::{IceCandidate, IceServer, PeerConnection};
+use super::relay::{relay_session, RelayConnection};
+use super::signal::{
+    HttpBackend, RoomClaim, RoomInfo, SignalBackend, SignalClient, DEFAULT_SERVER,
+};
+use super::transport::{Connection, Transport};
+
+type ConnHandler = Box<dyn FnMut(&Connection)>;
+type ConnHandler1<T> = Box<dyn FnMut(&Connection, T)>;
+// Called with the room code and, for hosts, the secret to reclaim it
+type RoomHandler = Box<dyn FnMut(String, Option<String>)>;
+// Handlers are kept around so they can move to the relay if WebRTC fails
+type Shared<T> = Rc<RefCell<T>>;
+
+// How long to wait for ICE gathering in manual mode
+const GATHER_TIMEOUT: Duration = Duration::from_secs(5);
+const GATHER_INTERVAL: Duration = Duration::from_millis(100);
+// How long the data channel may take to open before falling back to the relay
+const OPEN_TIMEOUT: Duration = Duration::from_secs(10);
+// How long the relay may take to pair us with the peer
+const RELAY_OPEN_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Where connectors find their peers
+#[derive(Clone)]
+pub struct NetConfig {
+    pub signal: Rc<dyn SignalBackend>,
+    pub ice_servers: Vec<IceServer>,
+    // WebSocket relay used when WebRTC can't connect
+    pub relay: Option<String>,
+    // How long joining a room may take. Hosts aren't limited while
+    // waiting for someone to join.
+    pub connect_timeout: Duration,
+    pub clock: Rc<dyn Clock>,
+}
 
-const SERVER: &str = "https://signalling.tocu.workers.dev";
+impl Default for NetConfig {
+    fn default() -> Self {
+        NetConfig {
+            signal: Rc::new(HttpBackend::new(DEFAULT_SERVER)),
+            ice_servers: vec![],
+            relay: None,
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            clock: Rc::new(WebClock),
+        }
+    }
+}
 
-async fn wrap<F>(onerror: Option<Box<dyn FnMut(JsValue)>>, fut: F)
+async fn wrap<F>(onerror: Option<Box<dyn FnMut(NetError)>>, fut: F)
 where
-    F: Future<Output = Result<(), JsValue>> + 'static,
+    F: Future<Output = Result<(), NetError>> + 'static,
 {
     let result = fut.await;
 
@@ -25,100 +76,199 @@ where
     }
 }
 
-fn bind_handler(conn: &Connection, mut handler: Box<dyn FnMut(&Connection)>) -> Box<dyn FnMut()> {
-    let conn = conn.clone();
-    Box::new(move || handler(&conn))
-}
-
-fn bind_handler_1<T>(
-    conn: &Connection,
-    mut handler: Box<dyn FnMut(&Connection, T)>,
-) -> Box<dyn FnMut(T)>
+fn bind_handler_1<T>(conn: &Connection, handler: Shared<ConnHandler1<T>>) -> Box<dyn FnMut(T)>
 where
     T: 'static,
 {
     let conn = conn.clone();
-    Box::new(move |value: T| handler(&conn, value))
+    Box::new(move |value: T| (handler.borrow_mut())(&conn, value))
+}
+
+// Which of a room's seats to take
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Seat {
+    Player,
+    Spectator,
+    // Guests: play if the room has space, watch otherwise
+    Any,
 }
 
 pub struct Connector {
     signal: SignalClient,
+    clock: Rc<dyn Clock>,
+    ice_servers: Vec<IceServer>,
+    relay: Option<String>,
+    connect_timeout: Duration,
+    // Hosts: list the room publicly with this
+    advertise: Option<RoomInfo>,
+    seat: Seat,
+    // Whether the current transport opened
+    opened: Rc<Cell<bool>>,
     kill_channel: Option<UnboundedReceiver<()>>,
     onestablishing: Option<Box<dyn FnMut()>>,
-    onopen: Option<Box<dyn FnMut(&Connection)>>,
-    onmessage: Option<Box<dyn FnMut(&Connection, Buffer)>>,
-    onclose: Option<Box<dyn FnMut()>>,
-    onroom: Option<Box<dyn FnMut(String)>>,
-    onerror: Option<Box<dyn FnMut(JsValue)>>,
+    onopen: Option<Shared<ConnHandler>>,
+    onmessage: Option<Shared<ConnHandler1<Buffer>>>,
+    onclose: Option<Shared<Box<dyn FnMut()>>>,
+    // Room code, and the secret to claim it back if we opened it
+    onroom: Option<RoomHandler>,
+    oncode: Option<Box<dyn FnMut(String)>>,
+    onerror: Option<Box<dyn FnMut(NetError)>>,
 }
 
 impl Connector {
-    pub fn new() -> Self {
+    pub fn new(config: NetConfig) -> Self {
         Connector {
-            signal: SignalClient::new(SERVER),
+            signal: SignalClient::new(config.signal, config.clock.clone()),
+            clock: config.clock,
+            ice_servers: config.ice_servers,
+            relay: config.relay,
+            connect_timeout: config.connect_timeout,
+            advertise: None,
+            seat: Seat::Player,
+            opened: Rc::new(Cell::new(false)),
             kill_channel: None,
             onestablishing: None,
             onopen: None,
             onmessage: None,
             onclose: None,
             onroom: None,
+            oncode: None,
             onerror: None,
         }
     }
 
+    pub fn set_advertise(&mut self, info: RoomInfo) {
+        self.advertise = Some(info);
+    }
+
+    pub fn set_seat(&mut self, seat: Seat) {
+        self.seat = seat;
+    }
+
     pub fn set_onestablishing(&mut self, handler: Box<dyn FnMut()>) {
         self.onestablishing = Some(handler);
     }
-    pub fn set_onopen(&mut self, handler: Box<dyn FnMut(&Connection)>) {
-        self.onopen = Some(handler);
+    pub fn set_onopen(&mut self, handler: ConnHandler) {
+        self.onopen = Some(Rc::new(RefCell::new(handler)));
     }
-    pub fn set_onmessage(&mut self, handler: Box<dyn FnMut(&Connection, Buffer)>) {
-        self.onmessage = Some(handler);
+    pub fn set_onmessage(&mut self, handler: ConnHandler1<Buffer>) {
+        self.onmessage = Some(Rc::new(RefCell::new(handler)));
     }
     pub fn set_onclose(&mut self, handler: Box<dyn FnMut()>) {
-        self.onclose = Some(handler);
+        self.onclose = Some(Rc::new(RefCell::new(handler)));
     }
-    pub fn set_onroom(&mut self, handler: Box<dyn FnMut(String)>) {
+    pub fn set_onroom(&mut self, handler: RoomHandler) {
         self.onroom = Some(handler);
     }
-    pub fn set_onerror(&mut self, handler: Box<dyn FnMut(JsValue)>) {
+    pub fn set_oncode(&mut self, handler: Box<dyn FnMut(String)>) {
+        self.oncode = Some(handler);
+    }
+    pub fn set_onerror(&mut self, handler: Box<dyn FnMut(NetError)>) {
         self.onerror = Some(handler);
     }
 
-    fn new_connection(&mut self) -> Connection {
-        let conn = Connection::new();
+    fn bind(&mut self, conn: &Connection) {
+        let opened = self.opened.clone();
+        let onopen = self.onopen.clone();
+        let bound = conn.clone();
+        conn.set_onopen(Box::new(move || {
+            opened.set(true);
+            if let Some(handler) = &onopen {
+                (handler.borrow_mut())(&bound);
+            }
+        }));
 
-        if let Some(handler) = self.onopen.take() {
-            conn.set_onopen(bind_handler(&conn, handler));
+        if let Some(handler) = self.onmessage.clone() {
+            conn.set_onmessage(bind_handler_1(conn, handler));
         }
-        if let Some(handler) = self.onmessage.take() {
-            conn.set_onmessage(bind_handler_1(&conn, handler));
+        if let Some(handler) = self.onclose.clone() {
+            conn.set_onclose(Box::new(move || (handler.borrow_mut())()));
         }
-        if let Some(handler) = self.onclose.take() {
-            conn.set_onclose(handler);
+    }
+
+    // Gets a token, and the ICE servers the signal service suggests. Its relay
+    // is used unless one was configured.
+    async fn ident(&mut self) -> Result<Vec<IceServer>, NetError> {
+        let resp = self.signal.ident().await?;
+        if self.relay.is_none() {
+            self.relay = resp.relay;
         }
+        Ok(resp.ice_servers)
+    }
 
+    // Uses the configured ICE servers, plus the ones the signal service gave us
+    fn new_connection(&mut self, suggested: Vec<IceServer>) -> PeerConnection {
+        let mut servers = self.ice_servers.clone();
+        servers.extend(suggested);
+        let conn = PeerConnection::new(&servers, self.clock.system_now());
+        self.bind(&Connection::new(conn.clone()));
         conn
     }
 
-    async fn drain_ice(&mut self, conn: &Connection) -> Result<(), JsValue> {
+    // Waits for the data channel, moving over to the relay if it doesn't open
+    async fn await_open(&mut self, conn: &PeerConnection, offer: &str) -> Result<(), NetError> {
+        let deadline = self.signal.connect_at.unwrap_or(self.clock.now()) + OPEN_TIMEOUT;
+        while !self.opened.get() && !conn.ice_failed() && self.clock.now() < deadline {
+            wait_until(self.clock.as_ref(), self.clock.now() + GATHER_INTERVAL).await;
+            self.check_dead(conn)?;
+        }
+        if self.opened.get() {
+            return Ok(());
+        }
+
+        let failed = conn.ice_failed();
+        conn.close();
+        let url = match &self.relay {
+            Some(url) => url.clone(),
+            None if failed => return Err(NetError::IceFailed),
+            None => return Err(NetError::Timeout),
+        };
+        let relay = Connection::new(RelayConnection::new(&url, &relay_session(offer))?);
+        self.bind(&relay);
+
+        let deadline = self.clock.now() + RELAY_OPEN_TIMEOUT;
+        while !self.opened.get() && self.clock.now() < deadline {
+            wait_until(self.clock.as_ref(), self.clock.now() + GATHER_INTERVAL).await;
+            if let Err(err) = self.check_dead(conn) {
+                relay.close();
+                return Err(err);
+            }
+        }
+        if !self.opened.get() {
+            // Relay is unreachable, or the peer never made it there
+            relay.close();
+            return Err(NetError::Timeout);
+        }
+        Ok(())
+    }
+
+    async fn drain_ice(&mut self, conn: &PeerConnection) -> Result<(), NetError> {
         conn.add_ice_candidates(self.signal.peer_ice.drain(0..).collect())
             .await?;
         Ok(())
     }
 
-    fn check_dead(&mut self, conn: &Connection) -> Result<(), JsValue> {
+    fn check_dead(&mut self, conn: &PeerConnection) -> Result<(), NetError> {
         let channel = self.kill_channel.as_mut().unwrap();
-        if channel.try_next().is_err() {
+        if let Err(TryRecvError::Empty) = channel.try_recv() {
             // No message received, and channel is not dropped.
-            return Ok(());
+            if !self.signal.past_deadline() {
+                return Ok(());
+            }
+
+            conn.close();
+            return Err(NetError::Timeout);
         }
 
         conn.close();
-        Err(JsValue::from_str("killed connector"))
+        Err(NetError::Killed)
+    }
+
+    fn start_deadline(&mut self) {
+        self.signal.deadline = Some(self.clock.now() + self.connect_timeout);
     }
 
-    async fn poll(&mut self, conn: &Connection) -> Result<(), JsValue> {
+    async fn poll(&mut self, conn: &PeerConnection) -> Result<(), NetError> {
         self.signal.wait_for_poll().await;
         self.check_dead(conn)?;
         self.signal.send_ice(conn.poll_ice_candidates());
@@ -126,10 +276,17 @@ impl Connector {
         Ok(())
     }
 
-    async fn wait_for_connect(&mut self, conn: &Connection, drain: bool) -> Result<(), JsValue> {
+    async fn wait_for_connect(
+        &mut self,
+        conn: &PeerConnection,
+        drain: bool,
+    ) -> Result<(), NetError> {
         while self.signal.connect_at.is_none() {
             if !self.signal.can_poll() {
-                panic!("can't poll but didn't connect?");
+                conn.close();
+                return Err(NetError::Http(
+                    "signalling ended before connecting".to_owned(),
+                ));
             }
 
             self.poll(conn).await?;
@@ -138,14 +295,16 @@ impl Connector {
             }
         }
 
+        // Someone showed up, so we're back on the clock
+        self.start_deadline();
         if let Some(ref mut handler) = self.onestablishing {
             handler();
         }
-        wait_until(self.signal.connect_at.unwrap()).await;
+        wait_until(self.clock.as_ref(), self.signal.connect_at.unwrap()).await;
         Ok(())
     }
 
-    async fn poll_until_done(&mut self, conn: &Connection) -> Result<(), JsValue> {
+    async fn poll_until_done(&mut self, conn: &PeerConnection) -> Result<(), NetError> {
         while self.signal.can_poll() {
             self.poll(conn).await?;
             self.drain_ice(conn).await?;
@@ -153,45 +312,85 @@ impl Connector {
         Ok(())
     }
 
-    async fn run_as_host(mut self) -> Result<(), JsValue> {
-        let conn = self.new_connection();
+    async fn run_as_host(mut self, claim: Option<RoomClaim>) -> Result<(), NetError> {
+        self.start_deadline();
+        let suggested = self.ident().await?;
+        let conn = self.new_connection(suggested);
         let sdp = conn.prepare(RtcSdpType::Offer, None).await?;
-        self.signal.send_sdp(sdp);
+        match (claim, self.seat) {
+            (Some(claim), Seat::Spectator) => self.signal.send_host_spectators(claim),
+            (Some(claim), _) => self.signal.send_host_room(claim),
+            (None, _) => {}
+        }
+        self.signal.send_sdp(sdp.clone());
+        if let Some(info) = self.advertise.take() {
+            self.signal.send_advertise(info);
+        }
         self.poll(&conn).await?;
 
+        if self.signal.room_full {
+            // Someone else's room, or it was forgotten
+            conn.close();
+            return Err(NetError::RoomFull);
+        }
         if self.signal.room.is_empty() {
-            panic!("couldn't create room");
+            conn.close();
+            return Err(NetError::Http("no room was assigned".to_owned()));
         }
 
         if let Some(ref mut handler) = self.onroom {
-            handler(self.signal.room.clone());
+            handler(self.signal.room.clone(), self.signal.secret.clone());
         }
 
+        // Waiting for a guest can take as long as it takes
+        self.signal.deadline = None;
         self.wait_for_connect(&conn, true).await?;
-        conn.set_remote(RtcSdpType::Answer, self.signal.peer_sdp.clone().unwrap())
-            .await?;
+        let answer = match self.signal.peer_sdp.clone() {
+            Some(answer) => answer,
+            None => {
+                conn.close();
+                return Err(NetError::Http("peer never answered".to_owned()));
+            }
+        };
+        conn.set_remote(RtcSdpType::Answer, answer).await?;
 
         self.poll_until_done(&conn).await?;
-        Ok(())
+        self.await_open(&conn, &sdp).await
     }
 
-    async fn run_as_guest(mut self, code: String) -> Result<(), JsValue> {
-        let conn = self.new_connection();
-        self.signal.send_join_room(code);
+    async fn run_as_guest(mut self, code: String) -> Result<(), NetError> {
+        self.start_deadline();
+        let suggested = self.ident().await?;
+        let conn = self.new_connection(suggested);
+        if self.seat == Seat::Spectator {
+            self.signal.send_watch_room(code.clone());
+        } else {
+            self.signal.send_join_room(code.clone());
+        }
         self.poll(&conn).await?;
 
+        if self.signal.room_full && self.seat == Seat::Any {
+            // The game already started, so watch it
+            self.signal.room_full = false;
+            self.signal.send_watch_room(code);
+            self.poll(&conn).await?;
+        }
+        if self.signal.room_full {
+            conn.close();
+            return Err(NetError::RoomFull);
+        }
         if self.signal.room.is_empty() || self.signal.peer_sdp.is_none() {
-            panic!("couldn't join room");
+            conn.close();
+            return Err(NetError::RoomNotFound);
         }
 
-        let sdp = conn
-            .create_answer(self.signal.peer_sdp.clone().unwrap())
-            .await?;
+        let offer = self.signal.peer_sdp.clone().unwrap();
+        let sdp = conn.create_answer(offer.clone()).await?;
         self.signal.send_sdp(sdp.clone());
         self.poll(&conn).await?;
 
         if let Some(ref mut handler) = self.onroom {
-            handler(self.signal.room.clone());
+            handler(self.signal.room.clone(), None);
         }
 
         self.wait_for_connect(&conn, false).await?;
@@ -199,6 +398,77 @@ impl Connector {
         self.drain_ice(&conn).await?;
 
         self.poll_until_done(&conn).await?;
+        self.await_open(&conn, &offer).await
+    }
+
+    // Collects local candidates until gathering is done, as they can't trickle
+    async fn gather_ice(&mut self, conn: &PeerConnection) -> Result<Vec<IceCandidate>, NetError> {
+        let deadline = self.clock.now() + GATHER_TIMEOUT;
+        let mut ice = vec![];
+        while self.clock.now() < deadline {
+            wait_until(self.clock.as_ref(), self.clock.now() + GATHER_INTERVAL).await;
+            self.check_dead(conn)?;
+
+            ice.extend(conn.poll_ice_candidates());
+            if ice.last().is_some_and(|c| c.0.is_empty()) {
+                break;
+            }
+        }
+        Ok(ice)
+    }
+
+    fn send_code(&mut self, code: ManualCode) {
+        if let Some(ref mut handler) = self.oncode {
+            handler(code.encode());
+        }
+    }
+
+    // Manual games don't fall back to the relay: without a signalling
+    // server, peers can't agree on when to give up on WebRTC
+    async fn run_manual_host(
+        mut self,
+        mut answers: UnboundedReceiver<ManualCode>,
+    ) -> Result<(), NetError> {
+        let conn = self.new_connection(vec![]);
+        let sdp = conn.prepare(RtcSdpType::Offer, None).await?;
+        let ice = self.gather_ice(&conn).await?;
+        self.send_code(ManualCode {
+            is_offer: true,
+            sdp,
+            ice,
+        });
+
+        let answer = loop {
+            wait_until(self.clock.as_ref(), self.clock.now() + GATHER_INTERVAL).await;
+            self.check_dead(&conn)?;
+            match answers.try_recv() {
+                Ok(answer) => break answer,
+                Err(TryRecvError::Empty) => {}
+                Err(TryRecvError::Closed) => {
+                    // Nobody is left to give us an answer
+                    conn.close();
+                    return Err(NetError::Killed);
+                }
+            }
+        };
+
+        conn.set_remote(RtcSdpType::Answer, answer.sdp).await?;
+        conn.add_ice_candidates(answer.ice).await?;
+        Ok(())
+    }
+
+    async fn run_manual_guest(mut self, offer: ManualCode) -> Result<(), NetError> {
+        let conn = self.new_connection(vec![]);
+        let sdp = conn.create_answer(offer.sdp).await?;
+        conn.prepare(RtcSdpType::Answer, Some(sdp.clone())).await?;
+        conn.add_ice_candidates(offer.ice).await?;
+
+        let ice = self.gather_ice(&conn).await?;
+        self.send_code(ManualCode {
+            is_offer: false,
+            sdp,
+            ice,
+        });
         Ok(())
     }
 
@@ -208,9 +478,10 @@ impl Connector {
         tx
     }
 
-    pub fn start_as_host(mut self) -> UnboundedSender<()> {
+    // Creates a new room, or reclaims the given one to resume a game
+    pub fn start_as_host(mut self, claim: Option<RoomClaim>) -> UnboundedSender<()> {
         let tx = self.start_kill_channel();
-        spawn_local(wrap(self.onerror.take(), self.run_as_host()));
+        spawn_local(wrap(self.onerror.take(), self.run_as_host(claim)));
         tx
     }
 
@@ -219,4 +490,19 @@ impl Connector {
         spawn_local(wrap(self.onerror.take(), self.run_as_guest(code)));
         tx
     }
+
+    // Hands out an offer code, then waits for the answer to be sent through
+    // the returned channel
+    pub fn start_manual_host(mut self) -> (UnboundedSender<()>, UnboundedSender<ManualCode>) {
+        let tx = self.start_kill_channel();
+        let (answer_tx, answer_rx) = futures_channel::mpsc::unbounded();
+        spawn_local(wrap(self.onerror.take(), self.run_manual_host(answer_rx)));
+        (tx, answer_tx)
+    }
+
+    pub fn start_manual_guest(mut self, offer: ManualCode) -> UnboundedSender<()> {
+        let tx = self.start_kill_channel();
+        spawn_local(wrap(self.onerror.take(), self.run_manual_guest(offer)));
+        tx
+    }
 }
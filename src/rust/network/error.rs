@@ -0,0 +1,79 @@
+use std::fmt::Display;
+
+use wasm_bindgen::JsValue;
+
+// Why a connection attempt failed
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "trace", derive(serde::Serialize, serde::Deserialize))]
+pub enum NetError {
+    RoomNotFound,
+    // The room already has two players
+    RoomFull,
+    // Signalling server couldn't be reached, or sent garbage
+    Http(String),
+    // Signalling server answered with an error status
+    Status(u16),
+    IceFailed,
+    Timeout,
+    // The attempt was cancelled on our side
+    Killed,
+    // A browser API refused to cooperate
+    Browser(String),
+    // The host turned down our room password
+    WrongPassword,
+    // Whoever answered as host couldn't prove they know the room password
+    ImpostorHost,
+    // The host sent a game we can't pick up from
+    InvalidState(String),
+}
+
+impl NetError {
+    // Reason shown by the online menu
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Self::RoomNotFound => "not-found",
+            Self::RoomFull => "room-full",
+            Self::Http(_) | Self::Status(_) => "http",
+            Self::IceFailed => "ice-failed",
+            Self::Timeout => "timeout",
+            Self::Killed => "killed",
+            Self::Browser(_) => "browser",
+            Self::WrongPassword => "wrong-password",
+            Self::ImpostorHost => "impostor-host",
+            Self::InvalidState(_) => "invalid-state",
+        }
+    }
+
+    // Whether trying again later might work
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Self::Http(_) | Self::Timeout => true,
+            Self::Status(s) => *s == 429 || *s >= 500,
+            _ => false,
+        }
+    }
+}
+
+impl Display for NetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::RoomNotFound => write!(f, "room not found"),
+            Self::RoomFull => write!(f, "room is full"),
+            Self::Http(e) => write!(f, "signalling failed: {}", e),
+            Self::Status(s) => write!(f, "signalling server answered with status {}", s),
+            Self::IceFailed => write!(f, "couldn't reach peer"),
+            Self::Timeout => write!(f, "timed out"),
+            Self::Killed => write!(f, "killed connector"),
+            Self::Browser(e) => write!(f, "browser error: {}", e),
+            Self::WrongPassword => write!(f, "wrong room password"),
+            Self::ImpostorHost => write!(f, "host doesn't know the room password"),
+            Self::InvalidState(e) => write!(f, "invalid game state: {}", e),
+        }
+    }
+}
+
+impl From<JsValue> for NetError {
+    fn from(value: JsValue) -> Self {
+        Self::Browser(value.as_string().unwrap_or_else(|| format!("{:?}", value)))
+    }
+}
@@ -0,0 +1,64 @@
+use futures_channel::mpsc::{TryRecvError, UnboundedReceiver, UnboundedSender};
+use wasm_bindgen_futures::spawn_local;
+use web_time::Duration;
+
+use crate::utils::wait_until;
+
+use super::connector::NetConfig;
+use super::error::NetError;
+use super::signal::{PublicRoom, SignalClient};
+
+// How often the room list is refreshed
+const REFRESH_INTERVAL: Duration = Duration::from_secs(3);
+
+// Keeps fetching the public rooms until killed
+pub struct Lobby {
+    signal: SignalClient,
+    kill_channel: UnboundedReceiver<()>,
+    onrooms: Box<dyn FnMut(Vec<PublicRoom>)>,
+    onerror: Box<dyn FnMut(NetError)>,
+}
+
+impl Lobby {
+    // Lists rooms until the returned channel is used or dropped
+    pub fn start(
+        config: NetConfig,
+        onrooms: Box<dyn FnMut(Vec<PublicRoom>)>,
+        onerror: Box<dyn FnMut(NetError)>,
+    ) -> UnboundedSender<()> {
+        let (tx, rx) = futures_channel::mpsc::unbounded();
+        let lobby = Lobby {
+            signal: SignalClient::new(config.signal, config.clock),
+            kill_channel: rx,
+            onrooms,
+            onerror,
+        };
+        spawn_local(lobby.run());
+        tx
+    }
+
+    fn is_dead(&mut self) -> bool {
+        !matches!(self.kill_channel.try_recv(), Err(TryRecvError::Empty))
+    }
+
+    async fn run(mut self) {
+        loop {
+            match self.signal.list_rooms().await {
+                Ok(rooms) if !self.is_dead() => (self.onrooms)(rooms),
+                Err(e) if !self.is_dead() => {
+                    (self.onerror)(e);
+                    return;
+                }
+                _ => return,
+            }
+
+            // Never faster than the server asks for
+            let clock = self.signal.clock.clone();
+            wait_until(clock.as_ref(), clock.now() + REFRESH_INTERVAL).await;
+            self.signal.wait_for_poll().await;
+            if self.is_dead() {
+                return;
+            }
+        }
+    }
+}
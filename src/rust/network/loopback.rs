@@ -0,0 +1,131 @@
+use std::{
+    cell::{Cell, RefCell},
+    fmt,
+    rc::Rc,
+};
+
+use super::buffer::Buffer;
+use super::transport::{Connection, Transport};
+use crate::clock::Clock;
+
+#[derive(Default)]
+struct Handlers {
+    onopen: Option<Box<dyn FnMut()>>,
+    onmessage: Option<Box<dyn FnMut(Buffer)>>,
+    onclose: Option<Box<dyn FnMut()>>,
+}
+
+// One side of the pipe
+#[derive(Default)]
+struct End {
+    handlers: RefCell<Handlers>,
+    closed: Cell<bool>,
+}
+
+impl End {
+    fn deliver(&self, packet: Buffer) {
+        if self.closed.get() {
+            return;
+        }
+
+        // Taken out while it runs, as it may close this very end
+        let handler = self.handlers.borrow_mut().onmessage.take();
+        if let Some(mut handler) = handler {
+            handler(packet);
+            let mut handlers = self.handlers.borrow_mut();
+            if !self.closed.get() && handlers.onmessage.is_none() {
+                handlers.onmessage = Some(handler);
+            }
+        }
+    }
+
+    fn open(&self) {
+        let handler = self.handlers.borrow_mut().onopen.take();
+        if let Some(mut handler) = handler {
+            handler();
+        }
+    }
+
+    fn hang_up(&self) {
+        if self.closed.replace(true) {
+            return;
+        }
+
+        let handler = self.handlers.borrow_mut().onclose.take();
+        *self.handlers.borrow_mut() = Handlers::default();
+        if let Some(mut handler) = handler {
+            handler();
+        }
+    }
+}
+
+// Both ends of a pipe living in the same process. Packets go through the
+// clock, so they arrive once it runs whatever is due, never mid-handler.
+pub struct LoopbackTransport {
+    own: Rc<End>,
+    peer: Rc<End>,
+    clock: Rc<dyn Clock>,
+}
+
+impl fmt::Debug for LoopbackTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LoopbackTransport")
+            .field("closed", &self.own.closed.get())
+            .finish()
+    }
+}
+
+// Two linked connections; whatever one sends, the other receives
+pub fn pair(clock: Rc<dyn Clock>) -> (Connection, Connection) {
+    let (a, b) = (Rc::new(End::default()), Rc::new(End::default()));
+    let first = LoopbackTransport {
+        own: a.clone(),
+        peer: b.clone(),
+        clock: clock.clone(),
+    };
+    let second = LoopbackTransport {
+        own: b,
+        peer: a,
+        clock,
+    };
+    (Connection::new(first), Connection::new(second))
+}
+
+impl Transport for LoopbackTransport {
+    fn send(&self, packet: Buffer) {
+        if self.own.closed.get() {
+            return;
+        }
+
+        let peer = self.peer.clone();
+        self.clock
+            .schedule(self.clock.now(), Box::new(move || peer.deliver(packet)));
+    }
+
+    fn close(&self) {
+        if self.own.closed.replace(true) {
+            return;
+        }
+        *self.own.handlers.borrow_mut() = Handlers::default();
+
+        let peer = self.peer.clone();
+        self.clock
+            .schedule(self.clock.now(), Box::new(move || peer.hang_up()));
+    }
+
+    fn set_onopen(&self, handler: Box<dyn FnMut()>) {
+        // Always open already; still fired asynchronously, like the others
+        self.own.handlers.borrow_mut().onopen = Some(handler);
+        let own = self.own.clone();
+        self.clock
+            .schedule(self.clock.now(), Box::new(move || own.open()));
+    }
+
+    fn set_onmessage(&self, handler: Box<dyn FnMut(Buffer)>) {
+        self.own.handlers.borrow_mut().onmessage = Some(handler);
+    }
+
+    fn set_onclose(&self, handler: Box<dyn FnMut()>) {
+        self.own.handlers.borrow_mut().onclose = Some(handler);
+    }
+}
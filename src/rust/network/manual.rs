@@ -0,0 +1,32 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use miniz_oxide::{deflate::compress_to_vec, inflate::decompress_to_vec_with_limit};
+use serde::{Deserialize, Serialize};
+
+use super::p2p::IceCandidate;
+
+// Decompressed codes can't be bigger than this
+const MAX_CODE_SIZE: usize = 64 * 1024;
+
+// Everything a peer needs to connect to us, exchanged by hand
+// when there's no signalling server around
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ManualCode {
+    pub is_offer: bool,
+    pub sdp: String,
+    pub ice: Vec<IceCandidate>,
+}
+
+impl ManualCode {
+    pub fn encode(&self) -> String {
+        let json = serde_json::to_vec(self).unwrap();
+        URL_SAFE_NO_PAD.encode(compress_to_vec(&json, 9))
+    }
+
+    pub fn decode(code: &str) -> Option<Self> {
+        // Pasted codes may have been wrapped by chat apps
+        let code: String = code.split_whitespace().collect();
+        let data = URL_SAFE_NO_PAD.decode(code).ok()?;
+        let json = decompress_to_vec_with_limit(&data, MAX_CODE_SIZE).ok()?;
+        serde_json::from_slice(&json).ok()
+    }
+}
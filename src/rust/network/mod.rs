@@ -1,8 +1,19 @@
-pub mod buffer;
+mod auth;
 mod client;
 mod connector;
+mod error;
+mod lobby;
+#[cfg(feature = "testing")]
+pub mod loopback;
+mod manual;
 pub mod p2p;
-pub mod packet;
+mod relay;
 mod signal;
+#[cfg(feature = "testing")]
+pub mod simulator;
+pub mod transport;
 
+pub use chessagon_core::{buffer, packet, signalling};
 pub use client::Client;
+pub use error::NetError;
+pub use signal::PublicRoom;
@@ -1,56 +1,71 @@
 use std::{cell::RefCell, rc::Rc};
 
+use futures_channel::mpsc::TryRecvError;
 use js_sys::{Array, Reflect, Uint8Array};
 use serde::Serialize;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen_futures::JsFuture;
 use web_sys::{
     Event, MessageEvent, RtcConfiguration, RtcDataChannel, RtcDataChannelEvent, RtcDataChannelInit,
-    RtcDataChannelType, RtcIceCandidate, RtcIceCandidateInit, RtcPeerConnection,
-    RtcPeerConnectionIceEvent, RtcSdpType, RtcSessionDescriptionInit,
+    RtcDataChannelType, RtcIceCandidate, RtcIceCandidateInit, RtcIceConnectionState,
+    RtcPeerConnection, RtcPeerConnectionIceEvent, RtcSdpType, RtcSessionDescriptionInit,
 };
+use web_time::SystemTime;
 
 use super::buffer::Buffer;
+use super::transport::{set_event, Transport};
 
-// Sets an event on a target and forgets about the closure
-// (leaves it up to JS' GC to drop the object)
-macro_rules! set_event {
-    ($target: expr, $handler: expr, $setter: ident) => {
-        let handler = Closure::wrap($handler);
-        $target.$setter(Some(handler.as_ref().unchecked_ref()));
-        handler.forget();
-    };
-}
-
-pub type IceCandidate = (String, Option<String>, Option<u16>);
-
-fn default_ice_servers() -> JsValue {
-    #[derive(Serialize)]
-    struct IceServerConfig {
-        urls: Vec<String>,
-        username: String,
-        credential: String,
-    }
+pub use chessagon_core::signalling::{IceCandidate, IceServer};
 
-    serde_wasm_bindgen::to_value(&[
-        IceServerConfig {
+pub fn default_ice_servers() -> Vec<IceServer> {
+    vec![
+        IceServer {
             urls: vec![
                 "stun:stun1.l.google.com:19302".to_owned(),
                 "stun:global.stun.twilio.com:3478".to_owned(),
             ],
             username: "".to_owned(),
             credential: "".to_owned(),
+            expires: None,
         },
-        IceServerConfig {
+        IceServer {
             urls: vec![
                 "turn:openrelay.metered.ca:80".to_owned(),
                 "turn:openrelay.metered.ca:443".to_owned(),
             ],
             username: "openrelayproject".to_owned(),
             credential: "openrelayproject".to_owned(),
+            expires: None,
         },
-    ])
-    .unwrap()
+    ]
+}
+
+fn ice_config(servers: &[IceServer], now: SystemTime) -> JsValue {
+    #[derive(Serialize)]
+    struct IceServerConfig<'a> {
+        urls: &'a [String],
+        username: &'a str,
+        credential: &'a str,
+    }
+
+    let mut servers: Vec<_> = servers
+        .iter()
+        .filter(|s| !s.is_expired(now))
+        .cloned()
+        .collect();
+    if servers.is_empty() {
+        servers = default_ice_servers();
+    }
+
+    let config: Vec<_> = servers
+        .iter()
+        .map(|s| IceServerConfig {
+            urls: &s.urls,
+            username: &s.username,
+            credential: &s.credential,
+        })
+        .collect();
+    serde_wasm_bindgen::to_value(&config).unwrap()
 }
 
 fn ser_candidate(candidate: &RtcIceCandidate) -> IceCandidate {
@@ -62,23 +77,26 @@ fn ser_candidate(candidate: &RtcIceCandidate) -> IceCandidate {
 }
 
 fn de_candidate(candidate: &IceCandidate) -> RtcIceCandidate {
-    let mut init = RtcIceCandidateInit::new(&candidate.0);
-    init.sdp_mid(candidate.1.as_deref());
-    init.sdp_m_line_index(candidate.2);
+    let init = RtcIceCandidateInit::new(&candidate.0);
+    init.set_sdp_mid(candidate.1.as_deref());
+    init.set_sdp_m_line_index(candidate.2);
     RtcIceCandidate::new(&init).expect("couldn't deserialize ice candidate")
 }
 
+// WebRTC data channel to the peer
 #[derive(Clone, Debug)]
-pub struct Connection {
+pub struct PeerConnection {
     conn: RtcPeerConnection,
     channel: RtcDataChannel,
     ice_rx: Rc<RefCell<Option<futures_channel::mpsc::UnboundedReceiver<Option<RtcIceCandidate>>>>>,
 }
 
-impl Connection {
-    pub fn new() -> Self {
-        let mut conf = RtcConfiguration::new();
-        conf.ice_servers(&default_ice_servers());
+impl PeerConnection {
+    // Falls back to the default servers if none of the given ones are
+    // usable at `now`
+    pub fn new(ice_servers: &[IceServer], now: SystemTime) -> Self {
+        let conf = RtcConfiguration::new();
+        conf.set_ice_servers(&ice_config(ice_servers, now));
         let conn = RtcPeerConnection::new_with_configuration(&conf)
             .expect("can't create RtcPeerConnection");
 
@@ -86,53 +104,21 @@ impl Connection {
         let handler: Box<dyn FnMut(_)> = Box::new(move |_event: JsValue| {});
         set_event!(conn, handler, set_oniceconnectionstatechange);
 
-        let mut conf = RtcDataChannelInit::new();
-        conf.id(0);
-        conf.negotiated(true);
+        let conf = RtcDataChannelInit::new();
+        conf.set_id(0);
+        conf.set_negotiated(true);
         let channel = conn.create_data_channel_with_data_channel_dict("chessagon", &conf);
         channel.set_binary_type(RtcDataChannelType::Arraybuffer);
 
-        Connection {
+        PeerConnection {
             conn,
             channel,
             ice_rx: Default::default(),
         }
     }
 
-    pub fn close(&self) {
-        self.channel.set_onopen(None);
-        self.channel.set_onmessage(None);
-        self.channel.set_onclose(None);
-        self.channel.close();
-        self.conn.close();
-    }
-
-    pub fn send(&self, packet: Buffer) {
-        // TODO: Handle error on channel.send
-        let packet: Vec<u8> = packet.into();
-        let _ = self.channel.send_with_u8_array(packet.as_slice());
-    }
-
-    pub fn set_onopen(&self, mut handler: Box<dyn FnMut()>) {
-        let handler: Box<dyn FnMut(_)> = Box::new(move |_event: RtcDataChannelEvent| {
-            handler();
-        });
-        set_event!(self.channel, handler, set_onopen);
-    }
-
-    pub fn set_onmessage(&self, mut handler: Box<dyn FnMut(Buffer)>) {
-        let handler: Box<dyn FnMut(_)> = Box::new(move |event: MessageEvent| {
-            let data = Uint8Array::new(&event.data()).to_vec();
-            handler(data.into());
-        });
-        set_event!(self.channel, handler, set_onmessage);
-    }
-
-    pub fn set_onclose(&self, mut handler: Box<dyn FnMut()>) {
-        let handler: Box<dyn FnMut(_)> = Box::new(move |_event: Event| {
-            handler();
-        });
-        set_event!(self.channel, handler, set_onclose);
+    pub fn ice_failed(&self) -> bool {
+        self.conn.ice_connection_state() == RtcIceConnectionState::Failed
     }
 
     fn listen_ice_candidates(&self) {
@@ -156,8 +142,13 @@ impl Connection {
         };
 
         let mut candidates = vec![];
-        while let Ok(opt) = rx.try_next() {
-            match opt.flatten() {
+        loop {
+            let opt = match rx.try_recv() {
+                Ok(opt) => opt,
+                Err(TryRecvError::Closed) => None,
+                Err(TryRecvError::Empty) => break,
+            };
+            match opt {
                 Some(ice) => candidates.push(ser_candidate(&ice)),
                 None => {
                     // Push end of list
@@ -205,8 +196,8 @@ impl Connection {
     }
 
     pub async fn set_remote(&self, ty: RtcSdpType, sdp: String) -> Result<(), JsValue> {
-        let mut desc = RtcSessionDescriptionInit::new(ty);
-        desc.sdp(&sdp);
+        let desc = RtcSessionDescriptionInit::new(ty);
+        desc.set_sdp(&sdp);
         JsFuture::from(self.conn.set_remote_description(&desc)).await?;
 
         Ok(())
@@ -228,10 +219,48 @@ impl Connection {
         };
         // Set connection's description
         // This initiates ICE candidate gathering
-        let mut desc = RtcSessionDescriptionInit::new(ty);
-        desc.sdp(&sdp);
+        let desc = RtcSessionDescriptionInit::new(ty);
+        desc.set_sdp(&sdp);
         JsFuture::from(self.conn.set_local_description(&desc)).await?;
 
         Ok(sdp)
     }
 }
+
+impl Transport for PeerConnection {
+    fn close(&self) {
+        self.channel.set_onopen(None);
+        self.channel.set_onmessage(None);
+        self.channel.set_onclose(None);
+        self.channel.close();
+        self.conn.close();
+    }
+
+    fn send(&self, packet: Buffer) {
+        // TODO: Handle error on channel.send
+        let packet: Vec<u8> = packet.into();
+        let _ = self.channel.send_with_u8_array(packet.as_slice());
+    }
+
+    fn set_onopen(&self, mut handler: Box<dyn FnMut()>) {
+        let handler: Box<dyn FnMut(_)> = Box::new(move |_event: RtcDataChannelEvent| {
+            handler();
+        });
+        set_event!(self.channel, handler, set_onopen);
+    }
+
+    fn set_onmessage(&self, mut handler: Box<dyn FnMut(Buffer)>) {
+        let handler: Box<dyn FnMut(_)> = Box::new(move |event: MessageEvent| {
+            let data = Uint8Array::new(&event.data()).to_vec();
+            handler(data.into());
+        });
+        set_event!(self.channel, handler, set_onmessage);
+    }
+
+    fn set_onclose(&self, mut handler: Box<dyn FnMut()>) {
+        let handler: Box<dyn FnMut(_)> = Box::new(move |_event: Event| {
+            handler();
+        });
+        set_event!(self.channel, handler, set_onclose);
+    }
+}
@@ -0,0 +1,100 @@
+use std::{cell::RefCell, fmt, rc::Rc};
+
+use js_sys::{ArrayBuffer, Uint8Array};
+use wasm_bindgen::prelude::*;
+use web_sys::{BinaryType, CloseEvent, MessageEvent, WebSocket};
+
+use super::buffer::Buffer;
+use super::transport::{set_event, Transport};
+
+// Text frame the relay sends once both peers are on the line
+const OPEN_SIGNAL: &str = "open";
+
+type Handler<T> = Rc<RefCell<Option<Box<dyn FnMut(T)>>>>;
+
+// Both peers derive the same session from the host's offer
+pub fn relay_session(offer: &str) -> String {
+    let hash = offer.bytes().fold(0xcbf29ce484222325_u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    });
+    format!("{:016x}", hash)
+}
+
+// Tunnels packets through a WebSocket relay, for networks where WebRTC
+// can't get through. Both peers connect to the same session and the
+// relay forwards binary frames between them.
+#[derive(Clone)]
+pub struct RelayConnection {
+    ws: WebSocket,
+    onopen: Handler<()>,
+    onmessage: Handler<Buffer>,
+}
+
+impl fmt::Debug for RelayConnection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RelayConnection")
+            .field("url", &self.ws.url())
+            .finish()
+    }
+}
+
+impl RelayConnection {
+    pub fn new(url: &str, session: &str) -> Result<Self, JsValue> {
+        let ws = WebSocket::new(&format!("{}/{}", url.trim_end_matches('/'), session))?;
+        ws.set_binary_type(BinaryType::Arraybuffer);
+
+        let onopen: Handler<()> = Default::default();
+        let onmessage: Handler<Buffer> = Default::default();
+
+        let (open, message) = (onopen.clone(), onmessage.clone());
+        let handler: Box<dyn FnMut(_)> = Box::new(move |event: MessageEvent| {
+            let data = event.data();
+            if data.as_string().as_deref() == Some(OPEN_SIGNAL) {
+                if let Some(handler) = open.borrow_mut().as_mut() {
+                    handler(());
+                }
+            } else if data.is_instance_of::<ArrayBuffer>() {
+                let data = Uint8Array::new(&data).to_vec();
+                if let Some(handler) = message.borrow_mut().as_mut() {
+                    handler(data.into());
+                }
+            }
+        });
+        set_event!(ws, handler, set_onmessage);
+
+        Ok(RelayConnection {
+            ws,
+            onopen,
+            onmessage,
+        })
+    }
+}
+
+impl Transport for RelayConnection {
+    fn send(&self, packet: Buffer) {
+        let packet: Vec<u8> = packet.into();
+        let _ = self.ws.send_with_u8_array(packet.as_slice());
+    }
+
+    fn close(&self) {
+        self.onopen.borrow_mut().take();
+        self.onmessage.borrow_mut().take();
+        self.ws.set_onclose(None);
+        let _ = self.ws.close();
+    }
+
+    fn set_onopen(&self, mut handler: Box<dyn FnMut()>) {
+        *self.onopen.borrow_mut() = Some(Box::new(move |_| handler()));
+    }
+
+    fn set_onmessage(&self, handler: Box<dyn FnMut(Buffer)>) {
+        *self.onmessage.borrow_mut() = Some(handler);
+    }
+
+    fn set_onclose(&self, mut handler: Box<dyn FnMut()>) {
+        let handler: Box<dyn FnMut(_)> = Box::new(move |_event: CloseEvent| {
+            handler();
+        });
+        set_event!(self.ws, handler, set_onclose);
+    }
+}
@@ -1,46 +1,81 @@
-use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{future::Future, pin::Pin, rc::Rc};
+
+use futures::future::{select, Either};
+
+use serde::de::DeserializeOwned;
 use wasm_bindgen::JsValue;
 use wasm_bindgen_futures::JsFuture;
 use web_sys::{window, Request, RequestInit, RequestMode, Response};
-use web_time::{Duration, Instant, SystemTime};
+use web_time::{Duration, Instant};
 
+use crate::clock::Clock;
 use crate::utils::wait_until;
 
+use super::error::NetError;
 use super::p2p::IceCandidate;
 
-#[derive(Serialize, Deserialize)]
-pub enum Signal {
-    // Sets the peer's SDP
-    SetSDP(String),
-    // Adds an ICE candidate, as offered by the peer
-    AddCandidate(IceCandidate),
-    // Joins a room
-    JoinRoom(String),
-    // When to attempt peer connection
-    ConnectAt(SystemTime),
-    // When to poll next
-    NextPoll(SystemTime),
-    // Signal service
-    SetService(String),
+pub use chessagon_core::signalling::{IdentResponse, PublicRoom, RoomClaim, RoomInfo, Signal};
+
+pub const DEFAULT_SERVER: &str = "https://signalling.tocu.workers.dev";
+
+// How long a single request may take
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(8);
+// Backoff between retries of a failed request
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(8);
+const MAX_RETRIES: u32 = 5;
+// Poll delay when the server doesn't send a hint
+const DEFAULT_POLL_DELAY: Duration = Duration::from_secs(10);
+
+pub type SignalFuture<T> = Pin<Box<dyn Future<Output = Result<T, NetError>>>>;
+
+// Carries signals between peers until they can talk directly
+pub trait SignalBackend {
+    // Generates a token
+    fn ident(&self) -> SignalFuture<IdentResponse>;
+    // Sends & receives updates. Retries of a poll reuse its `seq`, so the
+    // server can answer them without applying the updates twice.
+    fn poll(&self, token: &str, seq: u32, updates: Vec<Signal>) -> SignalFuture<Vec<Signal>>;
 }
 
-#[derive(Serialize, Deserialize)]
-struct IdentResponse {
-    token: String,
+// Talks to a signalling server over its `/ident` + `/poll` JSON API
+pub struct HttpBackend {
+    url: String,
 }
 
-async fn exec<T>(url: &str, auth: Option<&str>, signals: Option<Vec<Signal>>) -> Result<T, JsValue>
+impl HttpBackend {
+    pub fn new(url: &str) -> Self {
+        HttpBackend {
+            url: url.trim_end_matches('/').to_string(),
+        }
+    }
+}
+
+impl SignalBackend for HttpBackend {
+    fn ident(&self) -> SignalFuture<IdentResponse> {
+        let url = self.url.clone();
+        Box::pin(async move { ident(&url).await })
+    }
+
+    fn poll(&self, token: &str, seq: u32, updates: Vec<Signal>) -> SignalFuture<Vec<Signal>> {
+        let url = self.url.clone();
+        let token = token.to_string();
+        Box::pin(async move { poll(&url, &token, seq, updates).await })
+    }
+}
+
+async fn exec<T>(url: &str, auth: Option<&str>, signals: Option<Vec<Signal>>) -> Result<T, NetError>
 where
     T: DeserializeOwned,
 {
-    let mut opts = RequestInit::new();
-    opts.method("POST");
-    opts.mode(RequestMode::Cors);
+    let opts = RequestInit::new();
+    opts.set_method("POST");
+    opts.set_mode(RequestMode::Cors);
 
     if let Some(ref signals) = signals {
-        opts.body(Some(&JsValue::from_str(
+        opts.set_body(&JsValue::from_str(
             &serde_json::ser::to_string(signals).unwrap(),
-        )));
+        ));
     }
 
     let req = Request::new_with_str_and_init(url, &opts)?;
@@ -53,37 +88,70 @@ where
 
     let window = window().unwrap();
     let promise = window.fetch_with_request(&req);
-    let resp = JsFuture::from(promise).await?;
+    let resp = JsFuture::from(promise)
+        .await
+        .map_err(|e| NetError::Http(format!("{:?}", e)))?;
     let resp: Response = resp.into();
     if resp.status() != 200 {
-        return Err(JsValue::from_str("signal process error"));
+        return Err(NetError::Status(resp.status()));
     }
 
-    let body = JsFuture::from(resp.text()?).await?;
-    let body = body.as_string().unwrap();
+    let body = JsFuture::from(resp.text()?)
+        .await
+        .map_err(|e| NetError::Http(format!("{:?}", e)))?;
+    let body = body
+        .as_string()
+        .ok_or(NetError::Http("response isn't text".to_owned()))?;
 
-    Ok(serde_json::de::from_str(&body).unwrap())
+    serde_json::de::from_str(&body).map_err(|e| NetError::Http(format!("invalid response: {}", e)))
 }
 
-async fn ident(url: &str) -> Result<String, JsValue> {
+async fn ident(url: &str) -> Result<IdentResponse, NetError> {
     // Generates a token
-    let resp: IdentResponse = exec(&format!("{}/ident", url), None, None).await?;
-    Ok(resp.token)
+    exec(&format!("{}/ident", url), None, None).await
 }
 
-async fn poll(url: &str, auth: &str, updates: Vec<Signal>) -> Result<Vec<Signal>, JsValue> {
+async fn poll(
+    url: &str,
+    auth: &str,
+    seq: u32,
+    updates: Vec<Signal>,
+) -> Result<Vec<Signal>, NetError> {
     // Sends & receives updates
-    exec(&format!("{}/poll", url), Some(auth), Some(updates)).await
+    exec(
+        &format!("{}/poll?seq={}", url, seq),
+        Some(auth),
+        Some(updates),
+    )
+    .await
+}
+
+async fn with_timeout<T>(clock: &dyn Clock, fut: SignalFuture<T>) -> Result<T, NetError> {
+    let timer = Box::pin(wait_until(clock, clock.now() + REQUEST_TIMEOUT));
+    match select(fut, timer).await {
+        Either::Left((result, _)) => result,
+        Either::Right(_) => Err(NetError::Timeout),
+    }
 }
 
 pub struct SignalClient {
-    url: String,
+    backend: Rc<dyn SignalBackend>,
+    pub clock: Rc<dyn Clock>,
     token: Option<String>,
+    // Give up once this passes, if set
+    pub deadline: Option<Instant>,
     pub room: String,
+    pub room_full: bool,
+    // Set when a new room was opened for us
+    pub secret: Option<String>,
     pub peer_sdp: Option<String>,
     pub peer_ice: Vec<IceCandidate>,
     pub connect_at: Option<Instant>,
     signal_queue: Vec<Signal>,
+    // Number of the latest poll
+    seq: u32,
+    // Last poll's signals, until the server acknowledges them
+    unacked: Option<(u32, Vec<Signal>)>,
     next_poll: Instant,
     sent_all_ice: bool,
     recv_all_ice: bool,
@@ -91,16 +159,22 @@ pub struct SignalClient {
 }
 
 impl SignalClient {
-    pub fn new(url: &str) -> Self {
+    pub fn new(backend: Rc<dyn SignalBackend>, clock: Rc<dyn Clock>) -> Self {
         SignalClient {
-            url: url.to_string(),
+            backend,
+            next_poll: clock.now(),
+            clock,
             token: None,
+            deadline: None,
             room: "".to_string(),
+            room_full: false,
+            secret: None,
             peer_sdp: None,
             peer_ice: vec![],
             connect_at: None,
             signal_queue: vec![],
-            next_poll: Instant::now(),
+            seq: 0,
+            unacked: None,
             sent_all_ice: false,
             recv_all_ice: false,
             sent_service: false,
@@ -113,6 +187,12 @@ impl SignalClient {
                 Signal::JoinRoom(r) => {
                     self.room = r;
                 }
+                Signal::RoomFull(_) => {
+                    self.room_full = true;
+                }
+                Signal::RoomSecret(s) => {
+                    self.secret = Some(s);
+                }
                 Signal::SetSDP(s) => {
                     self.peer_sdp = Some(s.to_string());
                 }
@@ -123,45 +203,111 @@ impl SignalClient {
                     self.peer_ice.push(c);
                 }
                 Signal::ConnectAt(d) => {
-                    let d = d.duration_since(SystemTime::now()).unwrap_or_default();
-                    self.connect_at = Some(Instant::now() + d);
+                    let d = d
+                        .duration_since(self.clock.system_now())
+                        .unwrap_or_default();
+                    self.connect_at = Some(self.clock.now() + d);
                 }
                 Signal::NextPoll(d) => {
-                    let d = d.duration_since(SystemTime::now()).unwrap_or_default();
-                    self.next_poll = Instant::now() + d;
+                    let d = d
+                        .duration_since(self.clock.system_now())
+                        .unwrap_or_default();
+                    self.next_poll = self.clock.now() + d;
                 }
-                Signal::SetService(_) => {}
+                Signal::HostRoom(_)
+                | Signal::HostSpectators(_)
+                | Signal::WatchRoom(_)
+                | Signal::SetService(_)
+                | Signal::Advertise(_)
+                | Signal::ListRooms
+                | Signal::Rooms(_) => {}
             };
         }
     }
 
+    pub fn past_deadline(&self) -> bool {
+        self.deadline.is_some_and(|d| self.clock.now() >= d)
+    }
+
     pub async fn wait_for_poll(&mut self) {
-        wait_until(self.next_poll).await;
+        let at = match self.deadline {
+            Some(deadline) => self.next_poll.min(deadline),
+            None => self.next_poll,
+        };
+        wait_until(self.clock.as_ref(), at).await;
         // Safeguard: default delay
-        self.next_poll = Instant::now() + Duration::from_secs(10);
+        self.next_poll = self.clock.now() + DEFAULT_POLL_DELAY;
+    }
+
+    // Runs a request, retrying transient failures with exponential backoff
+    async fn retry<T, F>(&self, mut call: F) -> Result<T, NetError>
+    where
+        F: FnMut() -> SignalFuture<T>,
+    {
+        let mut delay = RETRY_DELAY;
+        let mut retries = 0;
+        loop {
+            let err = match with_timeout(self.clock.as_ref(), call()).await {
+                Ok(value) => return Ok(value),
+                Err(e) => e,
+            };
+
+            let retry_at = self.clock.now() + delay;
+            if !err.is_transient()
+                || retries >= MAX_RETRIES
+                || self.deadline.is_some_and(|d| retry_at >= d)
+            {
+                return Err(err);
+            }
+
+            wait_until(self.clock.as_ref(), retry_at).await;
+            delay = (delay * 2).min(MAX_RETRY_DELAY);
+            retries += 1;
+        }
     }
 
     pub fn can_poll(&self) -> bool {
         !self.sent_all_ice || !self.recv_all_ice
     }
 
-    pub async fn poll(&mut self) -> Result<(), JsValue> {
+    // Gets a token, along with the ICE servers the service suggests
+    pub async fn ident(&mut self) -> Result<IdentResponse, NetError> {
+        let backend = self.backend.clone();
+        let resp = self.retry(|| backend.ident()).await?;
+        self.token = Some(resp.token.clone());
+        Ok(resp)
+    }
+
+    pub async fn poll(&mut self) -> Result<(), NetError> {
         if !self.can_poll() {
             return Ok(());
         }
 
         // Ident if not already
-        let token = match self.token.as_ref() {
-            Some(t) => t,
-            None => {
-                let token = ident(&self.url).await?;
-                self.token = Some(token);
+        if self.token.is_none() {
+            self.ident().await?;
+        }
+        let token = self.token.clone().unwrap();
 
-                self.token.as_ref().unwrap()
-            }
+        // A poll that got no answer goes again as it was
+        let (seq, signals) = match self.unacked.take() {
+            Some(batch) => batch,
+            None => self.next_batch(),
         };
+        self.unacked = Some((seq, signals.clone()));
+
+        let backend = self.backend.clone();
+        let signals = self
+            .retry(|| backend.poll(&token, seq, signals.clone()))
+            .await?;
+        self.unacked = None;
+        self.handle_signals(signals);
 
-        // Move all values from queue
+        Ok(())
+    }
+
+    // Moves all queued signals into a new poll
+    fn next_batch(&mut self) -> (u32, Vec<Signal>) {
         let mut signals: Vec<_> = self.signal_queue.drain(0..).collect();
         if signals
             .iter()
@@ -177,16 +323,61 @@ impl SignalClient {
             signals.push(Signal::SetService("chessagon".to_owned()));
             self.sent_service = true;
         }
-        let signals = poll(&self.url, token, signals).await?;
-        self.handle_signals(signals);
 
-        Ok(())
+        self.seq = self.seq.wrapping_add(1);
+        (self.seq, signals)
+    }
+
+    // Fetches the rooms hosts made public
+    pub async fn list_rooms(&mut self) -> Result<Vec<PublicRoom>, NetError> {
+        if self.token.is_none() {
+            self.ident().await?;
+        }
+        let token = self.token.clone().unwrap();
+
+        self.seq = self.seq.wrapping_add(1);
+        let seq = self.seq;
+        let backend = self.backend.clone();
+        let signals = self
+            .retry(|| backend.poll(&token, seq, vec![Signal::ListRooms]))
+            .await?;
+
+        let mut rooms = vec![];
+        for signal in signals {
+            match signal {
+                Signal::Rooms(list) => rooms = list,
+                Signal::NextPoll(d) => {
+                    let d = d
+                        .duration_since(self.clock.system_now())
+                        .unwrap_or_default();
+                    self.next_poll = self.clock.now() + d;
+                }
+                _ => {}
+            }
+        }
+        Ok(rooms)
+    }
+
+    pub fn send_advertise(&mut self, info: RoomInfo) {
+        self.signal_queue.push(Signal::Advertise(info));
     }
 
     pub fn send_join_room(&mut self, code: String) {
         self.signal_queue.push(Signal::JoinRoom(code));
     }
 
+    pub fn send_host_room(&mut self, claim: RoomClaim) {
+        self.signal_queue.push(Signal::HostRoom(claim));
+    }
+
+    pub fn send_watch_room(&mut self, code: String) {
+        self.signal_queue.push(Signal::WatchRoom(code));
+    }
+
+    pub fn send_host_spectators(&mut self, claim: RoomClaim) {
+        self.signal_queue.push(Signal::HostSpectators(claim));
+    }
+
     pub fn send_sdp(&mut self, sdp: String) {
         self.signal_queue.push(Signal::SetSDP(sdp));
     }
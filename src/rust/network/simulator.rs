@@ -0,0 +1,154 @@
+use std::{
+    cell::{Cell, RefCell},
+    fmt,
+    rc::Rc,
+};
+
+use rand::{rngs::SmallRng, Rng};
+use web_time::Duration;
+
+use super::buffer::Buffer;
+use super::transport::{Connection, Transport};
+use crate::clock::Clock;
+
+type Handler = Rc<RefCell<Option<Box<dyn FnMut(Buffer)>>>>;
+
+// How bad a link is, both ways. The default is a perfect one.
+#[derive(Clone, Debug, Default)]
+pub struct Conditions {
+    pub latency: Duration,
+    // Extra delay, anywhere between none and this much
+    pub jitter: Duration,
+    // Chances from 0 to 1, rolled for every packet
+    pub duplicate: f64,
+    // Held back for another full delay, so the ones behind it overtake it
+    pub reorder: f64,
+    pub drop: f64,
+}
+
+// Puts a bad network between a connection and whoever uses it. Packets
+// go through the clock, so tests decide when they arrive.
+pub struct Simulator {
+    conditions: RefCell<Conditions>,
+    clock: Rc<dyn Clock>,
+    rng: RefCell<SmallRng>,
+}
+
+impl Simulator {
+    pub fn new(conditions: Conditions, clock: Rc<dyn Clock>, rng: SmallRng) -> Rc<Self> {
+        Rc::new(Simulator {
+            conditions: RefCell::new(conditions),
+            clock,
+            rng: RefCell::new(rng),
+        })
+    }
+
+    // Applies to packets sent from now on
+    pub fn set_conditions(&self, conditions: Conditions) {
+        *self.conditions.borrow_mut() = conditions;
+    }
+
+    pub fn wrap(self: &Rc<Self>, inner: Connection) -> Connection {
+        Connection::new(SimulatedTransport {
+            inner,
+            sim: self.clone(),
+            closed: Rc::new(Cell::new(false)),
+        })
+    }
+
+    fn delay(&self, conditions: &Conditions) -> Duration {
+        let mut rng = self.rng.borrow_mut();
+        let mut delay = conditions.latency + conditions.jitter.mul_f64(rng.gen::<f64>());
+        if rng.gen_bool(conditions.reorder) {
+            delay += conditions.latency + conditions.jitter;
+        }
+        delay
+    }
+
+    // Schedules every copy of the packet that makes it through
+    fn pass<F>(&self, packet: Buffer, deliver: F)
+    where
+        F: Fn(Buffer) + Clone + 'static,
+    {
+        let conditions = self.conditions.borrow().clone();
+        let copies = {
+            let mut rng = self.rng.borrow_mut();
+            if rng.gen_bool(conditions.drop) {
+                return;
+            }
+            if rng.gen_bool(conditions.duplicate) {
+                2
+            } else {
+                1
+            }
+        };
+
+        let packet: Vec<u8> = packet.into();
+        for _ in 0..copies {
+            let at = self.clock.now() + self.delay(&conditions);
+            let (packet, deliver) = (packet.clone(), deliver.clone());
+            self.clock
+                .schedule(at, Box::new(move || deliver(packet.into())));
+        }
+    }
+}
+
+struct SimulatedTransport {
+    inner: Connection,
+    sim: Rc<Simulator>,
+    // Anything still in flight is lost with the connection
+    closed: Rc<Cell<bool>>,
+}
+
+impl fmt::Debug for SimulatedTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SimulatedTransport")
+            .field("inner", &self.inner)
+            .field("conditions", &self.sim.conditions.borrow())
+            .finish()
+    }
+}
+
+impl Transport for SimulatedTransport {
+    fn send(&self, packet: Buffer) {
+        let (inner, closed) = (self.inner.clone(), self.closed.clone());
+        self.sim.pass(packet, move |packet| {
+            if !closed.get() {
+                inner.send(packet);
+            }
+        });
+    }
+
+    fn close(&self) {
+        self.closed.set(true);
+        self.inner.close();
+    }
+
+    fn set_onopen(&self, handler: Box<dyn FnMut()>) {
+        self.inner.set_onopen(handler);
+    }
+
+    fn set_onmessage(&self, handler: Box<dyn FnMut(Buffer)>) {
+        let handler: Handler = Rc::new(RefCell::new(Some(handler)));
+        let (sim, closed) = (self.sim.clone(), self.closed.clone());
+        self.inner.set_onmessage(Box::new(move |packet| {
+            let (handler, closed) = (handler.clone(), closed.clone());
+            sim.pass(packet, move |packet| {
+                if closed.get() {
+                    return;
+                }
+
+                // Taken out while it runs, in case it gets replaced meanwhile
+                let taken = handler.borrow_mut().take();
+                if let Some(mut taken) = taken {
+                    taken(packet);
+                    handler.borrow_mut().get_or_insert(taken);
+                }
+            });
+        }));
+    }
+
+    fn set_onclose(&self, handler: Box<dyn FnMut()>) {
+        self.inner.set_onclose(handler);
+    }
+}
@@ -0,0 +1,71 @@
+use std::{fmt::Debug, rc::Rc};
+
+use super::buffer::Buffer;
+
+// Sets an event on a target and forgets about the closure
+// (leaves it up to JS' GC to drop the object)
+macro_rules! set_event {
+    ($target: expr, $handler: expr, $setter: ident) => {
+        let handler = Closure::wrap($handler);
+        $target.$setter(Some(handler.as_ref().unchecked_ref()));
+        handler.forget();
+    };
+}
+pub(crate) use set_event;
+
+// Anything able to carry packets between two peers
+pub trait Transport: Debug {
+    fn send(&self, packet: Buffer);
+    // Closes the transport without triggering onclose
+    fn close(&self);
+    fn set_onopen(&self, handler: Box<dyn FnMut()>);
+    fn set_onmessage(&self, handler: Box<dyn FnMut(Buffer)>);
+    fn set_onclose(&self, handler: Box<dyn FnMut()>);
+}
+
+// Goes nowhere. Stands in for connections that can't be recreated.
+#[derive(Debug)]
+struct Detached;
+
+impl Transport for Detached {
+    fn send(&self, _packet: Buffer) {}
+    fn close(&self) {}
+    fn set_onopen(&self, _handler: Box<dyn FnMut()>) {}
+    fn set_onmessage(&self, _handler: Box<dyn FnMut(Buffer)>) {}
+    fn set_onclose(&self, _handler: Box<dyn FnMut()>) {}
+}
+
+// Shared handle to an open (or opening) transport
+#[derive(Clone, Debug)]
+pub struct Connection(Rc<dyn Transport>);
+
+impl Connection {
+    pub fn new<T: Transport + 'static>(transport: T) -> Self {
+        Connection(Rc::new(transport))
+    }
+
+    // A connection to nobody, e.g. for replayed events
+    pub fn detached() -> Self {
+        Connection::new(Detached)
+    }
+
+    pub fn send(&self, packet: Buffer) {
+        self.0.send(packet);
+    }
+
+    pub fn close(&self) {
+        self.0.close();
+    }
+
+    pub fn set_onopen(&self, handler: Box<dyn FnMut()>) {
+        self.0.set_onopen(handler);
+    }
+
+    pub fn set_onmessage(&self, handler: Box<dyn FnMut(Buffer)>) {
+        self.0.set_onmessage(handler);
+    }
+
+    pub fn set_onclose(&self, handler: Box<dyn FnMut()>) {
+        self.0.set_onclose(handler);
+    }
+}
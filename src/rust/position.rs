@@ -0,0 +1,115 @@
+use chessagon_core::{
+    notation::{hex_name, parse_hex},
+    piece::Piece,
+    position::{parse_kind, Position, PositionError},
+};
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+// The rules on their own, for frontends that don't want the rest of the
+// game: no setup(), no events, just a position to query and play on.
+#[wasm_bindgen(js_name = Position)]
+#[derive(Default)]
+pub struct JsPosition(Position);
+
+#[derive(Serialize)]
+struct Hex {
+    hex: String,
+    q: u8,
+    r: u8,
+}
+
+impl Hex {
+    fn new(q: u8, r: u8) -> Self {
+        Hex {
+            hex: hex_name(q, r).unwrap_or_default(),
+            q,
+            r,
+        }
+    }
+}
+
+fn error(err: PositionError) -> JsError {
+    JsError::new(&err.to_string())
+}
+
+fn hex(name: &str) -> Result<(u8, u8), JsError> {
+    parse_hex(name).ok_or_else(|| JsError::new(&format!("invalid hex: {}", name)))
+}
+
+fn to_js<T: Serialize>(value: &T) -> JsValue {
+    serde_wasm_bindgen::to_value(value).unwrap()
+}
+
+#[wasm_bindgen(js_class = Position)]
+impl JsPosition {
+    // The usual starting position
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[wasm_bindgen(js_name = fromNotation)]
+    pub fn from_notation(text: &str) -> Result<JsPosition, JsError> {
+        Position::parse(text).map(JsPosition).map_err(error)
+    }
+
+    // Same format fromNotation reads
+    pub fn notation(&self) -> String {
+        self.0.notation()
+    }
+
+    // Pieces still on the board
+    pub fn pieces(&self) -> JsValue {
+        let pieces: Vec<&Piece> = self
+            .0
+            .board
+            .pieces
+            .iter()
+            .filter(|p| !p.is_captured())
+            .collect();
+        to_js(&pieces)
+    }
+
+    // Hexes the piece on the given one may move to
+    #[wasm_bindgen(js_name = legalMoves)]
+    pub fn legal_moves(&self, from: &str) -> Result<JsValue, JsError> {
+        let (q, r) = hex(from)?;
+        let moves: Vec<Hex> = self
+            .0
+            .legal_moves(q, r)
+            .into_iter()
+            .map(|(q, r)| Hex::new(q, r))
+            .collect();
+        Ok(to_js(&moves))
+    }
+
+    // Promotion is a piece letter, only needed for pawns reaching the end
+    pub fn play(
+        &mut self,
+        from: &str,
+        to: &str,
+        promotion: Option<String>,
+    ) -> Result<JsValue, JsError> {
+        let promotion = match promotion {
+            Some(letter) => {
+                let mut chars = letter.chars();
+                match (chars.next().and_then(parse_kind), chars.next()) {
+                    (Some(kind), None) => Some(kind),
+                    _ => return Err(error(PositionError::InvalidPromotion)),
+                }
+            }
+            None => None,
+        };
+
+        let played = self
+            .0
+            .play(hex(from)?, hex(to)?, promotion)
+            .map_err(error)?;
+        Ok(to_js(&played))
+    }
+
+    pub fn status(&self) -> JsValue {
+        to_js(&self.0.status())
+    }
+}
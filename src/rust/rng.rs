@@ -0,0 +1,53 @@
+use std::cell::Cell;
+
+use rand::{rngs::SmallRng, RngCore, SeedableRng};
+use web_time::{SystemTime, UNIX_EPOCH};
+
+// Where the game gets its randomness from
+pub trait RngSource {
+    fn new_rng(&self) -> SmallRng;
+
+    // Bytes nobody can guess, for auth challenges. They come from the
+    // browser's crypto.getRandomValues rather than a seeded generator.
+    fn secret(&self) -> [u8; 16] {
+        let mut bytes = [0; 16];
+        getrandom::getrandom(&mut bytes).expect("no secure randomness");
+        bytes
+    }
+}
+
+// Seeded from wall time, different every run
+pub struct TimeSeeded;
+
+impl RngSource for TimeSeeded {
+    fn new_rng(&self) -> SmallRng {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("time travel?")
+            .as_millis() as u64;
+        SmallRng::seed_from_u64(now)
+    }
+}
+
+// Same numbers every run, for tests. Each generator gets the next seed.
+pub struct Seeded(Cell<u64>);
+
+impl Seeded {
+    pub fn new(seed: u64) -> Self {
+        Seeded(Cell::new(seed))
+    }
+}
+
+impl RngSource for Seeded {
+    fn new_rng(&self) -> SmallRng {
+        let seed = self.0.get();
+        self.0.set(seed.wrapping_add(1));
+        SmallRng::seed_from_u64(seed)
+    }
+
+    fn secret(&self) -> [u8; 16] {
+        let mut bytes = [0; 16];
+        self.new_rng().fill_bytes(&mut bytes);
+        bytes
+    }
+}
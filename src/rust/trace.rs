@@ -0,0 +1,63 @@
+use serde::{Deserialize, Serialize};
+use web_time::Instant;
+
+use crate::{glue::Event, Context};
+
+// One event, as the handlers got it
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TraceEntry {
+    // Since the context was set up
+    pub at_ms: u64,
+    // Events still waiting behind this one
+    pub queue_depth: usize,
+    // Raised from outside any handler: player input, timers, the network.
+    // Every other event follows from these.
+    pub external: bool,
+    pub event: serde_json::Value,
+}
+
+pub struct Tracer {
+    started: Instant,
+    entries: Vec<TraceEntry>,
+}
+
+impl Tracer {
+    pub fn new(started: Instant) -> Self {
+        Tracer {
+            started,
+            entries: vec![],
+        }
+    }
+
+    pub fn record(&mut self, now: Instant, evt: &Event, queue_depth: usize, external: bool) {
+        self.entries.push(TraceEntry {
+            at_ms: (now - self.started).as_millis() as u64,
+            queue_depth,
+            external,
+            event: serde_json::to_value(evt).unwrap_or_default(),
+        });
+    }
+
+    pub fn entries(&self) -> &[TraceEntry] {
+        &self.entries
+    }
+}
+
+// Feeds a dumped log's external events to the context, in order, and
+// returns how many there were. Handlers raise the rest again themselves.
+// Use the same kind of seeded randomness, and a clock that only moves when
+// told, or timers will fire on top of the recorded ones.
+pub fn replay(ctx: &Context, log: &str) -> Result<usize, serde_json::Error> {
+    let entries: Vec<TraceEntry> = serde_json::from_str(log)?;
+    let events = entries
+        .into_iter()
+        .filter(|entry| entry.external)
+        .map(|entry| serde_json::from_value::<Event>(entry.event))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let count = events.len();
+    for evt in events {
+        ctx.handle(evt);
+    }
+    Ok(count)
+}
@@ -0,0 +1,239 @@
+#[cfg(feature = "testing")]
+use std::cell::RefCell;
+
+use crate::glue;
+
+// Everything the game tells its frontend
+pub trait Ui {
+    fn set_scene(&self, idx: i8);
+    fn show_chat(&self);
+    fn hide_chat(&self);
+    fn set_player_name(&self, is_self: bool, name: String);
+    fn join_response(&self, resp: String);
+    fn show_manual_code(&self, code: String);
+    fn show_rooms(&self, rooms: String);
+    fn lobby_response(&self, resp: String);
+    fn add_chat_message(&self, kind: u8, slots: Vec<String>);
+    fn set_pieces(&self, pieces: &[u16]);
+    fn move_pieces(&self, pieces: &[u16]);
+    fn highlight(&self, hexes: &[u16]);
+    fn promote_pieces(&self, pieces: &[u16]);
+    fn show_promotion_prompt(&self, color: u8, q: u8, r: u8);
+    fn set_timers(&self, light: u16, dark: u16, active: i8);
+    fn remove_timers(&self);
+    fn add_rtt(&self, rtt: i32);
+    fn set_board_perspective(&self, is_light: bool);
+    fn show_buttons(&self, ids: &[u8]);
+}
+
+// The browser frontend, in glue.js
+pub struct WebUi;
+
+impl Ui for WebUi {
+    fn set_scene(&self, idx: i8) {
+        glue::setScene(idx);
+    }
+
+    fn show_chat(&self) {
+        glue::showChat();
+    }
+
+    fn hide_chat(&self) {
+        glue::hideChat();
+    }
+
+    fn set_player_name(&self, is_self: bool, name: String) {
+        glue::setPlayerName(is_self, name);
+    }
+
+    fn join_response(&self, resp: String) {
+        glue::joinResponse(resp);
+    }
+
+    fn show_manual_code(&self, code: String) {
+        glue::showManualCode(code);
+    }
+
+    fn show_rooms(&self, rooms: String) {
+        glue::showRooms(rooms);
+    }
+
+    fn lobby_response(&self, resp: String) {
+        glue::lobbyResponse(resp);
+    }
+
+    fn add_chat_message(&self, kind: u8, slots: Vec<String>) {
+        glue::addChatMessage(kind, slots);
+    }
+
+    fn set_pieces(&self, pieces: &[u16]) {
+        glue::setPieces(pieces);
+    }
+
+    fn move_pieces(&self, pieces: &[u16]) {
+        glue::movePieces(pieces);
+    }
+
+    fn highlight(&self, hexes: &[u16]) {
+        glue::highlight(hexes);
+    }
+
+    fn promote_pieces(&self, pieces: &[u16]) {
+        glue::promotePieces(pieces);
+    }
+
+    fn show_promotion_prompt(&self, color: u8, q: u8, r: u8) {
+        glue::showPromotionPrompt(color, q, r);
+    }
+
+    fn set_timers(&self, light: u16, dark: u16, active: i8) {
+        glue::setTimers(light, dark, active);
+    }
+
+    fn remove_timers(&self) {
+        glue::removeTimers();
+    }
+
+    fn add_rtt(&self, rtt: i32) {
+        glue::addRTT(rtt);
+    }
+
+    fn set_board_perspective(&self, is_light: bool) {
+        glue::setBoardPerspective(is_light);
+    }
+
+    fn show_buttons(&self, ids: &[u8]) {
+        glue::showButtons(ids);
+    }
+}
+
+// A single call made to the frontend
+#[cfg(feature = "testing")]
+#[derive(Debug, Clone, PartialEq)]
+pub enum UiCall {
+    SetScene(i8),
+    ShowChat,
+    HideChat,
+    SetPlayerName { is_self: bool, name: String },
+    JoinResponse(String),
+    ShowManualCode(String),
+    ShowRooms(String),
+    LobbyResponse(String),
+    AddChatMessage { kind: u8, slots: Vec<String> },
+    SetPieces(Vec<u16>),
+    MovePieces(Vec<u16>),
+    Highlight(Vec<u16>),
+    PromotePieces(Vec<u16>),
+    ShowPromotionPrompt { color: u8, q: u8, r: u8 },
+    SetTimers { light: u16, dark: u16, active: i8 },
+    RemoveTimers,
+    AddRtt(i32),
+    SetBoardPerspective(bool),
+    ShowButtons(Vec<u8>),
+}
+
+// Frontend that only remembers what it was told, for tests
+#[cfg(feature = "testing")]
+#[derive(Default)]
+pub struct RecordingUi {
+    calls: RefCell<Vec<UiCall>>,
+}
+
+#[cfg(feature = "testing")]
+impl RecordingUi {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Calls made since the last time they were taken
+    pub fn take(&self) -> Vec<UiCall> {
+        std::mem::take(&mut self.calls.borrow_mut())
+    }
+
+    fn push(&self, call: UiCall) {
+        self.calls.borrow_mut().push(call);
+    }
+}
+
+#[cfg(feature = "testing")]
+impl Ui for RecordingUi {
+    fn set_scene(&self, idx: i8) {
+        self.push(UiCall::SetScene(idx));
+    }
+
+    fn show_chat(&self) {
+        self.push(UiCall::ShowChat);
+    }
+
+    fn hide_chat(&self) {
+        self.push(UiCall::HideChat);
+    }
+
+    fn set_player_name(&self, is_self: bool, name: String) {
+        self.push(UiCall::SetPlayerName { is_self, name });
+    }
+
+    fn join_response(&self, resp: String) {
+        self.push(UiCall::JoinResponse(resp));
+    }
+
+    fn show_manual_code(&self, code: String) {
+        self.push(UiCall::ShowManualCode(code));
+    }
+
+    fn show_rooms(&self, rooms: String) {
+        self.push(UiCall::ShowRooms(rooms));
+    }
+
+    fn lobby_response(&self, resp: String) {
+        self.push(UiCall::LobbyResponse(resp));
+    }
+
+    fn add_chat_message(&self, kind: u8, slots: Vec<String>) {
+        self.push(UiCall::AddChatMessage { kind, slots });
+    }
+
+    fn set_pieces(&self, pieces: &[u16]) {
+        self.push(UiCall::SetPieces(pieces.to_vec()));
+    }
+
+    fn move_pieces(&self, pieces: &[u16]) {
+        self.push(UiCall::MovePieces(pieces.to_vec()));
+    }
+
+    fn highlight(&self, hexes: &[u16]) {
+        self.push(UiCall::Highlight(hexes.to_vec()));
+    }
+
+    fn promote_pieces(&self, pieces: &[u16]) {
+        self.push(UiCall::PromotePieces(pieces.to_vec()));
+    }
+
+    fn show_promotion_prompt(&self, color: u8, q: u8, r: u8) {
+        self.push(UiCall::ShowPromotionPrompt { color, q, r });
+    }
+
+    fn set_timers(&self, light: u16, dark: u16, active: i8) {
+        self.push(UiCall::SetTimers {
+            light,
+            dark,
+            active,
+        });
+    }
+
+    fn remove_timers(&self) {
+        self.push(UiCall::RemoveTimers);
+    }
+
+    fn add_rtt(&self, rtt: i32) {
+        self.push(UiCall::AddRtt(rtt));
+    }
+
+    fn set_board_perspective(&self, is_light: bool) {
+        self.push(UiCall::SetBoardPerspective(is_light));
+    }
+
+    fn show_buttons(&self, ids: &[u8]) {
+        self.push(UiCall::ShowButtons(ids.to_vec()));
+    }
+}
@@ -1,7 +1,9 @@
+use std::{cell::Cell, future::Future, rc::Rc};
+
 use futures::StreamExt;
-use rand::{rngs::SmallRng, SeedableRng};
-use wasm_bindgen::closure::Closure;
-use web_time::{Instant, SystemTime, UNIX_EPOCH};
+use web_time::Instant;
+
+use crate::clock::Clock;
 
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum Gamemode {
@@ -10,13 +12,15 @@ pub enum Gamemode {
     Bot,
 }
 
-impl From<u8> for Gamemode {
-    fn from(value: u8) -> Self {
+impl TryFrom<u8> for Gamemode {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, u8> {
         match value {
-            0 => Gamemode::Solo,
-            1 => Gamemode::Online,
-            2 => Gamemode::Bot,
-            _ => panic!("invalid gamemode"),
+            0 => Ok(Gamemode::Solo),
+            1 => Ok(Gamemode::Online),
+            2 => Ok(Gamemode::Bot),
+            _ => Err(value),
         }
     }
 }
@@ -31,31 +35,46 @@ impl From<Gamemode> for u8 {
     }
 }
 
-pub fn new_rng() -> SmallRng {
-    let now = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .expect("time travel?")
-        .as_millis() as u64;
-    SmallRng::seed_from_u64(now)
+// Resolves once the clock reaches `at`
+pub fn wait_until(clock: &dyn Clock, at: Instant) -> impl Future<Output = ()> {
+    let (tx, mut rx) = futures_channel::mpsc::unbounded();
+    clock.schedule(
+        at,
+        Box::new(move || {
+            let _ = tx.unbounded_send(());
+        }),
+    );
+
+    async move {
+        rx.next().await;
+    }
 }
 
-#[wasm_bindgen::prelude::wasm_bindgen]
-extern "C" {
-    fn setTimeout(closure: &Closure<dyn FnMut()>, millis: u32) -> f64;
+// Pending call scheduled by `timeout_at`. Dropping it cancels the call.
+pub struct Timeout {
+    cancelled: Rc<Cell<bool>>,
 }
 
-pub async fn wait_until(at: Instant) {
-    let delay = at - Instant::now();
-    let delay = delay.as_millis();
-    if delay == 0 {
-        return;
+impl Drop for Timeout {
+    fn drop(&mut self) {
+        self.cancelled.set(true);
     }
+}
 
-    let (mut tx, mut rx) = futures_channel::mpsc::channel(1);
-    let handler: Box<dyn FnMut()> = Box::new(move || tx.try_send(()).unwrap());
-    let handler = Closure::wrap(handler);
-
-    setTimeout(&handler, delay as u32);
+pub fn timeout_at<F>(clock: &dyn Clock, at: Instant, handler: F) -> Timeout
+where
+    F: FnOnce() + 'static,
+{
+    let cancelled = Rc::new(Cell::new(false));
+    let flag = cancelled.clone();
+    clock.schedule(
+        at,
+        Box::new(move || {
+            if !flag.get() {
+                handler();
+            }
+        }),
+    );
 
-    rx.next().await;
+    Timeout { cancelled }
 }
@@ -0,0 +1,290 @@
+//! Two linked games with a bad network between them.
+
+mod common;
+
+use std::{rc::Rc, time::Duration};
+
+use chessagon::{
+    clock::ManualClock,
+    loopback,
+    rng::{RngSource, Seeded},
+    simulator::{Conditions, Simulator},
+    ui::UiCall,
+    JsEvent,
+};
+use common::{
+    chat_kinds, Game, CHAT_ABANDONED, CHAT_DARK_TIMER_EXPIRED, CHAT_DISCONNECTED, CHAT_LAGGING,
+    CHAT_LIGHT_WON, CHAT_NOT_LAGGING, CHAT_RECONNECTING,
+};
+
+fn moves(calls: &[UiCall]) -> Vec<&[u16]> {
+    calls
+        .iter()
+        .filter_map(|call| match call {
+            UiCall::MovePieces(pieces) => Some(pieces.as_slice()),
+            _ => None,
+        })
+        .collect()
+}
+
+struct Match {
+    clock: Rc<ManualClock>,
+    host: Game,
+    guest: Game,
+    // Sits on the guest's side of the link, so it affects both directions
+    net: Rc<Simulator>,
+}
+
+impl Match {
+    fn run(&self, ms: u64) {
+        self.clock.advance(Duration::from_millis(ms));
+    }
+}
+
+// Host plays light with a minute to reconnect. The link is perfect until
+// told otherwise.
+fn started(timer: u8) -> Match {
+    started_with(common::timed(timer))
+}
+
+fn started_with(settings: [u8; 6]) -> Match {
+    let clock = Rc::new(ManualClock::new());
+    let host = Game::online(&clock, "host", 1);
+    let guest = Game::online(&clock, "guest", 2);
+    let net = Simulator::new(
+        Conditions::default(),
+        clock.clone(),
+        Seeded::new(3).new_rng(),
+    );
+
+    let (host_conn, guest_conn) = loopback::pair(clock.clone());
+    host.ctx.link(host_conn, true);
+    guest.ctx.link(net.wrap(guest_conn), false);
+    host.ctx.dispatch(JsEvent::SetPassword, &[]);
+    host.ctx.dispatch(JsEvent::SetSettings, &settings);
+    clock.advance(Duration::ZERO);
+
+    host.ui.take();
+    guest.ui.take();
+    Match {
+        clock,
+        host,
+        guest,
+        net,
+    }
+}
+
+fn latency(ms: u64) -> Conditions {
+    Conditions {
+        latency: Duration::from_millis(ms),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn pings_measure_the_round_trip() {
+    let game = started(0);
+    game.net.set_conditions(latency(100));
+
+    game.run(5000);
+    game.run(200);
+    assert!(game.host.ui.take().contains(&UiCall::AddRtt(200)));
+    assert!(game.guest.ui.take().contains(&UiCall::AddRtt(200)));
+}
+
+#[test]
+fn slow_pings_flag_the_peer_as_lagging_until_they_arrive() {
+    let game = started(0);
+    // Replies take longer than the ping interval
+    game.net.set_conditions(latency(3000));
+
+    game.run(10_000);
+    assert_eq!(game.host.chat(), [CHAT_LAGGING]);
+    game.run(1000);
+    let calls = game.host.ui.take();
+    assert!(calls.contains(&UiCall::AddRtt(6000)));
+    assert!(calls.contains(&UiCall::AddChatMessage {
+        kind: CHAT_NOT_LAGGING,
+        slots: vec![],
+    }));
+}
+
+#[test]
+fn lost_pings_drop_the_connection() {
+    let game = started(0);
+    game.net.set_conditions(Conditions {
+        drop: 1.0,
+        ..Default::default()
+    });
+
+    game.run(20_000);
+    assert_eq!(game.host.chat(), [CHAT_LAGGING, CHAT_RECONNECTING]);
+    assert_eq!(game.guest.chat(), [CHAT_LAGGING, CHAT_RECONNECTING]);
+}
+
+#[test]
+fn only_the_host_calls_an_abandoned_game() {
+    let game = started(0);
+    game.net.set_conditions(Conditions {
+        drop: 1.0,
+        ..Default::default()
+    });
+
+    game.run(20_000);
+    game.run(60_000);
+    assert_eq!(
+        game.host.chat(),
+        [
+            CHAT_LAGGING,
+            CHAT_RECONNECTING,
+            CHAT_ABANDONED,
+            CHAT_LIGHT_WON,
+            CHAT_DISCONNECTED
+        ]
+    );
+    // The guest doesn't claim a win of its own
+    assert_eq!(
+        game.guest.chat(),
+        [
+            CHAT_LAGGING,
+            CHAT_RECONNECTING,
+            CHAT_ABANDONED,
+            CHAT_DISCONNECTED
+        ]
+    );
+}
+
+#[test]
+fn only_the_absent_players_clock_runs_while_apart() {
+    // Half a minute each, clocks keep running through disconnects
+    let game = started_with([0, 30, 1, 0, 60, 0]);
+    game.host.play((4, 7), (4, 5));
+    game.run(0);
+    game.net.set_conditions(Conditions {
+        drop: 1.0,
+        ..Default::default()
+    });
+
+    game.run(20_000);
+    // The guest can't move, so its own clock stopped. The host still
+    // charges the guest for the time it's away.
+    assert_eq!(game.guest.timers().map(|(.., active)| active), Some(-1));
+    assert_eq!(game.host.timers().map(|(.., active)| active), Some(1));
+
+    game.run(20_000);
+    assert!(game.guest.chat().is_empty());
+    assert_eq!(
+        game.host.chat(),
+        [CHAT_DARK_TIMER_EXPIRED, CHAT_LIGHT_WON, CHAT_DISCONNECTED]
+    );
+
+    game.run(60_000);
+    assert_eq!(game.guest.chat(), [CHAT_ABANDONED, CHAT_DISCONNECTED]);
+}
+
+#[test]
+fn latency_is_charged_to_the_player_waiting_on_it() {
+    let game = started(60);
+    game.net.set_conditions(latency(250));
+
+    game.run(2000);
+    game.host.play((4, 7), (4, 5));
+    game.run(250);
+    game.run(10_000);
+    game.guest.play((5, 4), (5, 5));
+    game.run(250);
+
+    // Light's clock only starts with its first move. The host saw dark
+    // think for a full round trip longer.
+    assert_eq!(game.host.timers(), Some((60, 49, 0)));
+    assert_eq!(game.guest.timers(), Some((60, 50, 0)));
+}
+
+#[test]
+fn flag_falls_on_both_sides_despite_latency() {
+    let game = started(10);
+    game.net.set_conditions(latency(250));
+
+    game.run(1000);
+    game.host.play((4, 7), (4, 5));
+    game.run(11_000);
+
+    let ending = [CHAT_DARK_TIMER_EXPIRED, CHAT_LIGHT_WON];
+    assert_eq!(game.host.chat(), ending);
+    assert_eq!(game.guest.chat(), ending);
+}
+
+// Each player says something either side of their move, so what the peer
+// receives right after the move can overtake it
+fn chatty_game(game: &Match) -> (Vec<UiCall>, Vec<UiCall>) {
+    for (player, from, to) in [
+        (&game.host, (4, 7), (4, 5)),
+        (&game.guest, (5, 4), (5, 5)),
+        (&game.host, (3, 8), (3, 6)),
+        (&game.guest, (3, 4), (3, 5)),
+    ] {
+        player.ctx.dispatch(JsEvent::SendMessage, b"hi");
+        player.play(from, to);
+        player.ctx.dispatch(JsEvent::SendMessage, b"bye");
+        game.run(1000);
+    }
+    (game.host.ui.take(), game.guest.ui.take())
+}
+
+// What the player on that side said, in the order it showed up
+fn said(calls: &[UiCall], is_light: bool) -> Vec<&str> {
+    calls
+        .iter()
+        .filter_map(|call| match call {
+            UiCall::AddChatMessage { kind, slots } if *kind == u8::from(!is_light) => {
+                Some(slots[1].as_str())
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn duplicated_moves_are_only_played_once() {
+    let game = started(60);
+    game.net.set_conditions(Conditions {
+        duplicate: 1.0,
+        jitter: Duration::from_millis(100),
+        reorder: 0.5,
+        ..Default::default()
+    });
+
+    let (host, guest) = chatty_game(&game);
+    assert_eq!(moves(&host).len(), 4);
+    assert_eq!(moves(&host), moves(&guest));
+    // Chat has nothing to tell copies apart, so every message shows twice
+    assert_eq!(said(&guest, true).len(), 8);
+    assert_eq!(said(&host, false).len(), 8);
+    assert!(chat_kinds(&host).iter().all(|&kind| kind < 2));
+    assert!(chat_kinds(&guest).iter().all(|&kind| kind < 2));
+}
+
+#[test]
+fn jittery_links_keep_boards_in_step() {
+    let game = started(60);
+    game.net.set_conditions(Conditions {
+        latency: Duration::from_millis(20),
+        jitter: Duration::from_millis(100),
+        reorder: 0.5,
+        ..Default::default()
+    });
+
+    let (host, guest) = chatty_game(&game);
+    assert_eq!(moves(&host).len(), 4);
+    assert_eq!(moves(&host), moves(&guest));
+    assert!(chat_kinds(&host).iter().all(|&kind| kind < 2));
+    assert!(chat_kinds(&guest).iter().all(|&kind| kind < 2));
+
+    // Everything arrived once, just not in the order it was sent
+    let (mut from_host, mut from_guest) = (said(&guest, true), said(&host, false));
+    assert_ne!(from_host, ["hi", "bye", "hi", "bye"]);
+    from_host.sort();
+    from_guest.sort();
+    assert_eq!(from_host, ["bye", "bye", "hi", "hi"]);
+    assert_eq!(from_guest, ["bye", "bye", "hi", "hi"]);
+}
@@ -0,0 +1,84 @@
+//! Clocks and randomness driven by the test.
+
+mod common;
+
+use std::time::Duration;
+
+use chessagon::{ui::UiCall, JsEvent};
+use common::{Game, CHAT_DARK_TIMER_EXPIRED, CHAT_LIGHT_WON};
+
+#[test]
+fn clocks_run_on_the_players_turn() {
+    let game = Game::solo(common::timed(60));
+
+    // Light's first move starts dark's clock
+    game.play((4, 7), (4, 5));
+    assert_eq!(game.timers(), Some((60, 60, 1)));
+
+    game.clock.advance(Duration::from_secs(25));
+    game.play((6, 3), (6, 4));
+    assert_eq!(game.timers(), Some((60, 35, 0)));
+
+    game.clock.advance(Duration::from_secs(10));
+    game.play((5, 6), (5, 5));
+    assert_eq!(game.timers(), Some((50, 35, 1)));
+}
+
+#[test]
+fn flag_falls_once_time_runs_out() {
+    let game = Game::solo(common::timed(60));
+    game.play((4, 7), (4, 5));
+    game.ui.take();
+
+    game.clock.advance(Duration::from_millis(59_999));
+    assert!(game.chat().is_empty());
+
+    // Dark's timer expired, so light won
+    game.clock.advance(Duration::from_millis(1));
+    assert_eq!(game.chat(), [CHAT_DARK_TIMER_EXPIRED, CHAT_LIGHT_WON]);
+    assert_eq!(game.clock.pending(), 0);
+}
+
+#[test]
+fn moving_in_time_cancels_the_flag() {
+    let game = Game::solo(common::timed(60));
+    game.play((4, 7), (4, 5));
+    game.clock.advance(Duration::from_secs(59));
+    game.play((6, 3), (6, 4));
+    game.ui.take();
+
+    game.clock.advance(Duration::from_secs(2));
+    assert!(game.chat().is_empty());
+}
+
+fn player_name(seed: u64) -> String {
+    let game = Game::new(seed);
+    game.ctx.dispatch_empty(JsEvent::Start);
+
+    game.ui
+        .take()
+        .into_iter()
+        .find_map(|call| match call {
+            UiCall::SetPlayerName {
+                is_self: true,
+                name,
+            } => Some(name),
+            _ => None,
+        })
+        .unwrap()
+}
+
+#[test]
+fn seeded_runs_repeat_themselves() {
+    assert_eq!(player_name(42), player_name(42));
+}
+
+#[test]
+fn pings_keep_coming_while_idle() {
+    let game = Game::new(0);
+    game.ctx.dispatch_empty(JsEvent::Start);
+    assert_eq!(game.clock.pending(), 1);
+
+    game.clock.advance(Duration::from_secs(60));
+    assert_eq!(game.clock.pending(), 1);
+}
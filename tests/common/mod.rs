@@ -0,0 +1,116 @@
+//! Fixtures shared by the integration tests. Each test file uses only some
+//! of them.
+#![allow(dead_code)]
+
+use std::rc::Rc;
+
+use chessagon::{
+    clock::ManualClock,
+    rng::Seeded,
+    setup_with,
+    ui::{RecordingUi, UiCall},
+    Context, JsEvent,
+};
+
+pub const CHAT_START: u8 = 3;
+pub const CHAT_LIGHT_WON: u8 = 4;
+pub const CHAT_DARK_WON: u8 = 5;
+pub const CHAT_DARK_TIMER_EXPIRED: u8 = 7;
+pub const CHAT_LIGHT_RESIGNED: u8 = 8;
+pub const CHAT_CONNECTED: u8 = 11;
+pub const CHAT_DISCONNECTED: u8 = 12;
+pub const CHAT_RECONNECTING: u8 = 14;
+pub const CHAT_LAGGING: u8 = 16;
+pub const CHAT_NOT_LAGGING: u8 = 17;
+pub const CHAT_ABANDONED: u8 = 18;
+pub const CHAT_DESYNCED: u8 = 22;
+
+// Untimed, the host plays light, a minute to reconnect with clocks paused
+pub const SETTINGS: [u8; 6] = [0, 0, 1, 0, 60, 1];
+
+// Same, with `timer` seconds for each player
+pub fn timed(timer: u8) -> [u8; 6] {
+    let mut settings = SETTINGS;
+    settings[1] = timer;
+    settings
+}
+
+// A game with a frontend that records calls, a clock the test drives, and
+// seeded randomness
+pub struct Game {
+    pub ctx: Context,
+    pub ui: Rc<RecordingUi>,
+    pub clock: Rc<ManualClock>,
+}
+
+impl Game {
+    pub fn new(seed: u64) -> Self {
+        Game::on(&Rc::new(ManualClock::new()), seed)
+    }
+
+    // Games sharing a clock can be linked together
+    pub fn on(clock: &Rc<ManualClock>, seed: u64) -> Self {
+        let ui = Rc::new(RecordingUi::new());
+        let ctx = setup_with(ui.clone(), clock.clone(), Rc::new(Seeded::new(seed)));
+        Game {
+            ctx,
+            ui,
+            clock: clock.clone(),
+        }
+    }
+
+    // Solo game started with these settings. What starting it showed is
+    // left for the test to look at.
+    pub fn solo(settings: [u8; 6]) -> Self {
+        let game = Game::new(0);
+        game.ctx.dispatch(JsEvent::SetGamemode, &[0]);
+        game.ui.take();
+        game.ctx.dispatch(JsEvent::SetSettings, &settings);
+        game
+    }
+
+    // Registered for an online game, waiting to be linked
+    pub fn online(clock: &Rc<ManualClock>, name: &str, seed: u64) -> Self {
+        let game = Game::on(clock, seed);
+        game.ctx.dispatch_empty(JsEvent::Start);
+        game.ctx.dispatch(JsEvent::SetGamemode, &[1]);
+        game.ctx.dispatch(JsEvent::Register, name.as_bytes());
+        game.ui.take();
+        game
+    }
+
+    pub fn play(&self, from: (u8, u8), to: (u8, u8)) {
+        self.ctx.dispatch(JsEvent::HexClicked, &[from.0, from.1]);
+        self.ctx.dispatch(JsEvent::HexClicked, &[to.0, to.1]);
+    }
+
+    pub fn chat(&self) -> Vec<u8> {
+        chat_kinds(&self.ui.take())
+    }
+
+    // Light, dark, and whose clock runs, in seconds
+    pub fn timers(&self) -> Option<(u16, u16, i8)> {
+        self.ui
+            .take()
+            .into_iter()
+            .rev()
+            .find_map(|call| match call {
+                UiCall::SetTimers {
+                    light,
+                    dark,
+                    active,
+                } => Some((light, dark, active)),
+                _ => None,
+            })
+    }
+}
+
+pub fn chat_kinds(calls: &[UiCall]) -> Vec<u8> {
+    calls
+        .iter()
+        .filter_map(|call| match call {
+            UiCall::AddChatMessage { kind, .. } => Some(*kind),
+            _ => None,
+        })
+        .collect()
+}
@@ -0,0 +1,23 @@
+//! ICE servers handed out by signalling services.
+
+use std::time::{Duration, UNIX_EPOCH};
+
+use chessagon::signalling::IceServer;
+
+fn expiring_at(secs: Option<u64>) -> IceServer {
+    IceServer {
+        urls: vec!["turn:turn.example.com".to_owned()],
+        username: "user".to_owned(),
+        credential: "secret".to_owned(),
+        expires: secs,
+    }
+}
+
+#[test]
+fn credentials_expire_on_time() {
+    let now = UNIX_EPOCH + Duration::from_secs(1_000);
+    assert!(!expiring_at(Some(1_001)).is_expired(now));
+    assert!(expiring_at(Some(1_000)).is_expired(now));
+    assert!(expiring_at(Some(999)).is_expired(now));
+    assert!(!expiring_at(None).is_expired(now));
+}
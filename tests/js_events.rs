@@ -0,0 +1,133 @@
+//! What the page sends along with its events, good and bad.
+
+use std::rc::Rc;
+
+use chessagon::{
+    clock::ManualClock,
+    rng::Seeded,
+    setup_with,
+    ui::{RecordingUi, UiCall},
+    Button, Event, EventError, JsEvent,
+};
+
+fn missing(evt: JsEvent, field: &'static str) -> EventError {
+    EventError::Missing { evt, field }
+}
+
+fn invalid(evt: JsEvent, field: &'static str) -> EventError {
+    EventError::OutOfRange { evt, field }
+}
+
+#[test]
+fn decodes_well_formed_events() {
+    assert!(matches!(
+        Event::from_js(JsEvent::HexClicked, &[4, 7]),
+        Ok(Event::HexClicked { q: 4, r: 7 })
+    ));
+    assert!(matches!(
+        Event::from_js(JsEvent::GameButtonClick, &[2]),
+        Ok(Event::GameButtonClick(Button::LeaveRoom))
+    ));
+    assert!(matches!(
+        Event::from_js(JsEvent::SetSettings, &[0, 60, 1, 0, 30, 0]),
+        Ok(Event::SetSettings {
+            timer: 60,
+            host_as_light: true,
+            reconnect_window: 30,
+            pause_on_disconnect: false,
+        })
+    ));
+}
+
+#[test]
+fn says_what_is_wrong_with_bad_data() {
+    let cases = [
+        (
+            JsEvent::SetGamemode,
+            &[][..],
+            missing(JsEvent::SetGamemode, "gamemode"),
+        ),
+        (
+            JsEvent::SetGamemode,
+            &[3],
+            invalid(JsEvent::SetGamemode, "gamemode"),
+        ),
+        (JsEvent::HexClicked, &[4], missing(JsEvent::HexClicked, "r")),
+        // Both are within 0 to 10, but the corner is cut off
+        (
+            JsEvent::HexClicked,
+            &[0, 0],
+            invalid(JsEvent::HexClicked, "hex"),
+        ),
+        (
+            JsEvent::HexClicked,
+            &[200, 3],
+            invalid(JsEvent::HexClicked, "hex"),
+        ),
+        (
+            JsEvent::GameButtonClick,
+            &[9],
+            invalid(JsEvent::GameButtonClick, "button"),
+        ),
+        (
+            JsEvent::PromotionResponse,
+            &[0],
+            invalid(JsEvent::PromotionResponse, "piece kind"),
+        ),
+        (
+            JsEvent::PromotionResponse,
+            &[7],
+            invalid(JsEvent::PromotionResponse, "piece kind"),
+        ),
+        (
+            JsEvent::MenuHidden,
+            &[100],
+            invalid(JsEvent::MenuHidden, "menu"),
+        ),
+        (
+            JsEvent::SetSettings,
+            &[0, 0, 1],
+            missing(JsEvent::SetSettings, "reconnect window"),
+        ),
+    ];
+    for (evt, data, expected) in cases {
+        assert_eq!(
+            Event::from_js(evt, data).err(),
+            Some(expected),
+            "{:?} {:?}",
+            evt,
+            data
+        );
+    }
+
+    let err = Event::from_js(JsEvent::HexClicked, &[4]).unwrap_err();
+    assert_eq!(err.to_string(), "HexClicked is missing its r");
+}
+
+#[test]
+fn bad_events_leave_the_game_alone() {
+    let ui = Rc::new(RecordingUi::new());
+    let ctx = setup_with(
+        ui.clone(),
+        Rc::new(ManualClock::new()),
+        Rc::new(Seeded::new(0)),
+    );
+    ctx.dispatch(JsEvent::SetGamemode, &[7]);
+    ctx.dispatch(JsEvent::SetGamemode, &[0]);
+    ctx.dispatch(JsEvent::SetSettings, &[0, 0, 1, 0, 60, 1]);
+    ui.take();
+
+    ctx.dispatch(JsEvent::HexClicked, &[]);
+    ctx.dispatch(JsEvent::HexClicked, &[11, 11]);
+    ctx.dispatch(JsEvent::GameButtonClick, &[3]);
+    ctx.dispatch(JsEvent::PromotionResponse, &[6]);
+    assert!(ui.take().is_empty());
+
+    // Still playing
+    ctx.dispatch(JsEvent::HexClicked, &[4, 7]);
+    ctx.dispatch(JsEvent::HexClicked, &[4, 5]);
+    assert!(ui
+        .take()
+        .iter()
+        .any(|call| matches!(call, UiCall::MovePieces(_))));
+}
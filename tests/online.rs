@@ -0,0 +1,622 @@
+//! Two games linked in the same process, talking over a loopback pipe.
+
+mod common;
+
+use std::{cell::RefCell, rc::Rc, time::Duration};
+
+use chessagon::{clock::ManualClock, loopback, ui::UiCall, Connection, Event, EventKind, JsEvent};
+use chessagon_core::{
+    auth,
+    board::Board,
+    notation::parse_hex,
+    packet::{
+        AuthChallenge, AuthResult, ChessPacket, Handshake, Movement, Promote, SetBoard,
+        SetSettings, Start, SyncState,
+    },
+    piece::{Color, Piece, PieceKind},
+};
+use common::{
+    chat_kinds, Game, CHAT_CONNECTED, CHAT_DESYNCED, CHAT_DISCONNECTED, CHAT_LIGHT_RESIGNED,
+    CHAT_LIGHT_WON, CHAT_RECONNECTING, CHAT_START,
+};
+
+const CANVAS_SCENE: i8 = -1;
+const RESIGN: u8 = 0;
+
+// Delivers everything in flight, and whatever that causes to be sent
+fn settle(clock: &ManualClock) {
+    clock.advance(Duration::ZERO);
+}
+
+struct Match {
+    clock: Rc<ManualClock>,
+    host: Game,
+    guest: Game,
+    // Guest's end of the pipe, to slip packets in behind its back
+    guest_conn: Connection,
+}
+
+fn linked() -> Match {
+    linked_with("", "")
+}
+
+fn linked_with(host_password: &str, guest_password: &str) -> Match {
+    let clock = Rc::new(ManualClock::new());
+    let host = Game::online(&clock, "host", 1);
+    let guest = Game::online(&clock, "guest", 2);
+
+    let (host_conn, guest_conn) = loopback::pair(clock.clone());
+    guest
+        .ctx
+        .dispatch(JsEvent::SetPassword, guest_password.as_bytes());
+    host.ctx.link(host_conn, true);
+    guest.ctx.link(guest_conn.clone(), false);
+    host.ctx
+        .dispatch(JsEvent::SetPassword, host_password.as_bytes());
+    settle(&clock);
+
+    Match {
+        clock,
+        host,
+        guest,
+        guest_conn,
+    }
+}
+
+// Host plays light without a timer
+fn started() -> Match {
+    let game = linked();
+    game.host
+        .ctx
+        .dispatch(JsEvent::SetSettings, &common::SETTINGS);
+    settle(&game.clock);
+    game.host.ui.take();
+    game.guest.ui.take();
+    game
+}
+
+#[test]
+fn handshake_names_both_players() {
+    let game = linked();
+    let host = game.host.ui.take();
+    let guest = game.guest.ui.take();
+
+    assert!(host.contains(&UiCall::SetPlayerName {
+        is_self: false,
+        name: "guest".to_owned(),
+    }));
+    assert!(guest.contains(&UiCall::SetPlayerName {
+        is_self: false,
+        name: "host".to_owned(),
+    }));
+    assert!(chat_kinds(&host).contains(&CHAT_CONNECTED));
+    assert!(chat_kinds(&guest).contains(&CHAT_CONNECTED));
+    assert!(guest.contains(&UiCall::SetScene(CANVAS_SCENE)));
+}
+
+#[test]
+fn room_passwords_are_proven_both_ways() {
+    let game = linked_with("secret", "secret");
+    assert!(chat_kinds(&game.guest.ui.take()).contains(&CHAT_CONNECTED));
+
+    let game = linked_with("secret", "guess");
+    let guest = game.guest.ui.take();
+    assert!(guest.contains(&UiCall::JoinResponse("wrong-password".to_owned())));
+    assert!(!chat_kinds(&guest).contains(&CHAT_CONNECTED));
+
+    // Someone else holding the room code, letting anyone in
+    let clock = Rc::new(ManualClock::new());
+    let guest = Game::online(&clock, "guest", 2);
+    guest.ctx.dispatch(JsEvent::SetPassword, b"secret");
+    let host = raw_link(&clock, &guest);
+    host.send(ChessPacket::AuthResult(AuthResult {
+        accepted: true,
+        proof: auth::host_proof("guess", &host.challenge()),
+    }));
+    settle(&clock);
+    let calls = guest.ui.take();
+    assert!(calls.contains(&UiCall::JoinResponse("impostor-host".to_owned())));
+    assert!(!chat_kinds(&calls).contains(&CHAT_CONNECTED));
+}
+
+#[test]
+fn settings_and_board_reach_the_guest() {
+    let game = linked();
+    game.host.ui.take();
+    game.guest.ui.take();
+
+    // Host plays dark this time
+    game.host
+        .ctx
+        .dispatch(JsEvent::SetSettings, &[0, 0, 0, 0, 60, 1]);
+    settle(&game.clock);
+    let host = game.host.ui.take();
+    let guest = game.guest.ui.take();
+
+    let mut board = Board::new();
+    board.load_default();
+    assert!(host.contains(&UiCall::SetBoardPerspective(false)));
+    assert!(guest.contains(&UiCall::SetBoardPerspective(true)));
+    assert!(host.contains(&UiCall::SetPieces(board.describe())));
+    assert!(guest.contains(&UiCall::SetPieces(board.describe())));
+    assert_eq!(chat_kinds(&host), [CHAT_START]);
+    assert_eq!(chat_kinds(&guest), [CHAT_START]);
+}
+
+#[test]
+fn moves_show_up_on_both_boards() {
+    let game = started();
+
+    // Light e-pawn two steps, then a dark pawn answers
+    game.host.play((4, 7), (4, 5));
+    settle(&game.clock);
+    let moved = UiCall::MovePieces(vec![12 << 8 | 4 << 4 | 5]);
+    assert!(game.host.ui.take().contains(&moved));
+    assert!(game.guest.ui.take().contains(&moved));
+
+    game.guest.play((5, 4), (5, 5));
+    settle(&game.clock);
+    let moved = UiCall::MovePieces(vec![(18 + 13) << 8 | 5 << 4 | 5]);
+    assert!(game.host.ui.take().contains(&moved));
+    assert!(game.guest.ui.take().contains(&moved));
+}
+
+#[test]
+fn out_of_turn_clicks_do_nothing() {
+    let game = started();
+
+    game.guest.play((5, 4), (5, 5));
+    settle(&game.clock);
+    assert!(!game
+        .guest
+        .ui
+        .take()
+        .iter()
+        .any(|call| matches!(call, UiCall::MovePieces(..))));
+    assert!(!game
+        .host
+        .ui
+        .take()
+        .iter()
+        .any(|call| matches!(call, UiCall::MovePieces(..))));
+}
+
+#[test]
+fn resignation_ends_the_game_for_both() {
+    let game = started();
+
+    game.guest.ctx.dispatch(JsEvent::GameButtonClick, &[RESIGN]);
+    settle(&game.clock);
+
+    // Guest played dark
+    let dark_resigned = CHAT_LIGHT_RESIGNED + 1;
+    assert_eq!(
+        chat_kinds(&game.host.ui.take()),
+        [dark_resigned, CHAT_LIGHT_WON]
+    );
+    assert_eq!(
+        chat_kinds(&game.guest.ui.take()),
+        [dark_resigned, CHAT_LIGHT_WON]
+    );
+}
+
+#[test]
+fn illegal_move_disconnects() {
+    let game = started();
+    game.host.play((4, 7), (4, 5));
+    settle(&game.clock);
+    game.host.ui.take();
+    game.guest.ui.take();
+
+    // The guest's queen can't jump over its own pawns
+    let mut board = Board::new();
+    board.load_default();
+    board.move_piece((4, 7), (4, 5));
+    game.guest_conn.send(
+        ChessPacket::Movement(Movement {
+            idx: 18,
+            q: 4,
+            r: 4,
+            time_left: None,
+            checksum: board.checksum(Color::Dark),
+        })
+        .write(),
+    );
+    settle(&game.clock);
+
+    assert_eq!(chat_kinds(&game.host.ui.take()), [CHAT_DISCONNECTED]);
+    // Guest is left waiting for the host to come back
+    assert_eq!(chat_kinds(&game.guest.ui.take()), [CHAT_RECONNECTING]);
+}
+
+#[test]
+fn only_accepted_moves_reach_spectators() {
+    let game = started();
+    let played: Rc<RefCell<Vec<(u8, u8)>>> = Default::default();
+    let seen = played.clone();
+    let _sub = game
+        .host
+        .ctx
+        .subscribe_to(&[EventKind::MovePlayed], 0, move |evt| {
+            if let Event::MovePlayed { to, .. } = evt {
+                seen.borrow_mut().push(*to);
+            }
+        });
+
+    game.host.play((4, 7), (4, 5));
+    settle(&game.clock);
+    // Same illegal queen move as above
+    let mut board = Board::new();
+    board.load_default();
+    board.move_piece((4, 7), (4, 5));
+    game.guest_conn.send(
+        ChessPacket::Movement(Movement {
+            idx: 18,
+            q: 4,
+            r: 4,
+            time_left: None,
+            checksum: board.checksum(Color::Dark),
+        })
+        .write(),
+    );
+    settle(&game.clock);
+
+    assert_eq!(*played.borrow(), [(4, 5)]);
+}
+
+// A host driven by hand, so the board can be anything
+struct RawHost {
+    conn: Connection,
+    received: Rc<RefCell<Vec<ChessPacket>>>,
+}
+
+impl RawHost {
+    fn send(&self, packet: ChessPacket) {
+        self.conn.send(packet.write());
+    }
+
+    fn take(&self) -> Vec<ChessPacket> {
+        std::mem::take(&mut self.received.borrow_mut())
+    }
+
+    // What the guest challenged us with in its answer
+    fn challenge(&self) -> String {
+        match self.take().as_slice() {
+            [ChessPacket::AuthProof(p)] => p.nonce.clone(),
+            other => panic!("expected an answer, got {:?}", other),
+        }
+    }
+}
+
+// Challenges the guest, without answering it yet
+fn raw_link(clock: &Rc<ManualClock>, guest: &Game) -> RawHost {
+    let (conn, guest_conn) = loopback::pair(clock.clone());
+    let received: Rc<RefCell<Vec<ChessPacket>>> = Default::default();
+    let inbox = received.clone();
+    conn.set_onmessage(Box::new(move |data| {
+        inbox.borrow_mut().push(ChessPacket::read(data).unwrap());
+    }));
+    guest.ctx.link(guest_conn, false);
+
+    let host = RawHost { conn, received };
+    host.send(ChessPacket::AuthChallenge(AuthChallenge {
+        nonce: "nonce".to_owned(),
+    }));
+    settle(clock);
+    host
+}
+
+fn raw_host(clock: &Rc<ManualClock>, guest: &Game) -> RawHost {
+    let host = raw_link(clock, guest);
+    host.send(ChessPacket::AuthResult(AuthResult {
+        accepted: true,
+        proof: auth::host_proof("", &host.challenge()),
+    }));
+    host.send(ChessPacket::Handshake(Handshake::new("host".to_owned())));
+    settle(clock);
+    host
+}
+
+#[test]
+fn pawns_promote_on_both_sides() {
+    let clock = Rc::new(ManualClock::new());
+    let guest = Game::online(&clock, "guest", 2);
+    let host = raw_host(&clock, &guest);
+    assert!(matches!(
+        host.take().last(),
+        Some(ChessPacket::Handshake(..))
+    ));
+
+    // Kings far apart, a pawn each one step away from promoting
+    let board = [
+        (PieceKind::King, Color::Light, 6, 9),
+        (PieceKind::Pawn, Color::Light, 5, 1),
+        (PieceKind::King, Color::Dark, 10, 5),
+        (PieceKind::Pawn, Color::Dark, 4, 9),
+    ]
+    .iter()
+    .enumerate()
+    .map(|(idx, &(kind, color, q, r))| {
+        Piece {
+            idx: idx as u8,
+            kind,
+            color,
+            q,
+            r,
+        }
+        .describe()
+    })
+    .collect::<Vec<_>>();
+    let mut mirror = Board::new();
+    mirror.load_desc(&board).unwrap();
+
+    host.send(ChessPacket::SetSettings(SetSettings {
+        timer: 0,
+        host_as_light: true,
+        reconnect_window: 60,
+        pause_on_disconnect: true,
+    }));
+    host.send(ChessPacket::SetBoard(SetBoard { board }));
+    host.send(ChessPacket::Start(Start {}));
+    settle(&clock);
+    guest.ui.take();
+
+    // Light reaches the last row, and the guest waits for its choice
+    host.send(ChessPacket::Movement(Movement {
+        idx: 1,
+        q: 5,
+        r: 0,
+        time_left: None,
+        checksum: mirror.checksum(Color::Light),
+    }));
+    settle(&clock);
+    let calls = guest.ui.take();
+    assert!(calls.contains(&UiCall::MovePieces(vec![1 << 8 | 5 << 4])));
+    assert!(!calls
+        .iter()
+        .any(|call| matches!(call, UiCall::ShowPromotionPrompt { .. })));
+
+    // Knight
+    host.send(ChessPacket::Promote(Promote { idx: 1, kind: 4 }));
+    settle(&clock);
+    assert!(guest
+        .ui
+        .take()
+        .contains(&UiCall::PromotePieces(vec![4 << 8 | 1])));
+
+    // Dark's turn: the guest gets asked, and the host told
+    guest.play((4, 9), (4, 10));
+    assert!(guest.ui.take().contains(&UiCall::ShowPromotionPrompt {
+        color: 1,
+        q: 4,
+        r: 10,
+    }));
+    // Rook
+    guest.ctx.dispatch(JsEvent::PromotionResponse, &[2]);
+    settle(&clock);
+    assert!(guest
+        .ui
+        .take()
+        .contains(&UiCall::PromotePieces(vec![2 << 8 | 3])));
+
+    let sent = host.take();
+    assert!(matches!(
+        sent.as_slice(),
+        [
+            ..,
+            ChessPacket::Movement(Movement {
+                idx: 3,
+                q: 4,
+                r: 10,
+                ..
+            }),
+            ChessPacket::Promote(Promote { idx: 3, kind: 2 })
+        ]
+    ));
+}
+
+#[test]
+fn repeated_moves_only_count_when_the_boards_agree() {
+    let clock = Rc::new(ManualClock::new());
+    let guest = Game::online(&clock, "guest", 2);
+    let host = raw_host(&clock, &guest);
+    let mut mirror = Board::new();
+    mirror.load_default();
+
+    host.send(ChessPacket::SetSettings(SetSettings {
+        timer: 0,
+        host_as_light: true,
+        reconnect_window: 60,
+        pause_on_disconnect: true,
+    }));
+    host.send(ChessPacket::SetBoard(SetBoard {
+        board: mirror.describe(),
+    }));
+    host.send(ChessPacket::Start(Start {}));
+    settle(&clock);
+
+    // Light e-pawn two steps, then a dark pawn answers
+    let push = |checksum| {
+        ChessPacket::Movement(Movement {
+            idx: 12,
+            q: 4,
+            r: 5,
+            time_left: None,
+            checksum,
+        })
+    };
+    let before = mirror.checksum(Color::Light);
+    host.send(push(before));
+    mirror.move_piece((4, 7), (4, 5));
+    settle(&clock);
+    guest.play((5, 4), (5, 5));
+    settle(&clock);
+    host.take();
+    guest.ui.take();
+
+    // The network delivering the push again changes nothing
+    host.send(push(before));
+    settle(&clock);
+    let calls = guest.ui.take();
+    assert!(!calls
+        .iter()
+        .any(|call| matches!(call, UiCall::MovePieces(_))));
+    assert!(chat_kinds(&calls).is_empty());
+    assert!(host.take().is_empty());
+
+    // The same move from a position the guest never had means the boards split
+    host.send(push(before.wrapping_add(1)));
+    settle(&clock);
+    assert_eq!(guest.chat(), [CHAT_DESYNCED]);
+    assert!(matches!(
+        host.take().as_slice(),
+        [ChessPacket::RequestSync(..)]
+    ));
+}
+
+#[test]
+fn refuses_boards_and_settings_that_make_no_sense() {
+    let settings = |timer, reconnect_window| {
+        ChessPacket::SetSettings(SetSettings {
+            timer,
+            host_as_light: true,
+            reconnect_window,
+            pause_on_disconnect: true,
+        })
+    };
+    let queen = |idx: usize| {
+        Piece {
+            idx: idx as u8,
+            kind: PieceKind::Queen,
+            color: Color::Light,
+            q: (idx % 11) as u8,
+            r: 5,
+        }
+        .describe()
+    };
+    let mut default = Board::new();
+    default.load_default();
+    let default = default.describe();
+    let (q, r) = parse_hex("f6").unwrap();
+    let king_on_f6 = Piece {
+        idx: default.len() as u8,
+        kind: PieceKind::King,
+        color: Color::Light,
+        q,
+        r,
+    }
+    .describe();
+
+    let bad: Vec<Vec<ChessPacket>> = vec![
+        vec![settings(u16::MAX, 60)],
+        vec![settings(0, 50_000)],
+        // Kind 7 doesn't exist
+        vec![
+            settings(0, 60),
+            ChessPacket::SetBoard(SetBoard {
+                board: [&default[..35], &[default[35] | 7 << 8]].concat(),
+            }),
+        ],
+        // A second light king, in the middle of the board
+        vec![
+            settings(0, 60),
+            ChessPacket::SetBoard(SetBoard {
+                board: [&default[..], &[king_on_f6]].concat(),
+            }),
+        ],
+        // A queen on every hex of a row, and no kings
+        vec![
+            settings(0, 60),
+            ChessPacket::SetBoard(SetBoard {
+                board: (0..200).map(queen).collect(),
+            }),
+        ],
+    ];
+    for packets in bad {
+        let clock = Rc::new(ManualClock::new());
+        let guest = Game::online(&clock, "guest", 2);
+        let host = raw_host(&clock, &guest);
+        guest.ui.take();
+
+        for packet in packets {
+            host.send(packet);
+        }
+        settle(&clock);
+        let calls = guest.ui.take();
+        assert_eq!(chat_kinds(&calls), [CHAT_DISCONNECTED]);
+        assert!(!calls
+            .iter()
+            .any(|call| matches!(call, UiCall::SetPieces(_))));
+    }
+}
+
+#[test]
+fn refuses_game_states_that_make_no_sense() {
+    // Light just pushed a pawn two hexes, so dark may take it en passant
+    let mut board = Board::new();
+    board.load_default();
+    let (q, r) = board
+        .pieces
+        .iter()
+        .find(|p| p.kind == PieceKind::Pawn && p.color == Color::Light)
+        .map(|p| (p.q, p.r))
+        .unwrap();
+    board.move_piece((q, r), (q, r - 2));
+    let (idx, _, skipped) = board.passant.unwrap();
+    let state = SyncState {
+        board: board.describe(),
+        passant: board.passant,
+        running: true,
+        light_turn: false,
+        light_ms: 0,
+        dark_ms: 0,
+        clock_active: false,
+        history: vec![
+            u16::from(q) << 12 | u16::from(r) << 8 | u16::from(q) << 4 | u16::from(r - 2),
+        ],
+    };
+
+    let bad = [
+        // Light's pawn, but light is the one to move
+        SyncState {
+            light_turn: true,
+            ..state.clone()
+        },
+        // Not the hex the pawn skipped
+        SyncState {
+            passant: Some((idx, q, skipped + 1)),
+            ..state.clone()
+        },
+        // Longer than any timer a host may set
+        SyncState {
+            dark_ms: 60 * 60 * 1000 + 1,
+            ..state.clone()
+        },
+        SyncState {
+            history: vec![0xffff],
+            ..state.clone()
+        },
+    ];
+    for (sync, accepted) in bad
+        .into_iter()
+        .map(|sync| (sync, false))
+        .chain([(state, true)])
+    {
+        let clock = Rc::new(ManualClock::new());
+        let guest = Game::online(&clock, "guest", 2);
+        let host = raw_host(&clock, &guest);
+        guest.ui.take();
+
+        host.send(ChessPacket::SyncState(sync));
+        settle(&clock);
+        let calls = guest.ui.take();
+        let loaded = calls
+            .iter()
+            .any(|call| matches!(call, UiCall::SetPieces(_)));
+        assert_eq!(loaded, accepted);
+        if !accepted {
+            assert_eq!(chat_kinds(&calls), [CHAT_DISCONNECTED]);
+            assert!(calls.contains(&UiCall::JoinResponse("invalid-state".to_owned())));
+        }
+    }
+}
@@ -0,0 +1,111 @@
+//! Listeners attached to a running context.
+
+mod common;
+
+use std::{cell::RefCell, rc::Rc};
+
+use chessagon::{Event, EventKind, JsEvent, Subscription};
+use common::Game;
+
+fn start(game: &Game) {
+    game.ctx.dispatch(JsEvent::SetGamemode, &[0]);
+    game.ctx.dispatch(JsEvent::SetSettings, &common::SETTINGS);
+}
+
+type Seen = Rc<RefCell<Vec<EventKind>>>;
+
+fn recorder(seen: &Seen) -> impl FnMut(&Event) + 'static {
+    let seen = seen.clone();
+    move |evt| seen.borrow_mut().push(evt.kind())
+}
+
+#[test]
+fn dropping_the_subscription_stops_events() {
+    let game = Game::new(0);
+    let ctx = &game.ctx;
+    let seen = Seen::default();
+    let sub = ctx.subscribe(0, recorder(&seen));
+
+    ctx.dispatch(JsEvent::SetGamemode, &[0]);
+    assert_eq!(*seen.borrow(), [EventKind::SetGamemode]);
+
+    drop(sub);
+    ctx.dispatch(JsEvent::SetSettings, &common::SETTINGS);
+    assert_eq!(*seen.borrow(), [EventKind::SetGamemode]);
+}
+
+#[test]
+fn filters_by_kind() {
+    let game = Game::new(0);
+    let ctx = &game.ctx;
+    let seen = Seen::default();
+    let _sub = ctx.subscribe_to(
+        &[EventKind::Movement, EventKind::GameStart],
+        0,
+        recorder(&seen),
+    );
+
+    start(&game);
+    game.play((4, 7), (4, 5));
+    game.play((5, 4), (5, 5));
+    assert_eq!(
+        *seen.borrow(),
+        [
+            EventKind::GameStart,
+            EventKind::Movement,
+            EventKind::Movement
+        ]
+    );
+}
+
+#[test]
+fn higher_priorities_hear_events_first() {
+    let Game { ctx, ui, .. } = Game::new(0);
+    // How many frontend calls the game had made by the time each heard it
+    let calls: Rc<RefCell<Vec<(&str, usize)>>> = Default::default();
+    let listen = |name, priority| {
+        let (calls, ui) = (calls.clone(), ui.clone());
+        ctx.subscribe_to(&[EventKind::SetGamemode], priority, move |_| {
+            calls.borrow_mut().push((name, ui.take().len()));
+        })
+    };
+    let _after = listen("after", -1);
+    let _before = listen("before", 1);
+
+    ctx.dispatch(JsEvent::SetGamemode, &[0]);
+    let calls = calls.borrow();
+    assert_eq!(calls[0], ("before", 0));
+    assert_eq!(calls[1].0, "after");
+    assert!(calls[1].1 > 0);
+}
+
+#[test]
+fn listeners_can_come_and_go_while_handling_events() {
+    let game = Game::new(0);
+    let ctx = &game.ctx;
+    let seen = Seen::default();
+
+    // Unsubscribes itself after the first event it hears
+    let once: Rc<RefCell<Option<Subscription>>> = Default::default();
+    let (slot, mut record) = (once.clone(), recorder(&seen));
+    *once.borrow_mut() = Some(ctx.subscribe(0, move |evt| {
+        record(evt);
+        slot.borrow_mut().take();
+    }));
+
+    // Attaches another one mid-event, which only hears what comes after
+    let late = Seen::default();
+    let added: Rc<RefCell<Vec<Subscription>>> = Default::default();
+    let (sink, inner, ctx2) = (added.clone(), late.clone(), ctx.clone());
+    let _adder = ctx.subscribe_to(&[EventKind::SetSettings], 0, move |_| {
+        sink.borrow_mut().push(ctx2.subscribe(0, recorder(&inner)));
+    });
+
+    start(&game);
+    assert_eq!(*seen.borrow(), [EventKind::SetGamemode]);
+    assert_eq!(
+        *late.borrow(),
+        [EventKind::LoadedBoard, EventKind::GameStart]
+    );
+    assert_eq!(added.borrow().len(), 1);
+}
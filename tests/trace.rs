@@ -0,0 +1,136 @@
+//! Event traces: what gets recorded, and replaying it.
+#![cfg(feature = "trace")]
+
+mod common;
+
+use std::{rc::Rc, time::Duration};
+
+use chessagon::{clock::ManualClock, loopback, trace, ui::UiCall, Context, JsEvent};
+use common::Game;
+use serde_json::{json, Value};
+
+fn entries(ctx: &Context) -> Vec<Value> {
+    match serde_json::from_str(&ctx.trace_json()).unwrap() {
+        Value::Array(entries) => entries,
+        other => panic!("not a list: {}", other),
+    }
+}
+
+// Variants without data are plain strings
+fn kind(event: &Value) -> String {
+    match event {
+        Value::String(kind) => kind.clone(),
+        Value::Object(map) => map.keys().next().unwrap().clone(),
+        other => panic!("not an event: {}", other),
+    }
+}
+
+#[test]
+fn records_where_events_came_from() {
+    let Game { ctx, clock, .. } = Game::new(0);
+    ctx.dispatch(JsEvent::SetGamemode, &[0]);
+    clock.advance(Duration::from_millis(1500));
+    ctx.dispatch(JsEvent::SetSettings, &common::SETTINGS);
+
+    let entries = entries(&ctx);
+    assert_eq!(entries[0]["event"], json!({ "SetGamemode": 0 }));
+    assert_eq!(entries[0]["external"], json!(true));
+    assert_eq!(entries[0]["at_ms"], json!(0));
+
+    let settings = &entries[1];
+    assert!(settings["event"].get("SetSettings").is_some());
+    assert_eq!(settings["external"], json!(true));
+    assert_eq!(settings["at_ms"], json!(1500));
+
+    // The board it loaded, and the game it started, followed from it
+    let board = &entries[2];
+    assert!(board["event"].get("LoadedBoard").is_some());
+    assert_eq!(board["external"], json!(false));
+    assert_eq!(entries[3]["event"], json!("GameStart"));
+    assert_eq!(entries.len(), 4);
+}
+
+#[test]
+fn counts_what_is_queued_behind() {
+    let Game { ctx, clock, .. } = Game::new(0);
+    let (conn, _other) = loopback::pair(clock);
+    ctx.link(conn, true);
+
+    // Linking raises two events at once
+    let summary: Vec<_> = entries(&ctx)
+        .iter()
+        .map(|entry| {
+            (
+                kind(&entry["event"]),
+                entry["queue_depth"].as_u64().unwrap(),
+                entry["external"].as_bool().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            ("Linked".to_owned(), 0, true),
+            ("JoinedRoom".to_owned(), 1, false),
+            ("TransportOpened".to_owned(), 0, false),
+        ]
+    );
+}
+
+#[test]
+fn replaying_a_solo_game_repeats_it() {
+    let game = Game::new(0);
+    game.ctx.dispatch(JsEvent::SetGamemode, &[0]);
+    game.ctx.dispatch(JsEvent::SetSettings, &common::SETTINGS);
+    game.play((4, 7), (4, 5));
+    game.play((5, 4), (5, 5));
+    game.play((3, 8), (3, 6));
+
+    let replayed = Game::new(0);
+    assert_eq!(
+        trace::replay(&replayed.ctx, &game.ctx.trace_json()).unwrap(),
+        8
+    );
+    assert_eq!(replayed.ui.take(), game.ui.take());
+    assert_eq!(entries(&replayed.ctx), entries(&game.ctx));
+}
+
+#[test]
+fn replaying_one_side_of_an_online_game_repeats_it() {
+    let clock = Rc::new(ManualClock::new());
+    let (host, guest) = (Game::on(&clock, 1), Game::on(&clock, 2));
+    for (game, name) in [(&host, "host"), (&guest, "guest")] {
+        game.ctx.dispatch(JsEvent::SetGamemode, &[1]);
+        game.ctx.dispatch(JsEvent::Register, name.as_bytes());
+    }
+
+    let (host_conn, guest_conn) = loopback::pair(clock.clone());
+    host.ctx.link(host_conn, true);
+    guest.ctx.link(guest_conn, false);
+    host.ctx.dispatch(JsEvent::SetPassword, &[]);
+    host.ctx.dispatch(JsEvent::SetSettings, &common::SETTINGS);
+    clock.advance(Duration::ZERO);
+    host.play((4, 7), (4, 5));
+    clock.advance(Duration::ZERO);
+    guest.play((5, 4), (5, 5));
+    clock.advance(Duration::ZERO);
+
+    // Nobody on the other end this time; the packets are in the log
+    let replayed = Game::new(2);
+    trace::replay(&replayed.ctx, &guest.ctx.trace_json()).unwrap();
+
+    let calls = replayed.ui.take();
+    assert_eq!(calls, guest.ui.take());
+    assert!(calls.contains(&UiCall::SetPlayerName {
+        is_self: false,
+        name: "host".to_owned(),
+    }));
+}
+
+#[test]
+fn rejects_logs_it_cannot_read() {
+    let Game { ctx, ui, .. } = Game::new(0);
+    assert!(trace::replay(&ctx, "[{\"at_ms\": 0}]").is_err());
+    assert!(trace::replay(&ctx, "not json").is_err());
+    assert!(ui.take().is_empty());
+}
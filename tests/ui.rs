@@ -0,0 +1,87 @@
+//! A solo game, as the frontend sees it.
+
+mod common;
+
+use chessagon::{ui::UiCall, JsEvent};
+use chessagon_core::board::Board;
+use common::{chat_kinds, Game, CHAT_DARK_WON, CHAT_LIGHT_RESIGNED, CHAT_START};
+
+const SETTINGS_SCENE: i8 = 3;
+const CANVAS_SCENE: i8 = -1;
+
+const RESIGN: u8 = 0;
+const PLAY_AGAIN: u8 = 1;
+const LEAVE_ROOM: u8 = 2;
+
+#[test]
+fn solo_games_are_set_up_first() {
+    let game = Game::new(0);
+    game.ctx.dispatch(JsEvent::SetGamemode, &[0]);
+    assert!(game.ui.take().contains(&UiCall::SetScene(SETTINGS_SCENE)));
+}
+
+#[test]
+fn starts_with_the_default_board() {
+    // No timer, playing as light
+    let Game { ui, .. } = Game::solo(common::SETTINGS);
+    let calls = ui.take();
+
+    let mut board = Board::new();
+    board.load_default();
+    assert!(calls.contains(&UiCall::SetScene(CANVAS_SCENE)));
+    assert!(calls.contains(&UiCall::SetBoardPerspective(true)));
+    assert!(calls.contains(&UiCall::SetPieces(board.describe())));
+    assert!(calls.contains(&UiCall::RemoveTimers));
+    assert_eq!(chat_kinds(&calls), [CHAT_START]);
+    assert_eq!(calls.last(), Some(&UiCall::ShowButtons(vec![LEAVE_ROOM])));
+}
+
+#[test]
+fn moves_the_selected_piece() {
+    let Game { ctx, ui, .. } = Game::solo(common::SETTINGS);
+    ui.take();
+
+    // Light pawn on e4 can advance one or two hexes
+    ctx.dispatch(JsEvent::HexClicked, &[4, 7]);
+    let selected = 1 << 8;
+    assert_eq!(
+        ui.take(),
+        [UiCall::Highlight(vec![
+            selected | 4 << 4 | 5,
+            selected | 4 << 4 | 6,
+            selected | 4 << 4 | 7,
+        ])]
+    );
+
+    ctx.dispatch(JsEvent::HexClicked, &[4, 5]);
+    let calls = ui.take();
+    let pawn = 12;
+    assert!(calls.contains(&UiCall::MovePieces(vec![pawn << 8 | 4 << 4 | 5])));
+
+    // Dark moves next, so light's pieces can't be picked anymore. Only
+    // the last move stays highlighted.
+    ctx.dispatch(JsEvent::HexClicked, &[4, 5]);
+    let moved = 1 << 10;
+    assert_eq!(
+        ui.take(),
+        [UiCall::Highlight(vec![
+            moved | 4 << 4 | 5,
+            moved | 4 << 4 | 7
+        ])]
+    );
+}
+
+#[test]
+fn resigning_ends_the_game() {
+    let Game { ctx, ui, .. } = Game::solo(common::SETTINGS);
+    ui.take();
+
+    ctx.dispatch(JsEvent::GameButtonClick, &[RESIGN]);
+    let calls = ui.take();
+    // Light resigned, so dark won
+    assert_eq!(chat_kinds(&calls), [CHAT_LIGHT_RESIGNED, CHAT_DARK_WON]);
+    assert_eq!(
+        calls.last(),
+        Some(&UiCall::ShowButtons(vec![LEAVE_ROOM, PLAY_AGAIN]))
+    );
+}